@@ -1,5 +1,7 @@
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-    tonic_build::configure()
-        .compile(&["proto/orderbook/orderbook.proto"], &["proto/orderbook"])?;
+    tonic_build::configure().compile(
+        &["proto/orderbook/orderbook.proto", "proto/orderbook/v1/orderbook.proto"],
+        &["proto/orderbook"],
+    )?;
     Ok(())
 }
@@ -0,0 +1,361 @@
+pub mod sink;
+
+use std::{
+    collections::HashMap,
+    fmt::Display,
+    str::FromStr,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use futures::StreamExt;
+use thiserror::Error;
+use tokio::sync::broadcast;
+
+use crate::exchanges::{
+    status::{VenueHealth, VenueState},
+    ExchangeType,
+};
+use crate::hub::{BookEvent, BookHub, HubError, MergedBook};
+use crate::queue::OverflowPolicy;
+
+/// How often rules are evaluated when the book is not updating
+const EVALUATION_INTERVAL: Duration = Duration::from_secs(1);
+/// Alerts buffered for each subscriber before it starts missing them
+const ALERT_CHANNEL_CAPACITY: usize = 256;
+
+#[derive(Error, Debug)]
+pub enum AlertError {
+    #[error("expected a rule such as spread>10bps/30s, stale/60s, crossed or top-size<0.5, got {0}")]
+    Malformed(String),
+    #[error("could not write the alert to {sink}: {reason}")]
+    SinkFailed { sink: String, reason: String },
+}
+
+/// What an alert watches for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Condition {
+    /// The spread is wider than this many basis points of the mid price
+    SpreadAbove(f64),
+    /// A venue of the symbol is stale, reconnecting or has failed
+    VenueStale,
+    /// The best bid is at or above the best ask
+    Crossed,
+    /// Less than this amount is quoted at the best bid or the best ask
+    TopSizeBelow(f64),
+}
+
+/// A condition and how long it must hold before the alert fires.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct AlertRule {
+    pub condition: Condition,
+    pub hold: Duration,
+}
+
+impl FromStr for AlertRule {
+    type Err = AlertError;
+
+    /// Parses a rule such as `spread>10bps/30s`, where the optional `/<seconds>s` is how long it must hold.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || AlertError::Malformed(s.to_string());
+        let (condition, hold) = match s.trim().split_once('/') {
+            Some((condition, hold)) => {
+                let seconds = hold.strip_suffix('s').ok_or_else(malformed)?;
+                let seconds: u64 = seconds.parse().map_err(|_| malformed())?;
+                (condition, Duration::from_secs(seconds))
+            }
+            None => (s.trim(), Duration::ZERO),
+        };
+        let threshold = |value: &str| {
+            value
+                .parse::<f64>()
+                .ok()
+                .filter(|value| value.is_finite() && *value >= 0.0)
+                .ok_or_else(malformed)
+        };
+        let condition = if let Some(bps) = condition.strip_prefix("spread>") {
+            Condition::SpreadAbove(threshold(bps.strip_suffix("bps").ok_or_else(malformed)?)?)
+        } else if let Some(size) = condition.strip_prefix("top-size<") {
+            Condition::TopSizeBelow(threshold(size)?)
+        } else {
+            match condition {
+                "stale" => Condition::VenueStale,
+                "crossed" => Condition::Crossed,
+                _ => return Err(malformed()),
+            }
+        };
+        Ok(Self { condition, hold })
+    }
+}
+
+impl Display for AlertRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.condition {
+            Condition::SpreadAbove(bps) => write!(f, "spread>{bps}bps")?,
+            Condition::VenueStale => write!(f, "stale")?,
+            Condition::Crossed => write!(f, "crossed")?,
+            Condition::TopSizeBelow(size) => write!(f, "top-size<{size}")?,
+        }
+        if !self.hold.is_zero() {
+            write!(f, "/{}s", self.hold.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+impl Display for AlertState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Firing => write!(f, "firing"),
+            Self::Resolved => write!(f, "resolved"),
+        }
+    }
+}
+
+/// A rule starting or ceasing to hold for a symbol, or one of its venues.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alert {
+    /// The rule as it was configured
+    pub rule: String,
+    pub symbol: String,
+    /// The venue the alert concerns, absent for rules on the merged book
+    pub exchange: Option<ExchangeType>,
+    pub state: AlertState,
+    /// What was measured when the alert changed state: the spread in basis points, the amount by which the book
+    /// crossed, the smaller top of book size, or the seconds since the venue's last message
+    pub value: Option<f64>,
+    /// When the condition started to hold
+    pub since: SystemTime,
+    pub at: SystemTime,
+}
+
+#[derive(Default)]
+struct RuleState {
+    since: Option<SystemTime>,
+    firing: bool,
+}
+
+/// Tracks which rules hold for a single symbol, reporting each alert once when it fires and once when it resolves.
+struct Evaluator {
+    symbol: String,
+    rules: Arc<Vec<AlertRule>>,
+    states: HashMap<(usize, Option<ExchangeType>), RuleState>,
+}
+
+impl Evaluator {
+    fn new(symbol: &str, rules: Arc<Vec<AlertRule>>) -> Self {
+        Self {
+            symbol: symbol.to_string(),
+            rules,
+            states: HashMap::new(),
+        }
+    }
+
+    /// Evaluates every rule against the latest book and the health of the symbol's venues as of `now`.
+    ///
+    /// Rules on the book are left as they were until a book has arrived.
+    fn evaluate(&mut self, book: Option<&MergedBook>, venues: &[VenueHealth], now: SystemTime) -> Vec<Alert> {
+        let mut alerts = Vec::new();
+        for (index, rule) in self.rules.clone().iter().enumerate() {
+            // Whether the rule holds for the book or each venue, with what was measured
+            let observations: Vec<(Option<ExchangeType>, bool, Option<f64>)> = match rule.condition {
+                Condition::VenueStale => {
+                    let mut observations: Vec<_> = venues
+                        .iter()
+                        .filter(|venue| venue.symbol == self.symbol)
+                        .map(|venue| {
+                            let stale = matches!(
+                                venue.state,
+                                VenueState::Stale | VenueState::Reconnecting | VenueState::Failed
+                            );
+                            let silent = venue
+                                .last_message
+                                .and_then(|at| now.duration_since(at).ok())
+                                .map(|silent| silent.as_secs_f64());
+                            (Some(venue.exchange), stale, silent)
+                        })
+                        .collect();
+                    // Venues that are no longer monitored have stopped being stale
+                    for (key_index, exchange) in self.states.keys() {
+                        if *key_index == index && !observations.iter().any(|(venue, _, _)| venue == exchange) {
+                            observations.push((*exchange, false, None));
+                        }
+                    }
+                    observations
+                }
+                _ => match book {
+                    Some(book) => {
+                        let (holds, value) = observe(rule.condition, book);
+                        vec![(None, holds, value)]
+                    }
+                    None => Vec::new(),
+                },
+            };
+            for (exchange, holds, value) in observations {
+                let state = self.states.entry((index, exchange)).or_default();
+                let alert = |state: AlertState, since: SystemTime| Alert {
+                    rule: rule.to_string(),
+                    symbol: self.symbol.clone(),
+                    exchange,
+                    state,
+                    value,
+                    since,
+                    at: now,
+                };
+                if holds {
+                    let since = *state.since.get_or_insert(now);
+                    let held = now.duration_since(since).unwrap_or_default();
+                    if !state.firing && held >= rule.hold {
+                        state.firing = true;
+                        alerts.push(alert(AlertState::Firing, since));
+                    }
+                } else {
+                    if let (true, Some(since)) = (state.firing, state.since) {
+                        alerts.push(alert(AlertState::Resolved, since));
+                    }
+                    *state = RuleState::default();
+                }
+            }
+        }
+        self.states.retain(|_, state| state.since.is_some());
+        alerts
+    }
+}
+
+/// Whether `condition` holds for `book`, with what was measured.
+fn observe(condition: Condition, book: &MergedBook) -> (bool, Option<f64>) {
+    let (best_bid, best_ask) = (book.bids.first(), book.asks.first());
+    match condition {
+        Condition::SpreadAbove(bps) => match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => {
+                let mid = (bid.price + ask.price) / 2.0;
+                let spread_bps = (ask.price - bid.price) / mid * 10_000.0;
+                (spread_bps > bps, Some(spread_bps))
+            }
+            _ => (false, None),
+        },
+        Condition::Crossed => match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => (bid.price >= ask.price, Some(bid.price - ask.price)),
+            _ => (false, None),
+        },
+        Condition::TopSizeBelow(size) => {
+            // An empty side has nothing at the top
+            let smallest = best_bid
+                .map_or(0.0, |bid| bid.amount)
+                .min(best_ask.map_or(0.0, |ask| ask.amount));
+            (smallest < size, Some(smallest))
+        }
+        Condition::VenueStale => (false, None),
+    }
+}
+
+/// Evaluates alert rules against the live merged books and publishes their alerts.
+#[derive(Clone)]
+pub struct AlertEngine {
+    hub: BookHub,
+    rules: Arc<Vec<AlertRule>>,
+    alerts: Arc<broadcast::Sender<Arc<Alert>>>,
+}
+
+impl AlertEngine {
+    pub fn new(hub: BookHub, rules: Vec<AlertRule>) -> Self {
+        Self {
+            hub,
+            rules: Arc::new(rules),
+            alerts: Arc::new(broadcast::channel(ALERT_CHANNEL_CAPACITY).0),
+        }
+    }
+
+    pub fn rules(&self) -> &[AlertRule] {
+        &self.rules
+    }
+
+    /// Receives the alerts of every symbol being watched.
+    pub fn subscribe(&self) -> broadcast::Receiver<Arc<Alert>> {
+        self.alerts.subscribe()
+    }
+
+    /// Evaluates the rules against `symbol` whenever its book updates, and every `EVALUATION_INTERVAL` so that
+    /// venues going quiet are noticed, until the hub shuts down.
+    pub async fn watch(&self, symbol: String) -> Result<(), HubError> {
+        let mut subscription = self
+            .hub
+            .subscribe(&symbol, self.hub.config().max_depth_of(&symbol), 1, OverflowPolicy::Conflate)
+            .await?;
+        let mut evaluator = Evaluator::new(&symbol, self.rules.clone());
+        let mut tick = tokio::time::interval(EVALUATION_INTERVAL);
+        let mut latest: Option<Arc<MergedBook>> = None;
+        loop {
+            tokio::select! {
+                _ = self.hub.shutting_down() => return Ok(()),
+                _ = tick.tick() => {},
+                event = subscription.next() => match event {
+                    Some(BookEvent::Update(book)) => latest = Some(book),
+                    Some(_) => continue,
+                    None => return Ok(()),
+                },
+            }
+            let venues = self.hub.venues().snapshot();
+            for alert in evaluator.evaluate(latest.as_deref(), &venues, SystemTime::now()) {
+                // Nobody listening is not an error
+                let _ = self.alerts.send(Arc::new(alert));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        str::FromStr,
+        sync::Arc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use crate::{exchanges::ExchangeType, hub::BookFixture};
+
+    use super::{AlertRule, AlertState, Condition, Evaluator};
+
+    #[test]
+    fn test_parse_rule() {
+        let rule = AlertRule::from_str("spread>10bps/30s").unwrap();
+        assert_eq!(rule.condition, Condition::SpreadAbove(10.0));
+        assert_eq!(rule.hold, Duration::from_secs(30));
+        assert_eq!(rule.to_string(), "spread>10bps/30s");
+        assert_eq!(AlertRule::from_str("crossed").unwrap().hold, Duration::ZERO);
+        assert!(AlertRule::from_str("spread>10").is_err());
+        assert!(AlertRule::from_str("stale/60").is_err());
+        assert!(AlertRule::from_str("top-size<-1").is_err());
+    }
+
+    #[test]
+    fn test_spread_alert() {
+        let book = |ask: f64| {
+            BookFixture::new()
+                .bid(100.0, 1.0, ExchangeType::Binance)
+                .ask(ask, 1.0, ExchangeType::Bitstamp)
+                .build()
+        };
+        let rules = Arc::new(vec![AlertRule::from_str("spread>50bps/10s").unwrap()]);
+        let mut evaluator = Evaluator::new("ethbtc", rules);
+        let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+
+        // About 100bps wide, but not yet for long enough
+        assert!(evaluator.evaluate(Some(&book(101.0)), &[], at(0)).is_empty());
+        let fired = evaluator.evaluate(Some(&book(101.0)), &[], at(10));
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].state, AlertState::Firing);
+        assert_eq!(fired[0].since, at(0));
+        // Fires only once while the condition holds
+        assert!(evaluator.evaluate(Some(&book(101.0)), &[], at(20)).is_empty());
+        let resolved = evaluator.evaluate(Some(&book(100.1)), &[], at(25));
+        assert_eq!(resolved[0].state, AlertState::Resolved);
+        assert!(evaluator.evaluate(Some(&book(100.1)), &[], at(30)).is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use async_trait::async_trait;
+use futures::future;
+use hyper::{client::HttpConnector, header::CONTENT_TYPE, Body, Client, Method, Request, Uri};
+use hyper_tls::HttpsConnector;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+use crate::json::JsonAlert;
+
+use super::{Alert, AlertError, AlertState};
+
+/// How long a sink may take to write an alert before it is given up on
+const SINK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Somewhere alerts are written as they fire and resolve.
+#[async_trait]
+pub trait AlertSink: Send + Sync {
+    async fn send(&self, alert: &Alert) -> Result<(), AlertError>;
+
+    fn name(&self) -> String;
+}
+
+/// Logs alerts, firing ones as warnings.
+pub struct LogSink {}
+
+#[async_trait]
+impl AlertSink for LogSink {
+    async fn send(&self, alert: &Alert) -> Result<(), AlertError> {
+        let venue = alert.exchange.map(|exchange| format!(" on {}", exchange.to_string())).unwrap_or_default();
+        match alert.state {
+            AlertState::Firing => warn!("ALERT {} {}{venue} firing: {:?}", alert.symbol, alert.rule, alert.value),
+            AlertState::Resolved => info!("ALERT {} {}{venue} resolved", alert.symbol, alert.rule),
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        String::from("the log")
+    }
+}
+
+/// Appends alerts to a file as JSON lines.
+pub struct FileSink {
+    path: PathBuf,
+    file: Arc<Mutex<File>>,
+}
+
+impl FileSink {
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        Ok(Self {
+            path,
+            file: Arc::new(Mutex::new(file)),
+        })
+    }
+}
+
+#[async_trait]
+impl AlertSink for FileSink {
+    async fn send(&self, alert: &Alert) -> Result<(), AlertError> {
+        let failed = |reason: String| AlertError::SinkFailed {
+            sink: self.name(),
+            reason,
+        };
+        let mut line = serde_json::to_string(&JsonAlert::from(alert)).map_err(|e| failed(e.to_string()))?;
+        line.push('\n');
+        let file = self.file.clone();
+        // Written off the runtime's threads, as the disk may stall
+        tokio::task::spawn_blocking(move || file.lock().unwrap().write_all(line.as_bytes()))
+            .await
+            .map_err(|e| failed(e.to_string()))?
+            .map_err(|e| failed(e.to_string()))
+    }
+
+    fn name(&self) -> String {
+        self.path.display().to_string()
+    }
+}
+
+/// Posts alerts to an HTTP or HTTPS endpoint as JSON.
+pub struct WebhookSink {
+    url: Uri,
+    client: Client<HttpsConnector<HttpConnector>>,
+}
+
+impl WebhookSink {
+    pub fn new(url: &str) -> Result<Self, AlertError> {
+        let url = url.parse().map_err(|e: hyper::http::uri::InvalidUri| AlertError::SinkFailed {
+            sink: url.to_string(),
+            reason: e.to_string(),
+        })?;
+        Ok(Self {
+            url,
+            client: Client::builder().build(HttpsConnector::new()),
+        })
+    }
+}
+
+#[async_trait]
+impl AlertSink for WebhookSink {
+    async fn send(&self, alert: &Alert) -> Result<(), AlertError> {
+        let failed = |reason: String| AlertError::SinkFailed {
+            sink: self.name(),
+            reason,
+        };
+        let body = serde_json::to_vec(&JsonAlert::from(alert)).map_err(|e| failed(e.to_string()))?;
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(self.url.clone())
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .map_err(|e| failed(e.to_string()))?;
+        let response = self.client.request(request).await.map_err(|e| failed(e.to_string()))?;
+        if !response.status().is_success() {
+            return Err(failed(format!("responded {}", response.status())));
+        }
+        Ok(())
+    }
+
+    fn name(&self) -> String {
+        self.url.to_string()
+    }
+}
+
+/// Writes every alert received to each of `sinks` until the engine closes, logging those that fail.
+///
+/// Each sink receives alerts on its own, so one that is slow or unreachable only holds up its own alerts.
+pub async fn deliver(sinks: Vec<Arc<dyn AlertSink>>, alerts: broadcast::Receiver<Arc<Alert>>) {
+    future::join_all(sinks.into_iter().map(|sink| deliver_to(sink, alerts.resubscribe()))).await;
+}
+
+async fn deliver_to(sink: Arc<dyn AlertSink>, mut alerts: broadcast::Receiver<Arc<Alert>>) {
+    loop {
+        let alert = match alerts.recv().await {
+            Ok(alert) => alert,
+            Err(RecvError::Lagged(missed)) => {
+                warn!("{missed} alerts were not written to {} as it fell behind", sink.name());
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        let result = match tokio::time::timeout(SINK_TIMEOUT, sink.send(&alert)).await {
+            Ok(result) => result,
+            Err(_) => Err(AlertError::SinkFailed {
+                sink: sink.name(),
+                reason: format!("timed out after {}s", SINK_TIMEOUT.as_secs()),
+            }),
+        };
+        if let Err(e) = result {
+            error!("{e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use async_trait::async_trait;
+    use tokio::sync::{broadcast, mpsc};
+
+    use crate::{
+        alerts::{Alert, AlertError, AlertState},
+        exchanges::ExchangeType,
+        json::JsonAlert,
+    };
+
+    use super::{deliver, AlertSink, FileSink};
+
+    /// Never finishes writing an alert.
+    struct StalledSink {}
+
+    #[async_trait]
+    impl AlertSink for StalledSink {
+        async fn send(&self, _alert: &Alert) -> Result<(), AlertError> {
+            std::future::pending().await
+        }
+
+        fn name(&self) -> String {
+            String::from("stalled")
+        }
+    }
+
+    /// Passes alerts on to a channel.
+    struct ChannelSink {
+        tx: mpsc::UnboundedSender<String>,
+    }
+
+    #[async_trait]
+    impl AlertSink for ChannelSink {
+        async fn send(&self, alert: &Alert) -> Result<(), AlertError> {
+            self.tx.send(alert.rule.clone()).unwrap();
+            Ok(())
+        }
+
+        fn name(&self) -> String {
+            String::from("channel")
+        }
+    }
+
+    fn alert(state: AlertState) -> Alert {
+        Alert {
+            rule: String::from("stale/60s"),
+            symbol: String::from("ethbtc"),
+            exchange: Some(ExchangeType::Bitstamp),
+            state,
+            value: Some(61.0),
+            since: UNIX_EPOCH,
+            at: UNIX_EPOCH + Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_sink() {
+        let path = std::env::temp_dir().join(format!("alerts-{}.jsonl", std::process::id()));
+        let sink = FileSink::open(&path).unwrap();
+        sink.send(&alert(AlertState::Firing)).await.unwrap();
+        sink.send(&alert(AlertState::Resolved)).await.unwrap();
+
+        let written = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let lines: Vec<JsonAlert> = written.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].state, "firing");
+        assert_eq!(lines[1].state, "resolved");
+        assert_eq!(lines[1].exchange.as_deref(), Some("Bitstamp"));
+        assert_eq!(lines[1].at, 60_000);
+    }
+
+    #[tokio::test]
+    async fn test_stalled_sink() {
+        let (alerts, rx) = broadcast::channel(4);
+        let (tx, mut delivered) = mpsc::unbounded_channel();
+        let sinks: Vec<Arc<dyn AlertSink>> = vec![Arc::new(StalledSink {}), Arc::new(ChannelSink { tx })];
+        tokio::spawn(deliver(sinks, rx));
+        tokio::task::yield_now().await;
+
+        alerts.send(Arc::new(alert(AlertState::Firing))).unwrap();
+        alerts.send(Arc::new(alert(AlertState::Resolved))).unwrap();
+        // Both arrive although the first sink never finishes writing either
+        let rule = tokio::time::timeout(Duration::from_secs(1), delivered.recv()).await.unwrap();
+        assert_eq!(rule.as_deref(), Some("stale/60s"));
+        assert!(tokio::time::timeout(Duration::from_secs(1), delivered.recv()).await.is_ok());
+    }
+}
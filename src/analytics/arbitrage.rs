@@ -0,0 +1,308 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime},
+};
+
+use crate::exchanges::ExchangeType;
+use crate::hub::MergedBook;
+use crate::orderbook::{
+    fees::FeeSchedule,
+    levels::{AskLevel, BidLevel},
+};
+
+use super::Analysis;
+
+/// Amounts smaller than this are treated as exhausted when walking the book
+const AMOUNT_EPSILON: f64 = 1e-12;
+
+/// The result of buying on one venue and selling on another for as long as it remains profitable after fees.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Crossing {
+    /// Best ask of the venue bought from
+    pub buy_price: f64,
+    /// Best bid of the venue sold to
+    pub sell_price: f64,
+    /// Amount that can be bought and sold at a profit
+    pub size: f64,
+    /// Proceeds of the sales less the cost of the purchases, before fees
+    pub gross_profit: f64,
+    /// Taker fees paid on both venues
+    pub fees: f64,
+}
+
+impl Crossing {
+    pub fn net_profit(&self) -> f64 {
+        self.gross_profit - self.fees
+    }
+}
+
+/// Walks the asks of one venue against the bids of another, best first, while each unit is profitable after the
+/// taker fees of both venues. Returns `None` when not even the best levels are.
+pub fn walk(
+    asks: &[&AskLevel],
+    bids: &[&BidLevel],
+    buy_fee: f64,
+    sell_fee: f64,
+) -> Option<Crossing> {
+    let (mut i, mut j) = (0, 0);
+    let mut ask_left = asks.first()?.amount;
+    let mut bid_left = bids.first()?.amount;
+    let mut crossing = Crossing {
+        buy_price: asks[0].price,
+        sell_price: bids[0].price,
+        ..Default::default()
+    };
+    while i < asks.len() && j < bids.len() {
+        let (ask, bid) = (asks[i].price, bids[j].price);
+        if bid * (1.0 - sell_fee) <= ask * (1.0 + buy_fee) {
+            break;
+        }
+        let amount = ask_left.min(bid_left);
+        crossing.size += amount;
+        crossing.gross_profit += (bid - ask) * amount;
+        crossing.fees += (ask * buy_fee + bid * sell_fee) * amount;
+        ask_left -= amount;
+        bid_left -= amount;
+        if ask_left <= AMOUNT_EPSILON {
+            i += 1;
+            ask_left = asks.get(i).map_or(0.0, |ask| ask.amount);
+        }
+        if bid_left <= AMOUNT_EPSILON {
+            j += 1;
+            bid_left = bids.get(j).map_or(0.0, |bid| bid.amount);
+        }
+    }
+    (crossing.size > 0.0).then_some(crossing)
+}
+
+/// A profitable crossing between two venues.
+#[derive(Debug, Clone)]
+pub struct Opportunity {
+    pub id: u64,
+    pub symbol: String,
+    pub buy_exchange: ExchangeType,
+    pub sell_exchange: ExchangeType,
+    /// The crossing as of the latest book
+    pub crossing: Crossing,
+    pub started_at: SystemTime,
+}
+
+#[derive(Debug, Clone)]
+pub enum OpportunityEvent {
+    Started(Opportunity),
+    Ended {
+        /// The opportunity as last seen
+        opportunity: Opportunity,
+        /// The largest profitable size seen while it was open
+        peak_size: f64,
+        /// The largest net profit seen while it was open
+        peak_net_profit: f64,
+        ended_at: SystemTime,
+        duration: Duration,
+    },
+}
+
+struct OpenOpportunity {
+    opportunity: Opportunity,
+    peak_size: f64,
+    peak_net_profit: f64,
+}
+
+/// Detects when one venue's bid exceeds another venue's ask by more than their taker fees.
+///
+/// An opportunity starts with the first book in which a pair of venues cross profitably and ends with the first in
+/// which they no longer do. Synthetic levels cannot be traded and the levels of delayed venues may be stale, so neither
+/// is taken into account.
+pub struct ArbitrageDetector {
+    symbol: String,
+    fees: FeeSchedule,
+    open: HashMap<(ExchangeType, ExchangeType), OpenOpportunity>,
+    next_id: u64,
+}
+
+impl ArbitrageDetector {
+    pub fn new<S: Into<String>>(symbol: S, fees: FeeSchedule) -> Self {
+        Self {
+            symbol: symbol.into(),
+            fees,
+            open: HashMap::new(),
+            next_id: 1,
+        }
+    }
+}
+
+impl Analysis for ArbitrageDetector {
+    type Output = OpportunityEvent;
+
+    fn update(&mut self, book: &MergedBook) -> Vec<OpportunityEvent> {
+        let mut venues: Vec<ExchangeType> = Vec::new();
+        for exchange in book.bids.iter().map(|bid| bid.exchange).chain(book.asks.iter().map(|ask| ask.exchange)) {
+            if exchange == ExchangeType::Synthetic || book.delayed.contains(&exchange) {
+                continue;
+            }
+            if !venues.contains(&exchange) {
+                venues.push(exchange);
+            }
+        }
+        let mut events = Vec::new();
+        let mut crossed = Vec::new();
+        for &buy in &venues {
+            for &sell in &venues {
+                if buy == sell {
+                    continue;
+                }
+                let asks: Vec<&AskLevel> = book.asks.iter().filter(|ask| ask.exchange == buy).collect();
+                let bids: Vec<&BidLevel> = book.bids.iter().filter(|bid| bid.exchange == sell).collect();
+                let crossing = match walk(&asks, &bids, self.fees.taker(buy), self.fees.taker(sell)) {
+                    Some(crossing) => crossing,
+                    None => continue,
+                };
+                crossed.push((buy, sell));
+                match self.open.get_mut(&(buy, sell)) {
+                    Some(open) => {
+                        open.opportunity.crossing = crossing;
+                        open.peak_size = open.peak_size.max(crossing.size);
+                        open.peak_net_profit = open.peak_net_profit.max(crossing.net_profit());
+                    }
+                    None => {
+                        let opportunity = Opportunity {
+                            id: self.next_id,
+                            symbol: self.symbol.clone(),
+                            buy_exchange: buy,
+                            sell_exchange: sell,
+                            crossing,
+                            started_at: book.timestamp,
+                        };
+                        self.next_id += 1;
+                        events.push(OpportunityEvent::Started(opportunity.clone()));
+                        self.open.insert(
+                            (buy, sell),
+                            OpenOpportunity {
+                                opportunity,
+                                peak_size: crossing.size,
+                                peak_net_profit: crossing.net_profit(),
+                            },
+                        );
+                    }
+                }
+            }
+        }
+
+        let closed: Vec<_> = self
+            .open
+            .keys()
+            .filter(|pair| !crossed.contains(pair))
+            .copied()
+            .collect();
+        for pair in closed {
+            if let Some(open) = self.open.remove(&pair) {
+                let duration = book
+                    .timestamp
+                    .duration_since(open.opportunity.started_at)
+                    .unwrap_or_default();
+                events.push(OpportunityEvent::Ended {
+                    opportunity: open.opportunity,
+                    peak_size: open.peak_size,
+                    peak_net_profit: open.peak_net_profit,
+                    ended_at: book.timestamp,
+                    duration,
+                });
+            }
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::{
+        analytics::Analysis,
+        exchanges::ExchangeType,
+        hub::BookFixture,
+        orderbook::{
+            fees::{FeeSchedule, Fees},
+            levels::{AskLevel, BidLevel},
+        },
+    };
+
+    use super::{walk, ArbitrageDetector, OpportunityEvent};
+
+    #[test]
+    fn test_walk() {
+        let asks = [
+            AskLevel::new(100.0, 1.0, ExchangeType::Binance),
+            AskLevel::new(101.0, 2.0, ExchangeType::Binance),
+        ];
+        let bids = [
+            BidLevel::new(102.0, 1.5, ExchangeType::Bitstamp),
+            BidLevel::new(100.5, 5.0, ExchangeType::Bitstamp),
+        ];
+        let crossing = walk(&asks.iter().collect::<Vec<_>>(), &bids.iter().collect::<Vec<_>>(), 0.0, 0.0).unwrap();
+        // 1 at 100 -> 102, 0.5 at 101 -> 102, then 101 no longer crosses 100.5
+        assert_eq!(crossing.size, 1.5);
+        assert_eq!(crossing.gross_profit, 2.5);
+
+        // A 1% fee on both sides leaves nothing profitable
+        assert!(walk(&asks.iter().collect::<Vec<_>>(), &bids.iter().collect::<Vec<_>>(), 0.01, 0.01).is_none());
+    }
+
+    #[test]
+    fn test_opportunity_lifecycle() {
+        let fees = FeeSchedule::default().with_fees(
+            ExchangeType::Binance,
+            Fees {
+                maker: 0.0,
+                taker: 0.001,
+            },
+        );
+        let mut detector = ArbitrageDetector::new("ethbtc", fees);
+        let book = |sequence: u64, bitstamp_bid: f64| {
+            BookFixture::new()
+                .sequence(sequence)
+                .timestamp(UNIX_EPOCH + Duration::from_secs(sequence))
+                .bid(bitstamp_bid, 1.0, ExchangeType::Bitstamp)
+                .bid(99.0, 1.0, ExchangeType::Binance)
+                .ask(100.0, 1.0, ExchangeType::Binance)
+                .ask(103.0, 1.0, ExchangeType::Bitstamp)
+                .build()
+        };
+
+        let events = detector.update(&book(1, 101.0));
+        assert!(matches!(&events[..], [OpportunityEvent::Started(opportunity)]
+            if opportunity.buy_exchange == ExchangeType::Binance
+                && opportunity.sell_exchange == ExchangeType::Bitstamp));
+        assert!(detector.update(&book(2, 102.0)).is_empty());
+
+        let events = detector.update(&book(4, 99.5));
+        match &events[..] {
+            [OpportunityEvent::Ended { duration, peak_size, peak_net_profit, .. }] => {
+                assert_eq!(*duration, Duration::from_secs(3));
+                assert_eq!(*peak_size, 1.0);
+                assert!((peak_net_profit - 1.9).abs() < 1e-9);
+            }
+            events => panic!("expected the opportunity to end, got {events:?}"),
+        }
+    }
+
+    #[test]
+    fn test_untradeable_venues() {
+        let mut detector = ArbitrageDetector::new("ethbtc", FeeSchedule::default());
+        // Synthetic levels cross Binance's ask but cannot be traded
+        let synthetic = BookFixture::new()
+            .bid(101.0, 1.0, ExchangeType::Synthetic)
+            .ask(100.0, 1.0, ExchangeType::Binance)
+            .build();
+        assert!(detector.update(&synthetic).is_empty());
+
+        // Bitstamp crosses Binance, but its levels are stale while it is delayed
+        let delayed = BookFixture::new()
+            .sequence(2)
+            .bid(101.0, 1.0, ExchangeType::Bitstamp)
+            .ask(100.0, 1.0, ExchangeType::Binance)
+            .delayed(ExchangeType::Bitstamp)
+            .build();
+        assert!(detector.update(&delayed).is_empty());
+    }
+}
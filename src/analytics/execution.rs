@@ -0,0 +1,182 @@
+use thiserror::Error;
+
+use crate::exchanges::ExchangeType;
+use crate::hub::MergedBook;
+use crate::orderbook::summary::BookStatistics;
+
+#[derive(Error, Debug)]
+pub enum ExecutionError {
+    #[error("the size to execute must be a positive number, got {0}")]
+    InvalidSize(f64),
+    #[error("the {0:?} side of the book is empty")]
+    EmptySide(Side),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Side {
+    /// Takes the asks
+    Buy,
+    /// Takes the bids
+    Sell,
+}
+
+/// How much to execute, in the base asset or as an amount of the quote asset to spend or receive.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Size {
+    Quantity(f64),
+    Notional(f64),
+}
+
+impl Size {
+    fn value(&self) -> f64 {
+        match self {
+            Size::Quantity(value) | Size::Notional(value) => *value,
+        }
+    }
+}
+
+/// The part of a fill taken from a single venue.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Allocation {
+    pub exchange: ExchangeType,
+    pub quantity: f64,
+    pub notional: f64,
+}
+
+/// The cost of taking liquidity from the merged book.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub side: Side,
+    pub quantity: f64,
+    pub notional: f64,
+    /// Volume-weighted average price of the fill
+    pub average_price: f64,
+    /// Price of the last level the fill reaches into
+    pub worst_price: f64,
+    /// Absent when either side of the book is empty
+    pub mid_price: Option<f64>,
+    /// How much worse than the mid price the average price is, in basis points
+    pub slippage_bps: Option<f64>,
+    /// Venues in the order the fill reaches them
+    pub allocations: Vec<Allocation>,
+    /// What the levels held could not fill, in the units the size was requested in
+    pub unfilled: f64,
+}
+
+impl Estimate {
+    pub fn is_complete(&self) -> bool {
+        self.unfilled <= 0.0
+    }
+}
+
+/// Walks the levels of `book` that an order of `size` on `side` would take, best first.
+///
+/// When the levels held cannot fill the order the estimate covers what they can, with the rest left `unfilled`.
+pub fn estimate(book: &MergedBook, side: Side, size: Size) -> Result<Estimate, ExecutionError> {
+    if !(size.value().is_finite() && size.value() > 0.0) {
+        return Err(ExecutionError::InvalidSize(size.value()));
+    }
+    let levels: Vec<(f64, f64, ExchangeType)> = match side {
+        Side::Buy => book.asks.iter().map(|ask| (ask.price, ask.amount, ask.exchange)).collect(),
+        Side::Sell => book.bids.iter().map(|bid| (bid.price, bid.amount, bid.exchange)).collect(),
+    };
+    if levels.is_empty() {
+        return Err(ExecutionError::EmptySide(side));
+    }
+
+    let mut remaining = size.value();
+    let (mut quantity, mut notional, mut worst_price) = (0.0, 0.0, levels[0].0);
+    let mut allocations: Vec<Allocation> = Vec::new();
+    for (price, amount, exchange) in levels {
+        if remaining <= 0.0 {
+            break;
+        }
+        let taken = match size {
+            Size::Quantity(_) => amount.min(remaining),
+            Size::Notional(_) => amount.min(remaining / price),
+        };
+        remaining -= match size {
+            Size::Quantity(_) => taken,
+            Size::Notional(_) => taken * price,
+        };
+        quantity += taken;
+        notional += taken * price;
+        worst_price = price;
+        match allocations.iter_mut().find(|allocation| allocation.exchange == exchange) {
+            Some(allocation) => {
+                allocation.quantity += taken;
+                allocation.notional += taken * price;
+            }
+            None => allocations.push(Allocation {
+                exchange,
+                quantity: taken,
+                notional: taken * price,
+            }),
+        }
+    }
+
+    let average_price = notional / quantity;
+    let mid_price = BookStatistics::new(&book.bids, &book.asks).mid_price;
+    let slippage_bps = mid_price.map(|mid| match side {
+        Side::Buy => (average_price - mid) / mid * 10_000.0,
+        Side::Sell => (mid - average_price) / mid * 10_000.0,
+    });
+    Ok(Estimate {
+        side,
+        quantity,
+        notional,
+        average_price,
+        worst_price,
+        mid_price,
+        slippage_bps,
+        allocations,
+        // Rounding can leave a remainder too small to matter
+        unfilled: if remaining > size.value() * 1e-12 { remaining } else { 0.0 },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exchanges::ExchangeType,
+        hub::{BookFixture, MergedBook},
+    };
+
+    use super::{estimate, Side, Size};
+
+    fn book() -> MergedBook {
+        BookFixture::new()
+            .bid(98.0, 1.0, ExchangeType::Binance)
+            .ask(102.0, 1.0, ExchangeType::Bitstamp)
+            .ask(104.0, 1.0, ExchangeType::Binance)
+            .ask(105.0, 1.0, ExchangeType::Bitstamp)
+            .build()
+    }
+
+    #[test]
+    fn test_estimate() {
+        let estimate = estimate(&book(), Side::Buy, Size::Quantity(2.5)).unwrap();
+        assert_eq!(estimate.notional, 102.0 + 104.0 + 52.5);
+        assert_eq!(estimate.average_price, 258.5 / 2.5);
+        assert_eq!(estimate.worst_price, 105.0);
+        // 103.4 against a mid of 100
+        assert!((estimate.slippage_bps.unwrap() - 340.0).abs() < 1e-9);
+        assert_eq!(estimate.allocations.len(), 2);
+        assert_eq!(estimate.allocations[0].exchange, ExchangeType::Bitstamp);
+        assert_eq!(estimate.allocations[0].quantity, 1.5);
+        assert!(estimate.is_complete());
+
+        let estimate = super::estimate(&book(), Side::Sell, Size::Notional(49.0)).unwrap();
+        assert_eq!(estimate.quantity, 0.5);
+        assert_eq!(estimate.slippage_bps, Some(200.0));
+    }
+
+    #[test]
+    fn test_insufficient_depth() {
+        let estimate = estimate(&book(), Side::Sell, Size::Quantity(3.0)).unwrap();
+        assert!(!estimate.is_complete());
+        assert_eq!(estimate.quantity, 1.0);
+        assert_eq!(estimate.unfilled, 2.0);
+        assert!(super::estimate(&book(), Side::Buy, Size::Quantity(0.0)).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::exchanges::ExchangeType;
+use crate::hub::{HubError, MergedBook};
+use crate::orderbook::summary::BookStatistics;
+
+use super::{Analysis, SharedAnalysis};
+
+/// Which depths and distances from the mid price the metrics are taken over.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsConfig {
+    /// Numbers of levels on each side to measure the imbalance over
+    pub imbalance_depths: Vec<usize>,
+    /// Distances from the mid price, in basis points, to total the depth within
+    pub depth_bands_bps: Vec<f64>,
+    /// Levels on each side the weighted mid, slope and venue shares are taken over
+    pub top_levels: usize,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            imbalance_depths: vec![1, 5, 10],
+            depth_bands_bps: vec![10.0, 50.0, 100.0],
+            top_levels: 10,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Imbalance {
+    pub levels: usize,
+    /// Bid volume less ask volume over their sum, from -1 when there are only asks to 1 when there are only bids
+    pub imbalance: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct DepthBand {
+    pub bps: f64,
+    /// Amount bid within the band below the mid price
+    pub bid_volume: f64,
+    /// Amount asked within the band above the mid price
+    pub ask_volume: f64,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VenueShare {
+    pub exchange: ExchangeType,
+    /// Fraction of the top bid volume quoted by the venue
+    pub bid_share: f64,
+    /// Fraction of the top ask volume quoted by the venue
+    pub ask_share: f64,
+}
+
+/// Imbalance and liquidity measures of a single update of a merged book.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookMetrics {
+    pub symbol: String,
+    pub sequence: u64,
+    pub timestamp: SystemTime,
+    pub imbalances: Vec<Imbalance>,
+    /// Empty when either side of the book is empty, as there is no mid price
+    pub depth_bands: Vec<DepthBand>,
+    /// Mid of the volume-weighted average prices of the top levels of each side
+    pub weighted_mid: Option<f64>,
+    /// Amount added per basis point away from the mid price across the top bids
+    pub bid_slope: Option<f64>,
+    /// Amount added per basis point away from the mid price across the top asks
+    pub ask_slope: Option<f64>,
+    /// Venues in the order they first appear in the book
+    pub venue_shares: Vec<VenueShare>,
+}
+
+impl BookMetrics {
+    pub fn new(book: &MergedBook, config: &MetricsConfig) -> Self {
+        let bid_volume = |levels: usize| book.bids.iter().take(levels).map(|bid| bid.amount).sum::<f64>();
+        let ask_volume = |levels: usize| book.asks.iter().take(levels).map(|ask| ask.amount).sum::<f64>();
+        let imbalances = config
+            .imbalance_depths
+            .iter()
+            .map(|&levels| {
+                let (bids, asks) = (bid_volume(levels), ask_volume(levels));
+                let total = bids + asks;
+                Imbalance {
+                    levels,
+                    imbalance: if total > 0.0 { (bids - asks) / total } else { 0.0 },
+                }
+            })
+            .collect();
+
+        let mid = BookStatistics::new(&book.bids, &book.asks).mid_price;
+        let depth_bands = match mid {
+            Some(mid) => config
+                .depth_bands_bps
+                .iter()
+                .map(|&bps| {
+                    let distance = mid * bps / 10_000.0;
+                    DepthBand {
+                        bps,
+                        bid_volume: book
+                            .bids
+                            .iter()
+                            .filter(|bid| bid.price >= mid - distance)
+                            .map(|bid| bid.amount)
+                            .sum(),
+                        ask_volume: book
+                            .asks
+                            .iter()
+                            .filter(|ask| ask.price <= mid + distance)
+                            .map(|ask| ask.amount)
+                            .sum(),
+                    }
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
+        let (bids, asks) = book.truncated(config.top_levels);
+        let bid_prices: Vec<(f64, f64)> = bids.iter().map(|bid| (bid.price, bid.amount)).collect();
+        let ask_prices: Vec<(f64, f64)> = asks.iter().map(|ask| (ask.price, ask.amount)).collect();
+        let weighted_mid = match (average_price(&bid_prices), average_price(&ask_prices)) {
+            (Some(bid), Some(ask)) => Some((bid + ask) / 2.0),
+            _ => None,
+        };
+        let (bid_slope, ask_slope) = match mid {
+            Some(mid) => (slope(&bid_prices, mid), slope(&ask_prices, mid)),
+            None => (None, None),
+        };
+
+        let (top_bid_volume, top_ask_volume) = (bid_volume(config.top_levels), ask_volume(config.top_levels));
+        let mut venue_shares: Vec<VenueShare> = Vec::new();
+        let levels = bids
+            .iter()
+            .map(|bid| (bid.exchange, bid.amount, 0.0))
+            .chain(asks.iter().map(|ask| (ask.exchange, 0.0, ask.amount)));
+        for (exchange, bid_amount, ask_amount) in levels {
+            let index = match venue_shares.iter().position(|share| share.exchange == exchange) {
+                Some(index) => index,
+                None => {
+                    venue_shares.push(VenueShare {
+                        exchange,
+                        bid_share: 0.0,
+                        ask_share: 0.0,
+                    });
+                    venue_shares.len() - 1
+                }
+            };
+            if top_bid_volume > 0.0 {
+                venue_shares[index].bid_share += bid_amount / top_bid_volume;
+            }
+            if top_ask_volume > 0.0 {
+                venue_shares[index].ask_share += ask_amount / top_ask_volume;
+            }
+        }
+
+        Self {
+            symbol: book.symbol.clone(),
+            sequence: book.sequence,
+            timestamp: book.timestamp,
+            imbalances,
+            depth_bands,
+            weighted_mid,
+            bid_slope,
+            ask_slope,
+            venue_shares,
+        }
+    }
+}
+
+/// Volume-weighted average of `(price, amount)` levels.
+fn average_price(levels: &[(f64, f64)]) -> Option<f64> {
+    let volume: f64 = levels.iter().map(|(_, amount)| amount).sum();
+    (volume > 0.0).then(|| levels.iter().map(|(price, amount)| price * amount).sum::<f64>() / volume)
+}
+
+/// Total amount of `(price, amount)` levels over the distance of the furthest from `mid`, in basis points.
+fn slope(levels: &[(f64, f64)], mid: f64) -> Option<f64> {
+    let (furthest, _) = levels.last()?;
+    let distance_bps = (furthest - mid).abs() / mid * 10_000.0;
+    let volume: f64 = levels.iter().map(|(_, amount)| amount).sum();
+    (distance_bps > 0.0).then(|| volume / distance_bps)
+}
+
+/// Computes `BookMetrics` for every update of a symbol's book.
+pub struct MetricsAnalysis {
+    config: MetricsConfig,
+}
+
+impl MetricsAnalysis {
+    pub fn new(config: MetricsConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl Analysis for MetricsAnalysis {
+    type Output = Arc<BookMetrics>;
+
+    fn update(&mut self, book: &MergedBook) -> Vec<Arc<BookMetrics>> {
+        vec![Arc::new(BookMetrics::new(book, &self.config))]
+    }
+}
+
+/// Logs the metrics of `symbol` at most once per `interval`, until the analysis stops.
+pub async fn log_metrics(
+    metrics: SharedAnalysis<MetricsAnalysis>,
+    symbol: String,
+    interval: Duration,
+) -> Result<(), HubError> {
+    let mut updates = metrics.subscribe(&symbol).await?;
+    let mut last_logged: Option<Instant> = None;
+    loop {
+        let update = match updates.recv().await {
+            Ok(update) => update,
+            Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return Ok(()),
+        };
+        if last_logged.is_some_and(|at| at.elapsed() < interval) {
+            continue;
+        }
+        last_logged = Some(Instant::now());
+        let imbalances: Vec<String> = update
+            .imbalances
+            .iter()
+            .map(|imbalance| format!("{}:{:+.3}", imbalance.levels, imbalance.imbalance))
+            .collect();
+        let bands: Vec<String> = update
+            .depth_bands
+            .iter()
+            .map(|band| format!("{}bps:{:.4}/{:.4}", band.bps, band.bid_volume, band.ask_volume))
+            .collect();
+        let shares: Vec<String> = update
+            .venue_shares
+            .iter()
+            .map(|share| format!("{}:{:.2}/{:.2}", share.exchange.to_string(), share.bid_share, share.ask_share))
+            .collect();
+        info!(
+            "{} metrics #{}: imbalance [{}] depth [{}] weighted mid {:?} slope {:?}/{:?} venue share [{}]",
+            update.symbol,
+            update.sequence,
+            imbalances.join(" "),
+            bands.join(" "),
+            update.weighted_mid,
+            update.bid_slope,
+            update.ask_slope,
+            shares.join(" "),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{exchanges::ExchangeType, hub::BookFixture};
+
+    use super::{BookMetrics, MetricsConfig};
+
+    #[test]
+    fn test_book_metrics() {
+        let book = BookFixture::new()
+            .bid(99.0, 3.0, ExchangeType::Binance)
+            .bid(98.0, 1.0, ExchangeType::Bitstamp)
+            .ask(101.0, 1.0, ExchangeType::Bitstamp)
+            .build();
+        let config = MetricsConfig {
+            imbalance_depths: vec![1, 2],
+            depth_bands_bps: vec![100.0, 200.0],
+            top_levels: 2,
+        };
+        let metrics = BookMetrics::new(&book, &config);
+        assert_eq!(metrics.imbalances[0].imbalance, 0.5);
+        assert_eq!(metrics.imbalances[1].imbalance, 0.6);
+        // A mid of 100, so 100bps reaches 99 and 101 but not 98
+        assert_eq!(metrics.depth_bands[0].bid_volume, 3.0);
+        assert_eq!(metrics.depth_bands[0].ask_volume, 1.0);
+        assert_eq!(metrics.depth_bands[1].bid_volume, 4.0);
+        assert_eq!(metrics.weighted_mid, Some((98.75 + 101.0) / 2.0));
+        assert_eq!(metrics.bid_slope, Some(4.0 / 200.0));
+        assert_eq!(metrics.venue_shares[0].exchange, ExchangeType::Binance);
+        assert_eq!(metrics.venue_shares[0].bid_share, 0.75);
+        assert_eq!(metrics.venue_shares[1].ask_share, 1.0);
+    }
+}
@@ -0,0 +1,126 @@
+pub mod arbitrage;
+pub mod execution;
+pub mod metrics;
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use futures::StreamExt;
+use tokio::sync::broadcast;
+
+use crate::hub::{BookEvent, BookHub, HubError, MergedBook};
+use crate::queue::OverflowPolicy;
+
+/// Outputs buffered for each subscriber to an analysis before it starts missing them
+const ANALYSIS_CHANNEL_CAPACITY: usize = 256;
+/// Books buffered for an analysis that falls behind the merged book
+const ANALYSIS_QUEUE_CAPACITY: usize = 64;
+
+/// A computation run over every update of a symbol's merged book.
+pub trait Analysis: Send + 'static {
+    type Output: Clone + Send + 'static;
+
+    /// Analyses the latest book, returning anything to publish to subscribers.
+    fn update(&mut self, book: &MergedBook) -> Vec<Self::Output>;
+}
+
+type Channels<T> = Arc<Mutex<HashMap<String, Arc<broadcast::Sender<T>>>>>;
+
+/// Runs a single instance of an analysis per symbol on the hub's shared book, however many clients subscribe to it.
+///
+/// An analysis starts with its first subscriber and stops once it publishes to none.
+pub struct SharedAnalysis<A: Analysis> {
+    hub: BookHub,
+    create: Arc<dyn Fn(&str) -> A + Send + Sync>,
+    channels: Channels<A::Output>,
+}
+
+impl<A: Analysis> Clone for SharedAnalysis<A> {
+    fn clone(&self) -> Self {
+        Self {
+            hub: self.hub.clone(),
+            create: self.create.clone(),
+            channels: self.channels.clone(),
+        }
+    }
+}
+
+impl<A: Analysis> SharedAnalysis<A> {
+    /// Analyses the hub's books with instances made by `create` for each symbol.
+    pub fn new<F>(hub: BookHub, create: F) -> Self
+    where
+        F: Fn(&str) -> A + Send + Sync + 'static,
+    {
+        Self {
+            hub,
+            create: Arc::new(create),
+            channels: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Receives the outputs of the analysis of `symbol`, starting it if nobody else is subscribed.
+    pub async fn subscribe(&self, symbol: &str) -> Result<broadcast::Receiver<A::Output>, HubError> {
+        let symbol = self.hub.resolve_symbol(symbol)?;
+        if let Some(tx) = self.channels.lock().unwrap().get(&symbol) {
+            return Ok(tx.subscribe());
+        }
+        let mut subscription = self
+            .hub
+            .subscribe(
+                &symbol,
+                self.hub.config().max_depth_of(&symbol),
+                ANALYSIS_QUEUE_CAPACITY,
+                OverflowPolicy::DropOldest,
+            )
+            .await?;
+        let (tx, rx) = {
+            let mut channels = self.channels.lock().unwrap();
+            // Another subscriber may have started the analysis while the book was connecting
+            if let Some(tx) = channels.get(&symbol) {
+                return Ok(tx.subscribe());
+            }
+            let (tx, rx) = broadcast::channel(ANALYSIS_CHANNEL_CAPACITY);
+            let tx = Arc::new(tx);
+            channels.insert(symbol.clone(), tx.clone());
+            (tx, rx)
+        };
+
+        let mut analysis = (self.create)(&symbol);
+        let channels = self.channels.clone();
+        let hub = self.hub.clone();
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    _ = hub.shutting_down() => break,
+                    event = subscription.next() => match event {
+                        Some(event) => event,
+                        None => break,
+                    },
+                };
+                let outputs = match event {
+                    BookEvent::Update(book) => analysis.update(&book),
+                    _ => continue,
+                };
+                let mut channels = channels.lock().unwrap();
+                for output in outputs {
+                    // Fails only when nobody is subscribed, which is checked below
+                    let _ = tx.send(output);
+                }
+                // Checked under the lock, so a new subscriber either joins this analysis or starts another
+                if tx.receiver_count() == 0 {
+                    channels.remove(&symbol);
+                    break;
+                }
+            }
+            debug!("stopped analysing the {symbol} orderbook");
+            // The book may have closed instead, dropping the sender ends every subscriber's stream
+            let mut channels = channels.lock().unwrap();
+            if channels.get(&symbol).is_some_and(|current| Arc::ptr_eq(current, &tx)) {
+                channels.remove(&symbol);
+            }
+        });
+        Ok(rx)
+    }
+}
@@ -1,38 +1,295 @@
 use std::str::FromStr;
 use std::error::Error;
+use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
-use orderbook::exchanges::ExchangeType;
+use orderbook::alerts::{
+    sink::{self, AlertSink, FileSink, LogSink, WebhookSink},
+    AlertEngine, AlertRule,
+};
+use orderbook::analytics::{
+    metrics::{log_metrics, MetricsAnalysis, MetricsConfig},
+    SharedAnalysis,
+};
+use orderbook::exchanges::{
+    self,
+    record::{self, RecordConfig},
+};
+use orderbook::export::ExportFormat;
+use orderbook::hub::{history::HistoryLimit, BookHub, HubConfig};
+use orderbook::orderbook::synthetic::{SyntheticError, SyntheticSymbol};
 use orderbook::server::{
-    orderbook_rpc::orderbook_aggregator_server::OrderbookAggregatorServer, OrderbookSummaryService,
+    auth::Authenticator,
+    orderbook_rpc::orderbook_aggregator_server::OrderbookAggregatorServer,
+    v1::rpc::orderbook_service_server::OrderbookServiceServer, OrderbookSummaryService,
 };
-use orderbook::cli::{Args, CliError};
-use tonic::transport::Server;
+use orderbook::cli::{export, validate_config, watch, Args, Command};
+use orderbook::config::{self, ServerConfig};
+use orderbook::persistence::{self, PersistenceConfig, Retention};
+use orderbook::rest::RestGateway;
+use orderbook::websocket::WebsocketServer;
+use tokio::signal::unix::{signal, SignalKind};
+use tokio_native_tls::{native_tls, TlsAcceptor};
+use tonic::service::interceptor::InterceptedService;
+use tonic::transport::{Certificate, Identity, Server, ServerTlsConfig};
 
+#[macro_use]
+extern crate log;
 
+/// Completes when the process receives SIGINT or SIGTERM
+async fn shutdown_signal() -> Result<(), std::io::Error> {
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        result = tokio::signal::ctrl_c() => result,
+        _ = terminate.recv() => Ok(()),
+    }
+}
+
+/// Builds the TLS configuration when a certificate has been supplied
+fn tls_config(config: &ServerConfig) -> Result<Option<ServerTlsConfig>, std::io::Error> {
+    let (cert, key) = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => (std::fs::read(cert)?, std::fs::read(key)?),
+        _ => return Ok(None),
+    };
+    let mut tls = ServerTlsConfig::new().identity(Identity::from_pem(cert, key));
+    if let Some(client_ca) = &config.tls_client_ca {
+        tls = tls.client_ca_root(Certificate::from_pem(std::fs::read(client_ca)?));
+    }
+    Ok(Some(tls))
+}
+
+/// Builds the TLS acceptor of the websocket and HTTP listeners from the same certificate as the gRPC server
+fn tls_acceptor(config: &ServerConfig) -> Result<Option<TlsAcceptor>, Box<dyn Error>> {
+    let (cert, key) = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => (std::fs::read(cert)?, std::fs::read(key)?),
+        _ => return Ok(None),
+    };
+    let identity = native_tls::Identity::from_pkcs8(&cert, &key)?;
+    Ok(Some(TlsAcceptor::from(native_tls::TlsAcceptor::new(identity)?)))
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     pretty_env_logger::init();
     let args = Args::parse();
+    match args.command {
+        Some(Command::Watch(watch_args)) => return watch::watch(watch_args).await,
+        Some(Command::Export(export_args)) => return export::export(export_args).await,
+        Some(Command::ValidateConfig(validate_args)) => return validate_config::validate_config(validate_args),
+        None => {}
+    }
+
+    // Flags given alongside the configuration file override its values, every problem is reported at once
+    let config = match config::from_args(&args) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    if !config.endpoints.is_empty() {
+        exchanges::set_endpoints(config.endpoints.clone())?;
+    }
+    let shutdown_timeout = Duration::from_secs(args.shutdown_timeout);
+    let mut symbols = config.symbols.clone();
+    let mut synthetics: Vec<SyntheticSymbol> = Vec::with_capacity(args.synthetics.len());
+    for synthetic in &args.synthetics {
+        let synthetic = SyntheticSymbol::from_str(synthetic)?;
+        // A synthetic symbol is served even when it is not listed itself
+        if !symbols.contains(&synthetic.symbol) {
+            symbols.push(synthetic.symbol.clone());
+        }
+        synthetics.push(synthetic);
+    }
+    for leg in synthetics.iter().flat_map(|synthetic| [&synthetic.base_leg, &synthetic.quote_leg]) {
+        if synthetics.iter().any(|synthetic| &synthetic.symbol == leg) {
+            return Err(SyntheticError::SyntheticLeg(leg.clone()).into());
+        }
+    }
+    if let Some(directory) = &args.record {
+        record::start(RecordConfig {
+            directory: directory.clone(),
+            max_bytes: args.record_max_mb * 1024 * 1024,
+            max_age: Duration::from_secs(args.record_rotate_secs),
+        })?;
+    }
+    // Every endpoint shares a single merged book per symbol
+    let hub = BookHub::new(HubConfig {
+        symbols: symbols.clone(),
+        exchanges: config.exchanges.clone(),
+        max_depth: config.max_depth,
+        reconnect: config.reconnect,
+        fees: config.fees.clone(),
+        synthetics,
+        latency_threshold: args.latency_threshold_ms.map(Duration::from_millis),
+        history: HistoryLimit {
+            max_books: args.history_books,
+            max_age: args.history_secs.map(Duration::from_secs),
+        },
+        overrides: config.overrides.clone(),
+    });
+    let orderbook_server = Arc::new(
+        OrderbookSummaryService::new(hub.clone())
+            .with_client_queue(config.queue_capacity, config.overflow_policy),
+    );
+    // Metrics are computed once per symbol for both the logs and the BookMetrics RPC
+    let metrics_config = MetricsConfig {
+        imbalance_depths: args.imbalance_depths.clone(),
+        depth_bands_bps: args.depth_bands_bps.clone(),
+        ..MetricsConfig::default()
+    };
+    let metrics = SharedAnalysis::new(hub.clone(), move |_: &str| MetricsAnalysis::new(metrics_config.clone()));
+    if let Some(seconds) = args.log_metrics {
+        for symbol in &symbols {
+            let (metrics, symbol) = (metrics.clone(), symbol.clone());
+            tokio::spawn(async move {
+                if let Err(e) = log_metrics(metrics, symbol.clone(), Duration::from_secs(seconds)).await {
+                    error!("could not log the {symbol} metrics: {e}");
+                }
+            });
+        }
+    }
+    // Rules are evaluated once per symbol for both the sinks and the Alerts RPC
+    let mut rules = Vec::with_capacity(args.alerts.len());
+    for rule in &args.alerts {
+        rules.push(AlertRule::from_str(rule)?);
+    }
+    let alerts = AlertEngine::new(hub.clone(), rules);
+    let mut sinks: Vec<Arc<dyn AlertSink>> = Vec::new();
+    if args.alert_log {
+        sinks.push(Arc::new(LogSink {}));
+    }
+    if let Some(path) = &args.alert_file {
+        sinks.push(Arc::new(FileSink::open(path)?));
+    }
+    if let Some(url) = &args.alert_webhook {
+        sinks.push(Arc::new(WebhookSink::new(url)?));
+    }
+    if !alerts.rules().is_empty() {
+        if !sinks.is_empty() {
+            tokio::spawn(sink::deliver(sinks, alerts.subscribe()));
+        }
+        for symbol in &symbols {
+            let (alerts, symbol) = (alerts.clone(), symbol.clone());
+            tokio::spawn(async move {
+                if let Err(e) = alerts.watch(symbol.clone()).await {
+                    error!("could not watch {symbol} for alerts: {e}");
+                }
+            });
+        }
+    }
+
+    if let Some(path) = &args.persist {
+        persistence::start(
+            hub.clone(),
+            &alerts,
+            symbols.clone(),
+            PersistenceConfig {
+                path: path.clone(),
+                top_interval: Duration::from_millis(args.persist_top_ms),
+                retention: Retention {
+                    max_age: args.persist_retention_secs.map(Duration::from_secs),
+                    max_rows: args.persist_max_rows,
+                },
+            },
+        )?;
+    }
+    if hub.config().history.is_enabled() {
+        // History is only retained while a book is connected, so every symbol is kept connected
+        for symbol in &symbols {
+            let (hub, symbol) = (hub.clone(), symbol.clone());
+            tokio::spawn(async move {
+                if let Err(e) = hub.retain_history(symbol.clone()).await {
+                    error!("could not retain the history of {symbol}: {e}");
+                }
+            });
+        }
+    }
+    let exports = [
+        (ExportFormat::Csv, &args.export_csv),
+        (ExportFormat::Binary, &args.export_binary),
+    ];
+    for (format, path) in exports {
+        if let Some(path) = path {
+            let writer = orderbook::export::create(format, path)?;
+            let (hub, symbols) = (hub.clone(), symbols.clone());
+            tokio::spawn(async move {
+                if let Err(e) = orderbook::export::export_books(hub, symbols, writer).await {
+                    error!("could not export books as {format}: {e}");
+                }
+            });
+        }
+    }
 
-    // Ensure arguments are valid
-    let addr = format!("[::0]:{}",args.port).parse()?;
-    if !(args.max_depth > 0) {
-        // Might have restrictions on the exchange
-        return Err(CliError::MaxDepthNotGreaterThanZeroError.into())
-    }
-    let mut exchanges : Vec<ExchangeType> = Vec::with_capacity(args.exchanges.len());
-    for exchange in args.exchanges {
-        exchanges.push(ExchangeType::from_str(&exchange)?);
-    }
-    // Supply the orderbook server with arguments
-    let orderbook_server = OrderbookSummaryService::new(args.symbol, args.max_depth, &exchanges);
-    
-    // Run server
-    Server::builder()
-        .add_service(OrderbookAggregatorServer::new(orderbook_server))
-        .serve(addr)
-        .await?;
+    let authenticator = match &config.api_keys {
+        Some(path) => Authenticator::from_file(path)?,
+        None => {
+            warn!("no API keys configured, the server is open to every client");
+            Authenticator::allow_all()
+        }
+    };
+    let acceptor = tls_acceptor(&config)?;
+    if config.tls_client_ca.is_some() && (config.websocket.is_some() || config.http.is_some()) {
+        warn!("client certificates are only checked by gRPC, the websocket and HTTP listeners rely on API keys");
+    }
+    if let Some(addr) = config.websocket {
+        let mut websocket = WebsocketServer::new(hub.clone(), authenticator.clone())
+            .with_client_queue(config.queue_capacity, config.overflow_policy);
+        if let Some(tls) = &acceptor {
+            websocket = websocket.with_tls(tls.clone());
+        }
+        tokio::spawn(async move {
+            if let Err(e) = websocket.serve(addr).await {
+                error!("websocket server failed: {e}");
+            }
+        });
+    }
+    if let Some(addr) = config.http {
+        let mut gateway = RestGateway::new(hub.clone(), authenticator.clone());
+        if let Some(tls) = &acceptor {
+            gateway = gateway.with_tls(tls.clone());
+        }
+        tokio::spawn(async move {
+            if let Err(e) = gateway.serve(addr).await {
+                error!("REST gateway failed: {e}");
+            }
+        });
+    }
+    let mut server = Server::builder();
+    if let Some(tls) = tls_config(&config)? {
+        server = server.tls_config(tls)?;
+    }
+
+    // Run server until it fails or is asked to stop
+    let server = server
+        .add_service(InterceptedService::new(
+            OrderbookServiceServer::new(orderbook_server.v1().with_metrics(metrics).with_alerts(alerts)),
+            authenticator.clone(),
+        ))
+        .add_service(InterceptedService::new(
+            OrderbookAggregatorServer::from_arc(orderbook_server.clone()),
+            authenticator,
+        ))
+        .serve_with_shutdown(config.grpc, hub.shutting_down());
+    tokio::pin!(server);
+    tokio::select! {
+        result = &mut server => return Ok(result?),
+        signal = shutdown_signal() => signal?,
+    }
+
+    // Stop accepting streams, tell clients why and close the exchange websockets
+    info!("shutting down, waiting up to {shutdown_timeout:?} for streams to close");
+    orderbook_server.shutdown();
+    let drained = tokio::time::timeout(shutdown_timeout, async {
+        let result = (&mut server).await;
+        exchanges::feeds_closed().await;
+        result
+    })
+    .await;
+    match drained {
+        Ok(result) => result?,
+        Err(_) => warn!("streams did not close within {shutdown_timeout:?}, exiting anyway"),
+    }
     Ok(())
 }
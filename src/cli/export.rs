@@ -0,0 +1,23 @@
+use std::str::FromStr;
+
+use crate::exchanges::replay::ReplaySession;
+use crate::export::{self, ExportFormat};
+
+use super::{CliError, ExportArgs};
+
+/// Merges the recorded frames of a symbol as fast as possible and writes every update to the output.
+pub async fn export(args: ExportArgs) -> Result<(), Box<dyn std::error::Error>> {
+    if args.max_depth == 0 {
+        return Err(CliError::MaxDepthNotGreaterThanZeroError.into());
+    }
+    let format = ExportFormat::from_str(&args.format)?;
+    let session = ReplaySession::open(&args.input)?;
+    let mut writer = export::create(format, &args.output)?;
+    let updates = export::export_replay(&session, &args.symbol, args.max_depth, writer.as_mut()).await?;
+    println!(
+        "wrote {updates} updates of {} to {} as {format}",
+        args.symbol,
+        args.output.display()
+    );
+    Ok(())
+}
@@ -1,4 +1,10 @@
-use clap::{Parser, command};
+pub mod export;
+pub mod validate_config;
+pub mod watch;
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand, command};
 use thiserror::Error;
 
 
@@ -14,23 +20,220 @@ pub enum CliError {
 /// A program that merges the orderbooks from multiple exchanges, the CLI is used to configure the GRPC server :)
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
+#[command(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 pub struct Args {
+    /// Runs a client or offline tool instead of the server
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// TOML or YAML file configuring the server, whose values are overridden by the flags given alongside it
+    #[arg(long)]
+    pub config: Option<PathBuf>,
+
     /// Maximum depth of retrieved orders
-    #[arg(short, long)]
-    pub max_depth: usize,
+    #[arg(short, long, required_unless_present = "config")]
+    pub max_depth: Option<usize>,
 
     /// Exchanges to source orders from
     #[arg(short, long, value_delimiter = ',')]
     pub exchanges: Vec<String>,
 
     /// Port to expose server
-    #[arg(short, long)]
-    pub port: String,
+    #[arg(short, long, required_unless_present = "config")]
+    pub port: Option<String>,
+
+    /// Symbols to construct orderbooks for, the first is streamed to clients that do not choose one
+    #[arg(short, long = "symbol", value_delimiter = ',', required_unless_present = "config")]
+    pub symbols: Vec<String>,
+
+    /// Port to expose the websocket JSON endpoint, which is disabled without one
+    #[arg(long)]
+    pub websocket_port: Option<u16>,
+
+    /// Port to expose the REST gateway, which is disabled without one
+    #[arg(long)]
+    pub http_port: Option<u16>,
+
+    /// Updates queued for each client before the overflow policy applies, 64 if absent
+    #[arg(long)]
+    pub queue_capacity: Option<usize>,
+
+    /// What to do when a client falls behind: drop-oldest, conflate or disconnect, drop-oldest if absent
+    #[arg(long)]
+    pub overflow_policy: Option<String>,
+
+    /// Seconds to wait for streams and exchange connections to close after SIGINT or SIGTERM
+    #[arg(long, default_value_t = 10)]
+    pub shutdown_timeout: u64,
+
+    /// PEM certificate to serve TLS with
+    #[arg(long, requires = "tls_key")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key of the TLS certificate
+    #[arg(long, requires = "tls_cert")]
+    pub tls_key: Option<PathBuf>,
+
+    /// PEM certificate authority that client certificates must be signed by, enabling mutual TLS
+    #[arg(long, requires = "tls_cert")]
+    pub tls_client_ca: Option<PathBuf>,
+
+    /// JSON file of API keys that clients must present, the server is open to anyone without one
+    #[arg(long)]
+    pub api_keys: Option<PathBuf>,
+
+    /// Taker fees of each venue in basis points, such as binance=10,bitstamp=30
+    #[arg(long, value_delimiter = ',')]
+    pub taker_fees: Vec<String>,
+
+    /// Maker fees of each venue in basis points, such as binance=2,bitstamp=0
+    #[arg(long, value_delimiter = ',')]
+    pub maker_fees: Vec<String>,
+
+    /// Symbols to also merge levels implied by two other symbols into, such as ethbtc=ethusdt/btcusdt
+    #[arg(long = "synthetic", value_delimiter = ',')]
+    pub synthetics: Vec<String>,
+
+    /// Numbers of levels the book metrics measure the imbalance over
+    #[arg(long, value_delimiter = ',', default_values_t = [1, 5, 10])]
+    pub imbalance_depths: Vec<usize>,
+
+    /// Distances from the mid price in basis points the book metrics total the depth within
+    #[arg(long, value_delimiter = ',', default_values_t = [10.0, 50.0, 100.0])]
+    pub depth_bands_bps: Vec<f64>,
+
+    /// Seconds between logging the book metrics of every symbol, which are not logged without one
+    #[arg(long)]
+    pub log_metrics: Option<u64>,
+
+    /// Milliseconds a venue's latency may rise above its usual before its levels are flagged as delayed
+    #[arg(long)]
+    pub latency_threshold_ms: Option<u64>,
+
+    /// Alert rules evaluated on every symbol, such as spread>10bps/30s, stale/60s, crossed or top-size<0.5
+    #[arg(long = "alert", value_delimiter = ',')]
+    pub alerts: Vec<String>,
+
+    /// Log alerts as they fire and resolve
+    #[arg(long)]
+    pub alert_log: bool,
+
+    /// File to append alerts to as JSON lines
+    #[arg(long)]
+    pub alert_file: Option<PathBuf>,
+
+    /// URL to post alerts to as JSON
+    #[arg(long)]
+    pub alert_webhook: Option<String>,
 
-    /// Symbol to construct orderbook
+    /// Directory to record every frame received from the venues to, as JSON lines
+    #[arg(long)]
+    pub record: Option<PathBuf>,
+
+    /// Megabytes a recording may grow to before another is started
+    #[arg(long, default_value_t = 100)]
+    pub record_max_mb: u64,
+
+    /// Seconds a recording may run for before another is started
+    #[arg(long, default_value_t = 3600)]
+    pub record_rotate_secs: u64,
+
+    /// Past books of each symbol retained for the GetBookAt and History RPCs, which are disabled unless this or
+    /// --history-secs is set
+    #[arg(long, default_value_t = 0)]
+    pub history_books: usize,
+
+    /// Seconds past which retained books are dropped, even if --history-books leaves room for them
+    #[arg(long)]
+    pub history_secs: Option<u64>,
+
+    /// SQLite database to persist the top of every symbol's book, venue transitions and alerts to
+    #[arg(long)]
+    pub persist: Option<PathBuf>,
+
+    /// Least milliseconds between two persisted tops of a symbol's book
+    #[arg(long, default_value_t = 1000)]
+    pub persist_top_ms: u64,
+
+    /// Seconds persisted rows are kept for, forever if absent
+    #[arg(long)]
+    pub persist_retention_secs: Option<u64>,
+
+    /// Rows kept in each persisted table, the oldest being deleted first
+    #[arg(long)]
+    pub persist_max_rows: Option<u64>,
+
+    /// File to write every update of every symbol's merged book to as CSV
+    #[arg(long)]
+    pub export_csv: Option<PathBuf>,
+
+    /// File to write every update of every symbol's merged book to as length-delimited protobuf Summary records
+    #[arg(long)]
+    pub export_binary: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Renders the live merged book of a running server in the terminal
+    Watch(WatchArgs),
+    /// Merges recorded frames offline and writes every update of the book as CSV or binary Summary records
+    Export(ExportArgs),
+    /// Checks a configuration file, reporting every problem found in it
+    ValidateConfig(ValidateConfigArgs),
+}
+
+#[derive(clap::Args, Debug)]
+pub struct WatchArgs {
+    /// Address of the server, such as http://localhost:50051
+    #[arg(long)]
+    pub server: String,
+
+    /// Symbol to watch, the server's default if absent
     #[arg(short, long)]
-    pub symbol: String
+    pub symbol: Option<String>,
+
+    /// Levels shown on each side
+    #[arg(short, long, default_value_t = 10)]
+    pub depth: u32,
+
+    /// API key to present to the server
+    #[arg(long)]
+    pub api_key: Option<String>,
+
+    /// PEM certificate authority to verify an https server with
+    #[arg(long)]
+    pub tls_ca: Option<PathBuf>,
+
+    /// Rank levels by quoted price (raw) or by price after each venue's taker fee (fee-adjusted)
+    #[arg(long, default_value = "raw")]
+    pub ordering: String,
 }
 
+#[derive(clap::Args, Debug)]
+pub struct ExportArgs {
+    /// Recording, or directory of recordings, made with --record
+    #[arg(short, long)]
+    pub input: PathBuf,
+
+    /// File to write the merged books to
+    #[arg(short, long)]
+    pub output: PathBuf,
+
+    /// Symbol whose frames are merged
+    #[arg(short, long)]
+    pub symbol: String,
 
+    /// Format to write, csv or binary
+    #[arg(short, long, default_value = "csv")]
+    pub format: String,
 
+    /// Maximum depth of the merged book
+    #[arg(short, long, default_value_t = 10)]
+    pub max_depth: usize,
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ValidateConfigArgs {
+    /// TOML or YAML file to check
+    pub config: PathBuf,
+}
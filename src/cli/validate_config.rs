@@ -0,0 +1,23 @@
+use crate::config::ConfigFile;
+
+use super::ValidateConfigArgs;
+
+/// Checks a configuration file as the server would read it without any flags, printing every problem found.
+pub fn validate_config(args: ValidateConfigArgs) -> Result<(), Box<dyn std::error::Error>> {
+    match ConfigFile::open(&args.config).and_then(ConfigFile::validate) {
+        Ok(config) => {
+            println!(
+                "{} is valid, serving {} on {}",
+                args.config.display(),
+                config.symbols.join(", "),
+                config.grpc
+            );
+            Ok(())
+        }
+        Err(e) => {
+            // Printed in full rather than returned, as main would only show the problems' debug form
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
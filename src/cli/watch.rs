@@ -0,0 +1,129 @@
+use std::fmt::Write;
+
+use std::str::FromStr;
+
+use futures::{pin_mut, StreamExt};
+
+use crate::client::{ClientError, OrderbookClient, Summary};
+use crate::exchanges::ExchangeType;
+use crate::orderbook::fees::BookOrdering;
+
+use super::WatchArgs;
+
+/// Clears the terminal and moves the cursor to the top left
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+
+/// The colour each venue's name is rendered in
+fn venue_colour(exchange: ExchangeType) -> &'static str {
+    match exchange {
+        ExchangeType::Binance => "\x1b[33m",
+        ExchangeType::Bitstamp => "\x1b[36m",
+        ExchangeType::Synthetic => "\x1b[35m",
+        ExchangeType::Default => RESET,
+    }
+}
+
+fn optional(value: Option<f64>) -> String {
+    value.map_or_else(|| String::from("-"), |value| format!("{value:.8}"))
+}
+
+/// Renders a merged book with the asks above the bids, so the spread sits in the middle.
+pub fn render(summary: &Summary) -> String {
+    let statistics = &summary.statistics;
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{BOLD}{}{RESET}  sequence {}  spread {}  mid {}  microprice {}",
+        summary.symbol.to_uppercase(),
+        summary.sequence,
+        optional(statistics.spread),
+        optional(statistics.mid_price),
+        optional(statistics.microprice),
+    );
+    let _ = writeln!(out, "{:>10} {:>18} {:>18}", "venue", "price", "amount");
+    for ask in summary.asks.iter().rev() {
+        let _ = writeln!(
+            out,
+            "{}{:>10}{RESET} {RED}{:>18.8}{RESET} {:>18.8}",
+            venue_colour(ask.exchange),
+            ask.exchange.to_string(),
+            ask.price,
+            ask.amount,
+        );
+    }
+    let _ = writeln!(out, "{:->48}", "");
+    for bid in &summary.bids {
+        let _ = writeln!(
+            out,
+            "{}{:>10}{RESET} {GREEN}{:>18.8}{RESET} {:>18.8}",
+            venue_colour(bid.exchange),
+            bid.exchange.to_string(),
+            bid.price,
+            bid.amount,
+        );
+    }
+    out
+}
+
+/// Subscribes to a server and redraws the terminal with every update until the subscription fails.
+pub async fn watch(args: WatchArgs) -> Result<(), Box<dyn std::error::Error>> {
+    let mut client = OrderbookClient::new(args.server).with_ordering(BookOrdering::from_str(&args.ordering)?);
+    if let Some(key) = args.api_key {
+        client = client.with_api_key(key);
+    }
+    if let Some(ca) = args.tls_ca {
+        client = client.with_tls_ca(std::fs::read(ca)?);
+    }
+    let summaries = client.subscribe(args.symbol, args.depth);
+    pin_mut!(summaries);
+    while let Some(summary) = summaries.next().await {
+        let summary: Summary = summary?;
+        print!("{CLEAR_SCREEN}{}", render(&summary));
+    }
+    Err(ClientError::from(tonic::Status::unavailable("the subscription ended")).into())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::UNIX_EPOCH;
+
+    use crate::{
+        client::Summary,
+        exchanges::ExchangeType,
+        orderbook::{
+            levels::{AskLevel, BidLevel},
+            summary::BookStatistics,
+        },
+    };
+
+    use super::render;
+
+    #[test]
+    fn test_render() {
+        let bids = vec![BidLevel::new(99.0, 1.0, ExchangeType::Binance)];
+        let asks = vec![
+            AskLevel::new(101.0, 1.0, ExchangeType::Bitstamp),
+            AskLevel::new(102.0, 1.0, ExchangeType::Binance),
+        ];
+        let summary = Summary {
+            symbol: String::from("ethbtc"),
+            sequence: 1,
+            timestamp: UNIX_EPOCH,
+            statistics: BookStatistics::new(&bids, &asks),
+            bids,
+            asks,
+        };
+        let rendered = render(&summary);
+        assert!(rendered.contains("ETHBTC"));
+        assert!(rendered.contains("spread 2.00000000"));
+        // Worst ask first, so the best prices meet in the middle
+        let worst_ask = rendered.find("102.00000000").unwrap();
+        let best_ask = rendered.find("101.00000000").unwrap();
+        let best_bid = rendered.find("99.00000000").unwrap();
+        assert!(worst_ask < best_ask && best_ask < best_bid);
+    }
+}
@@ -0,0 +1,303 @@
+use std::{
+    str::FromStr,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_stream::stream;
+use futures::Stream;
+use thiserror::Error;
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
+    transport::{Certificate, Channel, ClientTlsConfig, Endpoint},
+    Code, Request, Status,
+};
+
+use crate::exchanges::{status::ReconnectPolicy, ExchangeType};
+use crate::orderbook::{
+    fees::BookOrdering,
+    levels::{AskLevel, BidLevel},
+    summary::BookStatistics,
+};
+use crate::server::v1::rpc::{
+    self, book_update::Update, orderbook_service_client::OrderbookServiceClient, BookSnapshot, Level,
+    SubscribeBookRequest,
+};
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("invalid server address: {0}")]
+    InvalidAddress(String),
+    #[error("the API key contains characters that cannot be sent as metadata")]
+    InvalidApiKey,
+    #[error("could not connect to the server: {0}")]
+    Transport(#[from] tonic::transport::Error),
+    #[error("the server refused the subscription: {0}")]
+    Status(Box<Status>),
+    #[error("the server sent a level from an unknown exchange {0}")]
+    UnknownExchange(String),
+}
+
+impl From<Status> for ClientError {
+    fn from(status: Status) -> Self {
+        Self::Status(Box::new(status))
+    }
+}
+
+/// A merged book received from the server, ordered best deal first.
+#[derive(Debug, Clone)]
+pub struct Summary {
+    pub symbol: String,
+    /// Increments with every update of the merged book on the server, a gap means updates were dropped
+    pub sequence: u64,
+    pub timestamp: SystemTime,
+    pub bids: Vec<BidLevel>,
+    pub asks: Vec<AskLevel>,
+    pub statistics: BookStatistics,
+}
+
+fn exchange(level: &Level) -> Result<ExchangeType, ClientError> {
+    ExchangeType::from_str(&level.exchange)
+        .map_err(|_| ClientError::UnknownExchange(level.exchange.clone()))
+}
+
+impl TryFrom<BookSnapshot> for Summary {
+    type Error = ClientError;
+
+    fn try_from(snapshot: BookSnapshot) -> Result<Self, Self::Error> {
+        let bids = snapshot
+            .bids
+            .iter()
+            .map(|level| {
+                Ok(BidLevel {
+                    price: level.price,
+                    amount: level.amount,
+                    exchange: exchange(level)?,
+                })
+            })
+            .collect::<Result<Vec<_>, ClientError>>()?;
+        let asks = snapshot
+            .asks
+            .iter()
+            .map(|level| {
+                Ok(AskLevel {
+                    price: level.price,
+                    amount: level.amount,
+                    exchange: exchange(level)?,
+                })
+            })
+            .collect::<Result<Vec<_>, ClientError>>()?;
+        Ok(Self {
+            symbol: snapshot.symbol,
+            sequence: snapshot.sequence,
+            timestamp: UNIX_EPOCH + Duration::from_millis(snapshot.timestamp_ms),
+            statistics: BookStatistics::new(&bids, &asks),
+            bids,
+            asks,
+        })
+    }
+}
+
+/// Attaches the API key, if any, to every request.
+#[derive(Clone)]
+pub struct ApiKeyInterceptor {
+    key: Option<MetadataValue<Ascii>>,
+}
+
+impl Interceptor for ApiKeyInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(key) = &self.key {
+            request.metadata_mut().insert("x-api-key", key.clone());
+        }
+        Ok(request)
+    }
+}
+
+type ServiceClient = OrderbookServiceClient<InterceptedService<Channel, ApiKeyInterceptor>>;
+
+/// Whether a subscription that failed with `status` may succeed if it is retried.
+fn retryable(status: &Status) -> bool {
+    !matches!(
+        status.code(),
+        Code::InvalidArgument
+            | Code::NotFound
+            | Code::PermissionDenied
+            | Code::Unauthenticated
+            | Code::Unimplemented
+    )
+}
+
+/// A client of the `orderbook.v1` API that resubscribes whenever its stream is interrupted.
+#[derive(Clone)]
+pub struct OrderbookClient {
+    endpoint: String,
+    api_key: Option<String>,
+    tls_ca: Option<Vec<u8>>,
+    reconnect: ReconnectPolicy,
+    ordering: BookOrdering,
+}
+
+impl OrderbookClient {
+    /// A client of the server at `endpoint`, such as `http://localhost:50051`.
+    pub fn new<S: Into<String>>(endpoint: S) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            api_key: None,
+            tls_ca: None,
+            reconnect: ReconnectPolicy::default(),
+            ordering: BookOrdering::default(),
+        }
+    }
+
+    pub fn with_api_key<S: Into<String>>(mut self, key: S) -> Self {
+        self.api_key = Some(key.into());
+        self
+    }
+
+    /// Verifies the server's certificate against a PEM certificate authority, for `https` endpoints.
+    pub fn with_tls_ca(mut self, pem: Vec<u8>) -> Self {
+        self.tls_ca = Some(pem);
+        self
+    }
+
+    /// Governs how many consecutive failures to resubscribe are tolerated, and how long to wait between them.
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    /// Asks the server to rank levels by their price after each venue's taker fee, rather than the quoted price.
+    pub fn with_ordering(mut self, ordering: BookOrdering) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    async fn connect(&self) -> Result<ServiceClient, ClientError> {
+        let mut endpoint = Endpoint::from_shared(self.endpoint.clone())
+            .map_err(|_| ClientError::InvalidAddress(self.endpoint.clone()))?;
+        if self.endpoint.starts_with("https") {
+            let mut tls = ClientTlsConfig::new();
+            if let Some(ca) = &self.tls_ca {
+                tls = tls.ca_certificate(Certificate::from_pem(ca));
+            }
+            endpoint = endpoint.tls_config(tls)?;
+        }
+        let key = match &self.api_key {
+            Some(key) => Some(key.parse().map_err(|_| ClientError::InvalidApiKey)?),
+            None => None,
+        };
+        let channel = endpoint.connect().await?;
+        Ok(OrderbookServiceClient::with_interceptor(channel, ApiKeyInterceptor { key }))
+    }
+
+    /// Streams the merged book of `symbol`, or the server's default symbol, with at most `depth` levels a side.
+    ///
+    /// Interrupted streams are resubscribed to according to the reconnect policy. The stream ends with
+    /// an error once the server refuses the subscription or the policy gives up.
+    pub fn subscribe(
+        &self,
+        symbol: Option<String>,
+        depth: u32,
+    ) -> impl Stream<Item = Result<Summary, ClientError>> {
+        let client = self.clone();
+        let request = SubscribeBookRequest {
+            symbol: symbol.unwrap_or_default(),
+            depth,
+            deltas: false,
+            queue: None,
+            ordering: match self.ordering {
+                BookOrdering::Raw => rpc::BookOrdering::Raw,
+                BookOrdering::FeeAdjusted => rpc::BookOrdering::FeeAdjusted,
+            } as i32,
+        };
+        stream! {
+            let policy = client.reconnect;
+            let mut backoff = policy.initial_backoff;
+            let mut failures = 0;
+            loop {
+                let error = match client.connect().await {
+                    Ok(mut service) => match service.subscribe_book(request.clone()).await {
+                        Ok(response) => {
+                            let mut updates = response.into_inner();
+                            loop {
+                                match updates.message().await {
+                                    Ok(Some(update)) => {
+                                        // A delivered update shows the connection is healthy again
+                                        failures = 0;
+                                        backoff = policy.initial_backoff;
+                                        if let Some(Update::Snapshot(snapshot)) = update.update {
+                                            yield Summary::try_from(snapshot);
+                                        }
+                                    }
+                                    Ok(None) => break Status::unavailable("the stream ended").into(),
+                                    Err(status) => break status.into(),
+                                }
+                            }
+                        }
+                        Err(status) => status.into(),
+                    },
+                    Err(e) => e,
+                };
+                let give_up = match &error {
+                    ClientError::Status(status) => !retryable(status),
+                    ClientError::Transport(_) => false,
+                    _ => true,
+                };
+                failures += 1;
+                if give_up || failures > policy.max_attempts {
+                    yield Err(error);
+                    break;
+                }
+                warn!("subscription interrupted, resubscribing in {backoff:?}: {error}");
+                tokio::time::sleep(backoff).await;
+                backoff = (backoff * 2).min(policy.max_backoff);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::Status;
+
+    use crate::exchanges::ExchangeType;
+    use crate::server::v1::rpc::{BookSnapshot, Level};
+
+    use super::{retryable, Summary};
+
+    #[test]
+    fn test_summary_from_snapshot() {
+        let snapshot = BookSnapshot {
+            symbol: String::from("ethbtc"),
+            sequence: 4,
+            timestamp_ms: 1_000,
+            bids: vec![Level {
+                exchange: String::from("Binance"),
+                price: 99.0,
+                amount: 1.0,
+                delayed: false,
+            }],
+            asks: vec![Level {
+                exchange: String::from("Bitstamp"),
+                price: 101.0,
+                amount: 1.0,
+                delayed: false,
+            }],
+            statistics: None,
+        };
+        let summary = Summary::try_from(snapshot.clone()).unwrap();
+        assert_eq!(summary.bids[0].exchange, ExchangeType::Binance);
+        assert_eq!(summary.asks[0].exchange, ExchangeType::Bitstamp);
+        assert_eq!(summary.statistics.spread, Some(2.0));
+
+        let mut unknown = snapshot;
+        unknown.asks[0].exchange = String::from("Kraken");
+        assert!(Summary::try_from(unknown).is_err());
+    }
+
+    #[test]
+    fn test_retryable() {
+        assert!(retryable(&Status::unavailable("server is shutting down")));
+        assert!(!retryable(&Status::not_found("unknown symbol")));
+    }
+}
@@ -0,0 +1,647 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ffi::OsStr,
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    cli::Args,
+    exchanges::{self, status::ReconnectPolicy, ExchangeType},
+    hub::SymbolOverride,
+    orderbook::fees::{self, FeeSchedule, Fees},
+    queue::OverflowPolicy,
+    server::{auth::Authenticator, DEFAULT_QUEUE_CAPACITY},
+};
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("could not read {}: {source}", .path.display())]
+    Unreadable { path: PathBuf, source: io::Error },
+    #[error("{} is not a .toml, .yaml or .yml file", .0.display())]
+    UnknownFormat(PathBuf),
+    #[error("could not parse {}: {reason}", .path.display())]
+    Malformed { path: PathBuf, reason: String },
+    #[error("the configuration is invalid:{}", bullets(.0))]
+    Invalid(Vec<String>),
+}
+
+fn bullets(problems: &[String]) -> String {
+    problems.iter().map(|problem| format!("\n  - {problem}")).collect()
+}
+
+/// The server's configuration as written in a TOML or YAML file, every value optional so that flags can supply it.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ConfigFile {
+    /// Depth of the books of symbols that do not set their own
+    pub max_depth: Option<usize>,
+    /// Venues of symbols that do not list their own
+    pub exchanges: Vec<String>,
+    pub listeners: Listeners,
+    /// Symbols to construct orderbooks for, the first is streamed to clients that do not choose one
+    pub symbols: Vec<SymbolEntry>,
+    /// The endpoint and fees of each venue, by name
+    pub venues: BTreeMap<String, VenueEntry>,
+    pub reconnect: ReconnectEntry,
+    pub throttle: Throttle,
+    pub auth: Auth,
+    /// Problems found before validating, such as keys that are not recognised
+    #[serde(skip)]
+    problems: Vec<String>,
+}
+
+/// Addresses the server listens on, the websocket and REST endpoints being disabled without one.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Listeners {
+    pub grpc: Option<String>,
+    pub websocket: Option<String>,
+    pub http: Option<String>,
+}
+
+/// A symbol served, merged from the default venues to the default depth unless it sets its own.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct SymbolEntry {
+    pub symbol: String,
+    pub exchanges: Option<Vec<String>>,
+    pub max_depth: Option<usize>,
+}
+
+/// Where a venue is connected to and what it charges.
+#[derive(Debug, Default, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct VenueEntry {
+    /// Websocket URL used in place of the venue's public endpoint, for Binance the base stream names are appended to
+    pub url: Option<String>,
+    pub taker_bps: Option<f64>,
+    pub maker_bps: Option<f64>,
+}
+
+/// How dropped exchange feeds are reconnected, `ReconnectPolicy::default()` filling in what is absent.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ReconnectEntry {
+    pub max_attempts: Option<u32>,
+    pub initial_backoff_ms: Option<u64>,
+    pub max_backoff_ms: Option<u64>,
+}
+
+/// How far each client may fall behind, and what happens once it does.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Throttle {
+    pub queue_capacity: Option<usize>,
+    pub overflow_policy: Option<String>,
+}
+
+/// TLS and the API keys clients must present, the server is open to anyone without keys.
+#[derive(Debug, Default, Clone, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Auth {
+    pub api_keys: Option<PathBuf>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_client_ca: Option<PathBuf>,
+}
+
+/// The server's configuration once validated, with defaults filled in.
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub grpc: SocketAddr,
+    pub websocket: Option<SocketAddr>,
+    pub http: Option<SocketAddr>,
+    /// Symbols served, the first is the default
+    pub symbols: Vec<String>,
+    pub exchanges: Vec<ExchangeType>,
+    pub max_depth: usize,
+    pub overrides: HashMap<String, SymbolOverride>,
+    /// URLs connected to in place of the venues' public endpoints
+    pub endpoints: HashMap<ExchangeType, String>,
+    pub fees: FeeSchedule,
+    pub reconnect: ReconnectPolicy,
+    pub queue_capacity: usize,
+    pub overflow_policy: OverflowPolicy,
+    pub api_keys: Option<PathBuf>,
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    pub tls_client_ca: Option<PathBuf>,
+}
+
+impl ConfigFile {
+    /// Reads a TOML or YAML file, told apart by its extension, taking the paths in it as relative to the file.
+    ///
+    /// Keys that are not recognised are reported by `validate` along with every other problem.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path).map_err(|source| ConfigError::Unreadable {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let malformed = |reason: String| ConfigError::Malformed {
+            path: path.to_path_buf(),
+            reason,
+        };
+        let mut unknown = Vec::new();
+        let mut file: Self = match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => serde_ignored::deserialize(toml::Deserializer::new(&text), |key| {
+                unknown.push(key.to_string())
+            })
+            .map_err(|e| malformed(e.to_string()))?,
+            Some("yaml" | "yml") => serde_ignored::deserialize(serde_yaml::Deserializer::from_str(&text), |key| {
+                unknown.push(key.to_string())
+            })
+            .map_err(|e| malformed(e.to_string()))?,
+            _ => return Err(ConfigError::UnknownFormat(path.to_path_buf())),
+        };
+        file.problems = unknown.into_iter().map(|key| format!("{key}: unknown key")).collect();
+
+        let directory = path.parent().unwrap_or(Path::new(""));
+        let auth = &mut file.auth;
+        for path in [&mut auth.api_keys, &mut auth.tls_cert, &mut auth.tls_key, &mut auth.tls_client_ca]
+            .into_iter()
+            .flatten()
+        {
+            *path = directory.join(&path);
+        }
+        Ok(file)
+    }
+
+    /// Replaces the values of the file with the flags given on the command line.
+    ///
+    /// Symbols passed with `--symbol` replace those of the file, keeping the venues and depth the file gives them.
+    pub fn override_with(mut self, args: &Args) -> Self {
+        self.max_depth = args.max_depth.or(self.max_depth);
+        if !args.exchanges.is_empty() {
+            self.exchanges = args.exchanges.clone();
+        }
+        if !args.symbols.is_empty() {
+            let symbols = args
+                .symbols
+                .iter()
+                .map(|symbol| {
+                    let entry = self.symbols.iter().find(|entry| &entry.symbol == symbol);
+                    entry.cloned().unwrap_or_else(|| SymbolEntry {
+                        symbol: symbol.clone(),
+                        ..SymbolEntry::default()
+                    })
+                })
+                .collect();
+            self.symbols = symbols;
+        }
+
+        let listeners = &mut self.listeners;
+        if let Some(port) = &args.port {
+            listeners.grpc = Some(format!("[::0]:{port}"));
+        }
+        if let Some(port) = args.websocket_port {
+            listeners.websocket = Some(format!("[::0]:{port}"));
+        }
+        if let Some(port) = args.http_port {
+            listeners.http = Some(format!("[::0]:{port}"));
+        }
+
+        for entry in &args.taker_fees {
+            match fees::parse_bps(entry) {
+                Ok((exchange, bps)) => self.venue_mut(exchange).taker_bps = Some(bps),
+                Err(e) => self.problems.push(format!("--taker-fees: {e}")),
+            }
+        }
+        for entry in &args.maker_fees {
+            match fees::parse_bps(entry) {
+                Ok((exchange, bps)) => self.venue_mut(exchange).maker_bps = Some(bps),
+                Err(e) => self.problems.push(format!("--maker-fees: {e}")),
+            }
+        }
+
+        let throttle = &mut self.throttle;
+        throttle.queue_capacity = args.queue_capacity.or(throttle.queue_capacity);
+        throttle.overflow_policy = args.overflow_policy.clone().or(throttle.overflow_policy.take());
+
+        let auth = &mut self.auth;
+        auth.api_keys = args.api_keys.clone().or(auth.api_keys.take());
+        auth.tls_cert = args.tls_cert.clone().or(auth.tls_cert.take());
+        auth.tls_key = args.tls_key.clone().or(auth.tls_key.take());
+        auth.tls_client_ca = args.tls_client_ca.clone().or(auth.tls_client_ca.take());
+        self
+    }
+
+    /// The entry of a venue, however its name is written in the file.
+    fn venue_mut(&mut self, exchange: ExchangeType) -> &mut VenueEntry {
+        let name = self
+            .venues
+            .keys()
+            .find(|name| ExchangeType::from_str(name).is_ok_and(|named| named == exchange))
+            .cloned()
+            .unwrap_or_else(|| exchange.to_string().to_lowercase());
+        self.venues.entry(name).or_default()
+    }
+
+    /// Checks every value, reporting all of the problems found at once.
+    pub fn validate(mut self) -> Result<ServerConfig, ConfigError> {
+        let mut problems = std::mem::take(&mut self.problems);
+
+        let max_depth = match self.max_depth {
+            Some(0) => {
+                problems.push(String::from("max_depth: must be greater than zero"));
+                None
+            }
+            Some(max_depth) => Some(max_depth),
+            None => {
+                problems.push(String::from("max_depth: missing, set it in the file or with --max-depth"));
+                None
+            }
+        };
+        let exchanges = parse_venues("exchanges", &self.exchanges, &mut problems);
+
+        let grpc = parse_listener("listeners.grpc", self.listeners.grpc.as_deref(), &mut problems);
+        if self.listeners.grpc.is_none() {
+            problems.push(String::from("listeners.grpc: missing, set it in the file or with --port"));
+        }
+        let websocket = parse_listener("listeners.websocket", self.listeners.websocket.as_deref(), &mut problems);
+        let http = parse_listener("listeners.http", self.listeners.http.as_deref(), &mut problems);
+        let listeners = [("grpc", grpc), ("websocket", websocket), ("http", http)];
+        for (i, (name, address)) in listeners.iter().enumerate() {
+            let earlier = listeners[..i]
+                .iter()
+                .find(|(_, other)| address.is_some() && other == address);
+            if let (Some(address), Some((other, _))) = (address, earlier) {
+                problems.push(format!("listeners.{name}: {address} is already used by listeners.{other}"));
+            }
+        }
+
+        if self.symbols.is_empty() {
+            problems.push(String::from("symbols: missing, set them in the file or with --symbol"));
+        }
+        let mut symbols: Vec<String> = Vec::with_capacity(self.symbols.len());
+        let mut overrides = HashMap::new();
+        // Whether a symbol is left to the venues of `exchanges`
+        let mut uses_default_exchanges = false;
+        for (i, entry) in self.symbols.iter().enumerate() {
+            let key = format!("symbols.{i}");
+            if entry.symbol.is_empty() {
+                problems.push(format!("{key}.symbol: missing"));
+                continue;
+            }
+            if symbols.contains(&entry.symbol) {
+                problems.push(format!("{key}.symbol: {} is listed more than once", entry.symbol));
+                continue;
+            }
+            if entry.max_depth == Some(0) {
+                problems.push(format!("{key}.max_depth: must be greater than zero"));
+            }
+            match &entry.exchanges {
+                Some(names) if names.is_empty() => {
+                    problems.push(format!("{key}.exchanges: missing, list a venue or leave it out to use exchanges"));
+                }
+                Some(_) => {}
+                None => uses_default_exchanges = true,
+            }
+            let exchanges = entry
+                .exchanges
+                .as_ref()
+                .map(|names| parse_venues(&format!("{key}.exchanges"), names, &mut problems));
+            if exchanges.is_some() || entry.max_depth.is_some() {
+                overrides.insert(
+                    entry.symbol.clone(),
+                    SymbolOverride {
+                        exchanges,
+                        max_depth: entry.max_depth,
+                    },
+                );
+            }
+            symbols.push(entry.symbol.clone());
+        }
+        if uses_default_exchanges && self.exchanges.is_empty() {
+            problems.push(String::from("exchanges: missing, set them in the file or with --exchanges"));
+        }
+
+        let mut endpoints = HashMap::new();
+        let mut fees = FeeSchedule::default();
+        for (name, venue) in &self.venues {
+            let key = format!("venues.{name}");
+            let exchange = match exchanges::parse_venue(name) {
+                Ok(exchange) if exchanges::exchange(exchange).is_some() => exchange,
+                _ => {
+                    problems.push(format!("{key}: {name} is not a venue that can be connected to"));
+                    continue;
+                }
+            };
+            if let Some(url) = &venue.url {
+                if url.starts_with("ws://") || url.starts_with("wss://") {
+                    endpoints.insert(exchange, url.clone());
+                } else {
+                    problems.push(format!("{key}.url: {url} is not a ws:// or wss:// URL"));
+                }
+            }
+            let mut venue_fees = Fees::default();
+            let rates = [
+                ("taker_bps", venue.taker_bps, &mut venue_fees.taker),
+                ("maker_bps", venue.maker_bps, &mut venue_fees.maker),
+            ];
+            for (field, bps, fee) in rates {
+                match bps {
+                    Some(bps) if bps.is_finite() && bps >= 0.0 => *fee = bps / 10_000.0,
+                    Some(bps) => problems.push(format!("{key}.{field}: {bps} is not a fee in basis points")),
+                    None => {}
+                }
+            }
+            if venue.taker_bps.is_some() || venue.maker_bps.is_some() {
+                fees = fees.with_fees(exchange, venue_fees);
+            }
+        }
+
+        let default = ReconnectPolicy::default();
+        let reconnect = ReconnectPolicy {
+            max_attempts: self.reconnect.max_attempts.unwrap_or(default.max_attempts),
+            initial_backoff: self
+                .reconnect
+                .initial_backoff_ms
+                .map_or(default.initial_backoff, Duration::from_millis),
+            max_backoff: self
+                .reconnect
+                .max_backoff_ms
+                .map_or(default.max_backoff, Duration::from_millis),
+        };
+        if reconnect.initial_backoff.is_zero() {
+            problems.push(String::from("reconnect.initial_backoff_ms: must be greater than zero"));
+        } else if reconnect.initial_backoff > reconnect.max_backoff {
+            problems.push(String::from("reconnect.max_backoff_ms: must be at least the initial backoff"));
+        }
+
+        let queue_capacity = self.throttle.queue_capacity.unwrap_or(DEFAULT_QUEUE_CAPACITY);
+        if queue_capacity == 0 {
+            problems.push(String::from("throttle.queue_capacity: must be greater than zero"));
+        }
+        let overflow_policy = match self.throttle.overflow_policy.as_deref().map(OverflowPolicy::from_str) {
+            Some(Ok(policy)) => policy,
+            Some(Err(e)) => {
+                problems.push(format!("throttle.overflow_policy: {e}"));
+                OverflowPolicy::default()
+            }
+            None => OverflowPolicy::default(),
+        };
+
+        let auth = self.auth;
+        match (&auth.tls_cert, &auth.tls_key) {
+            (Some(_), None) => problems.push(String::from("auth.tls_key: missing, the certificate needs its key")),
+            (None, Some(_)) => problems.push(String::from("auth.tls_cert: missing, the key needs its certificate")),
+            _ => {}
+        }
+        if auth.tls_client_ca.is_some() && auth.tls_cert.is_none() {
+            problems.push(String::from("auth.tls_client_ca: client certificates require tls_cert"));
+        }
+        let files = [
+            ("tls_cert", &auth.tls_cert),
+            ("tls_key", &auth.tls_key),
+            ("tls_client_ca", &auth.tls_client_ca),
+        ];
+        for (field, path) in files {
+            if let Some(path) = path.as_ref().filter(|path| !path.is_file()) {
+                problems.push(format!("auth.{field}: {} does not exist", path.display()));
+            }
+        }
+        if let Some(Err(e)) = auth.api_keys.as_ref().map(Authenticator::from_file) {
+            problems.push(format!("auth.api_keys: {e}"));
+        }
+
+        match (grpc, max_depth) {
+            (Some(grpc), Some(max_depth)) if problems.is_empty() => Ok(ServerConfig {
+                grpc,
+                websocket,
+                http,
+                symbols,
+                exchanges,
+                max_depth,
+                overrides,
+                endpoints,
+                fees,
+                reconnect,
+                queue_capacity,
+                overflow_policy,
+                api_keys: auth.api_keys,
+                tls_cert: auth.tls_cert,
+                tls_key: auth.tls_key,
+                tls_client_ca: auth.tls_client_ca,
+            }),
+            _ => Err(ConfigError::Invalid(problems)),
+        }
+    }
+}
+
+/// The venues named at `key`, each a problem unless it can be connected to.
+fn parse_venues(key: &str, names: &[String], problems: &mut Vec<String>) -> Vec<ExchangeType> {
+    let mut venues = Vec::with_capacity(names.len());
+    for name in names {
+        match exchanges::parse_venue(name) {
+            Ok(venue) if exchanges::exchange(venue).is_some() => {
+                if !venues.contains(&venue) {
+                    venues.push(venue);
+                }
+            }
+            _ => problems.push(format!("{key}: {name} is not a venue that can be connected to")),
+        }
+    }
+    venues
+}
+
+fn parse_listener(key: &str, address: Option<&str>, problems: &mut Vec<String>) -> Option<SocketAddr> {
+    let address = address?;
+    match SocketAddr::from_str(address) {
+        Ok(address) => Some(address),
+        Err(_) => {
+            problems.push(format!("{key}: {address} is not an address such as [::0]:50051"));
+            None
+        }
+    }
+}
+
+/// The configuration from `--config`, if given, overridden by the other flags.
+pub fn from_args(args: &Args) -> Result<ServerConfig, ConfigError> {
+    let file = match &args.config {
+        Some(path) => ConfigFile::open(path)?,
+        None => ConfigFile::default(),
+    };
+    file.override_with(args).validate()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf};
+
+    use clap::Parser;
+
+    use crate::{cli::Args, exchanges::ExchangeType, queue::OverflowPolicy};
+
+    use super::{from_args, ConfigError, ConfigFile};
+
+    const TOML: &str = r#"
+max_depth = 10
+exchanges = ["binance", "bitstamp"]
+
+[listeners]
+grpc = "[::0]:50051"
+http = "[::0]:8081"
+
+[[symbols]]
+symbol = "ethbtc"
+
+[[symbols]]
+symbol = "btcusdt"
+exchanges = ["binance"]
+max_depth = 20
+
+[venues.binance]
+url = "wss://testnet.binance.vision/ws/"
+taker_bps = 10
+
+[throttle]
+overflow_policy = "conflate"
+"#;
+
+    const YAML: &str = r#"
+max_depth: 10
+exchanges: [binance, bitstamp]
+listeners:
+  grpc: "[::0]:50051"
+  http: "[::0]:8081"
+symbols:
+  - symbol: ethbtc
+  - symbol: btcusdt
+    exchanges: [binance]
+    max_depth: 20
+venues:
+  binance:
+    url: wss://testnet.binance.vision/ws/
+    taker_bps: 10
+throttle:
+  overflow_policy: conflate
+"#;
+
+    fn write(name: &str, contents: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{name}", std::process::id()));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_formats() {
+        let toml = write("server.toml", TOML);
+        let yaml = write("server.yaml", YAML);
+        assert_eq!(ConfigFile::open(&toml).unwrap(), ConfigFile::open(&yaml).unwrap());
+
+        let config = ConfigFile::open(&toml).unwrap().validate().unwrap();
+        assert_eq!(config.grpc.port(), 50051);
+        assert!(config.websocket.is_none());
+        assert_eq!(config.symbols, vec!["ethbtc", "btcusdt"]);
+        assert_eq!(config.overrides["btcusdt"].exchanges, Some(vec![ExchangeType::Binance]));
+        assert!(!config.overrides.contains_key("ethbtc"));
+        assert_eq!(config.endpoints[&ExchangeType::Binance], "wss://testnet.binance.vision/ws/");
+        assert_eq!(config.fees.taker(ExchangeType::Binance), 0.001);
+        assert_eq!(config.overflow_policy, OverflowPolicy::Conflate);
+        fs::remove_file(toml).unwrap();
+        fs::remove_file(yaml).unwrap();
+    }
+
+    #[test]
+    fn test_override() {
+        let path = write("override.toml", TOML);
+        let args = Args::parse_from([
+            "orderbook",
+            "--config",
+            path.to_str().unwrap(),
+            "--port",
+            "6000",
+            "--symbol",
+            "btcusdt,ltcbtc",
+            "--taker-fees",
+            "binance=5",
+        ]);
+        let config = from_args(&args).unwrap();
+        assert_eq!(config.grpc.port(), 6000);
+        assert_eq!(config.http.unwrap().port(), 8081);
+        assert_eq!(config.symbols, vec!["btcusdt", "ltcbtc"]);
+        assert_eq!(config.overrides["btcusdt"].max_depth, Some(20));
+        assert_eq!(config.fees.taker(ExchangeType::Binance), 0.0005);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_missing_exchanges() {
+        let args = Args::parse_from(["orderbook", "--port", "6000", "--max-depth", "10", "--symbol", "ethbtc"]);
+        let problems = match from_args(&args) {
+            Err(ConfigError::Invalid(problems)) => problems,
+            other => panic!("expected the configuration to be invalid, got {other:?}"),
+        };
+        assert_eq!(problems, ["exchanges: missing, set them in the file or with --exchanges"]);
+    }
+
+    #[test]
+    fn test_problems() {
+        let path = write(
+            "problems.toml",
+            r#"
+max_depth = 0
+exchanges = ["kraken", "synthetic"]
+
+[listeners]
+grpc = "[::0]:50051"
+websocket = "[::0]:50051"
+htpp = "[::0]:8081"
+
+[[symbols]]
+symbol = "ethbtc"
+max_depth = 0
+
+[[symbols]]
+symbol = "ethbtc"
+
+[[symbols]]
+symbol = "btcusdt"
+exchanges = []
+
+[venues.bitstamp]
+url = "https://www.bitstamp.net"
+maker_bps = -1
+
+[reconnect]
+initial_backoff_ms = 5000
+max_backoff_ms = 1000
+
+[auth]
+tls_key = "missing.key"
+"#,
+        );
+        let problems = match ConfigFile::open(&path).unwrap().validate() {
+            Err(ConfigError::Invalid(problems)) => problems,
+            other => panic!("expected the configuration to be invalid, got {other:?}"),
+        };
+        assert_eq!(
+            problems[..12],
+            [
+                "listeners.htpp: unknown key",
+                "max_depth: must be greater than zero",
+                "exchanges: kraken is not a venue that can be connected to",
+                "exchanges: synthetic is not a venue that can be connected to",
+                "listeners.websocket: [::]:50051 is already used by listeners.grpc",
+                "symbols.0.max_depth: must be greater than zero",
+                "symbols.1.symbol: ethbtc is listed more than once",
+                "symbols.2.exchanges: missing, list a venue or leave it out to use exchanges",
+                "venues.bitstamp.url: https://www.bitstamp.net is not a ws:// or wss:// URL",
+                "venues.bitstamp.maker_bps: -1 is not a fee in basis points",
+                "reconnect.max_backoff_ms: must be at least the initial backoff",
+                "auth.tls_cert: missing, the key needs its certificate",
+            ]
+        );
+        assert!(problems[12].ends_with("missing.key does not exist"));
+        assert_eq!(problems.len(), 13);
+        fs::remove_file(path).unwrap();
+    }
+}
@@ -1,53 +1,238 @@
 use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
-use async_tungstenite::tokio::connect_async;
-use futures::stream::StreamExt;
+use async_tungstenite::{tokio::connect_async, tungstenite::Message};
+use futures::{stream::StreamExt, SinkExt};
+use serde::Deserialize;
 
-use super::{Exchange, ExchangeType, FeedSnapshot, SecureWebsocketReceiver, SnapshotStream};
+use super::{
+    endpoint,
+    record::{record, Channel},
+    trade_feed, Aggressor, Exchange, ExchangeType, FeedSnapshot, SecureWebsocketReceiver, SnapshotStream,
+    FeedGuard, Trade, TradeStream, FEED_QUEUE_CAPACITY,
+};
+use crate::queue::{self, OverflowPolicy};
 
 static EXCHANGE_URL: &str = "wss://stream.binance.com:9443/ws/";
 
 pub(crate) struct Binance {}
 
+/// A message of the `<symbol>@depth<levels>` stream
+#[derive(Deserialize, Debug)]
+struct BinanceDepth {
+    #[serde(flatten)]
+    snapshot: FeedSnapshot,
+    /// Event time in milliseconds, which the spot partial depth streams leave out
+    #[serde(rename = "E")]
+    event_time: Option<u64>,
+}
+
+/// The fields that tell apart the messages of the book websocket
+#[derive(Deserialize, Debug)]
+struct BinanceHeader {
+    #[serde(rename = "e")]
+    event: Option<String>,
+    /// Event time in milliseconds
+    #[serde(rename = "E")]
+    event_time: Option<u64>,
+    /// Set on replies to requests such as `SUBSCRIBE`
+    id: Option<u64>,
+}
+
+/// A message of the book websocket, which also carries the `<symbol>@trade` stream as the partial depth stream is not
+/// stamped with when it was published.
+#[derive(Debug)]
+pub(crate) enum BookMessage {
+    Depth(FeedSnapshot),
+    /// When a trade was published and received, a sample of the venue's latency
+    Latency { sent_at: SystemTime, received_at: SystemTime },
+    /// A reply to the trade subscription
+    Reply,
+}
+
+pub(crate) fn parse_message(text: &str, received_at: SystemTime) -> Result<BookMessage, Box<dyn Error + Send + Sync>> {
+    let header: BinanceHeader = serde_json::from_str(text)?;
+    match header {
+        BinanceHeader { id: Some(_), .. } => Ok(BookMessage::Reply),
+        BinanceHeader {
+            event: Some(event),
+            event_time: Some(ms),
+            ..
+        } if event == "trade" => Ok(BookMessage::Latency {
+            sent_at: UNIX_EPOCH + Duration::from_millis(ms),
+            received_at,
+        }),
+        _ => parse_depth(text, received_at).map(BookMessage::Depth),
+    }
+}
+
+pub(crate) fn parse_depth(text: &str, received_at: SystemTime) -> Result<FeedSnapshot, Box<dyn Error + Send + Sync>> {
+    let depth: BinanceDepth = serde_json::from_str(text)?;
+    Ok(FeedSnapshot {
+        event_time: depth.event_time.map(|ms| UNIX_EPOCH + Duration::from_millis(ms)),
+        received_at: Some(received_at),
+        ..depth.snapshot
+    })
+}
+
+/// A message of the `<symbol>@trade` stream
+#[derive(Deserialize, Debug)]
+struct BinanceTrade {
+    #[serde(rename = "t")]
+    id: u64,
+    #[serde(rename = "p")]
+    price: String,
+    #[serde(rename = "q")]
+    quantity: String,
+    /// Trade time in milliseconds
+    #[serde(rename = "T")]
+    trade_time: u64,
+    #[serde(rename = "m")]
+    buyer_is_maker: bool,
+}
+
+pub(crate) fn parse_trade(symbol: &str, text: &str) -> Result<Trade, Box<dyn Error + Send + Sync>> {
+    let trade: BinanceTrade = serde_json::from_str(text)?;
+    Ok(Trade {
+        exchange: ExchangeType::Binance,
+        symbol: symbol.to_string(),
+        id: trade.id.to_string(),
+        price: trade.price.parse()?,
+        amount: trade.quantity.parse()?,
+        // The buyer resting on the book means the seller crossed the spread
+        aggressor: if trade.buyer_is_maker { Aggressor::Sell } else { Aggressor::Buy },
+        traded_at: UNIX_EPOCH + Duration::from_millis(trade.trade_time),
+        received_at: SystemTime::now(),
+    })
+}
+
 #[async_trait]
 impl Exchange for Binance {
     async fn connect(&self, symbol: String, max_depth: usize) -> Result<SnapshotStream, Box<dyn Error>> {
-        let (ws_stream, _) = connect_async(format!("{EXCHANGE_URL}{symbol}@depth{max_depth}@100ms")).await?;
-
-        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<
-            Result<FeedSnapshot, Box<dyn Error + Sync + Send>>,
-        >();
-        let rx = Box::pin(async_stream::stream! {
-              while let Some(item) = rx.recv().await {
-                  yield item;
-              }
-        }) as SnapshotStream;
-        let (_, mut ws_receiver) = ws_stream.split();
+        let url = endpoint(ExchangeType::Binance, EXCHANGE_URL);
+        let (ws_stream, _) = connect_async(format!("{url}{symbol}@depth{max_depth}@100ms")).await?;
+        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
+        // Trades carry the event time the depth stream lacks, so they are read for the venue's latency
+        let subscribe = format!(r#"{{"method": "SUBSCRIBE", "params": ["{symbol}@trade"], "id": 1}}"#);
+        ws_sender.send(Message::Text(subscribe)).await?;
+
+        let (tx, rx) = queue::bounded::<Result<FeedSnapshot, Box<dyn Error + Sync + Send>>>(
+            FEED_QUEUE_CAPACITY,
+            OverflowPolicy::Conflate,
+        );
+        let rx = Box::pin(rx) as SnapshotStream;
+        let feed = FeedGuard::open();
         tokio::spawn(async move {
+            let _feed = feed;
+            let symbol = &symbol;
+            let mut latency_samples = Vec::new();
             loop {
-                let channel_result = async move |ws: &mut SecureWebsocketReceiver| -> Result<
-                    FeedSnapshot,
-                    Box<dyn Error + Send + Sync>,
-                > {
-                    let msg = ws.next().await.ok_or("didn't receive anything")??;
-                    let binance_data: FeedSnapshot =
-                        serde_json::from_str(msg.to_string().as_str())?;
-                    Ok(binance_data)
-                }(&mut ws_receiver)
-                .await;
-
-                match channel_result {
-                    Err(e) => tx.send(Err(e)).unwrap(),
-                    Ok(snapshot) => tx.send(Ok(snapshot)).unwrap(),
+                let channel_result = tokio::select! {
+                    // The orderbook is no longer listening
+                    _ = tx.closed() => break,
+                    result = async move |ws: &mut SecureWebsocketReceiver| -> Result<
+                        Option<BookMessage>,
+                        Box<dyn Error + Send + Sync>,
+                    > {
+                        let msg = match ws.next().await {
+                            Some(msg) => msg?,
+                            None => return Ok(None),
+                        };
+                        let text = msg.to_string();
+                        if msg.is_text() {
+                            record(ExchangeType::Binance, symbol, Channel::Book, &text);
+                        }
+                        Ok(Some(parse_message(&text, SystemTime::now())?))
+                    }(&mut ws_receiver) => result,
+                };
+
+                let pushed = match channel_result {
+                    Err(e) => tx.push(Err(e)),
+                    Ok(Some(BookMessage::Depth(snapshot))) => tx.push(Ok(FeedSnapshot {
+                        latency_samples: std::mem::take(&mut latency_samples),
+                        ..snapshot
+                    })),
+                    Ok(Some(BookMessage::Latency { sent_at, received_at })) => {
+                        latency_samples.push((sent_at, received_at));
+                        continue;
+                    }
+                    Ok(Some(BookMessage::Reply)) => continue,
+                    // The websocket was closed, ending the stream
+                    Ok(None) => break,
                 };
+                if pushed.is_err() {
+                    // The orderbook is no longer listening
+                    break;
+                }
+            }
+            // Close the websocket rather than leaving the venue to time the connection out
+            if let Err(e) = ws_sender.close().await {
+                debug!("{} websocket did not close cleanly: {e}", ExchangeType::Binance.to_string());
             }
         });
 
         Ok(rx)
     }
 
+    async fn connect_trades(&self, symbol: String) -> Result<TradeStream, Box<dyn Error>> {
+        let url = endpoint(ExchangeType::Binance, EXCHANGE_URL);
+        let (ws_stream, _) = connect_async(format!("{url}{symbol}@trade")).await?;
+        Ok(trade_feed(ExchangeType::Binance, symbol.clone(), ws_stream, move |text| {
+            parse_trade(&symbol, text).map(Some)
+        }))
+    }
+
     fn name(&self) -> ExchangeType {
         ExchangeType::Binance
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::exchanges::Aggressor;
+
+    use super::{parse_depth, parse_message, parse_trade, BookMessage};
+
+    #[test]
+    fn test_parse_depth() {
+        let text = r#"{"lastUpdateId":160,"bids":[["0.0024","10"]],"asks":[["0.0026","100"]]}"#;
+        let snapshot = parse_depth(text, UNIX_EPOCH).unwrap();
+        assert_eq!(snapshot.bids, vec![[0.0024, 10.0]]);
+        assert_eq!(snapshot.event_time, None);
+
+        let text = r#"{"E":1672515782136,"lastUpdateId":160,"bids":[],"asks":[]}"#;
+        let snapshot = parse_depth(text, UNIX_EPOCH).unwrap();
+        assert_eq!(snapshot.event_time, Some(UNIX_EPOCH + Duration::from_millis(1672515782136)));
+    }
+
+    #[test]
+    fn test_parse_message() {
+        // As sent on the book websocket once the trade stream is subscribed to
+        let text = r#"{"e":"trade","E":1672515782136,"s":"BNBBTC","t":12345,"p":"0.001","q":"100","T":1672515782136,"m":true,"M":true}"#;
+        let received_at = UNIX_EPOCH + Duration::from_millis(1672515782150);
+        match parse_message(text, received_at).unwrap() {
+            BookMessage::Latency { sent_at, received_at } => {
+                assert_eq!(received_at.duration_since(sent_at).unwrap(), Duration::from_millis(14));
+            }
+            message => panic!("expected a latency sample, got {message:?}"),
+        }
+
+        assert!(matches!(parse_message(r#"{"result":null,"id":1}"#, received_at).unwrap(), BookMessage::Reply));
+        let text = r#"{"lastUpdateId":160,"bids":[["0.0024","10"]],"asks":[["0.0026","100"]]}"#;
+        assert!(matches!(parse_message(text, received_at).unwrap(), BookMessage::Depth(_)));
+    }
+
+    #[test]
+    fn test_parse_trade() {
+        let text = r#"{"e":"trade","E":1672515782136,"s":"ETHBTC","t":12345,"p":"0.07","q":"1.5","b":88,"a":50,"T":1672515782134,"m":true,"M":true}"#;
+        let trade = parse_trade("ethbtc", text).unwrap();
+        assert_eq!(trade.id, "12345");
+        assert_eq!(trade.price, 0.07);
+        assert_eq!(trade.amount, 1.5);
+        assert_eq!(trade.aggressor, Aggressor::Sell);
+        assert_eq!(trade.traded_at, UNIX_EPOCH + Duration::from_millis(1672515782134));
+    }
+}
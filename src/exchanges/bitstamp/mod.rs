@@ -1,4 +1,5 @@
 use std::error::Error;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use async_trait::async_trait;
 use async_tungstenite::{tokio::connect_async, tungstenite::Message};
@@ -7,7 +8,13 @@ use futures::{stream::StreamExt, SinkExt};
 use serde::Deserialize;
 use thiserror::Error;
 
-use super::{Exchange, ExchangeType, FeedSnapshot, SecureWebsocketReceiver, SnapshotStream};
+use super::{
+    endpoint,
+    record::{record, Channel},
+    trade_feed, Aggressor, Exchange, ExchangeType, FeedSnapshot, SecureWebsocket, SecureWebsocketReceiver,
+    SnapshotStream, FeedGuard, Trade, TradeStream, FEED_QUEUE_CAPACITY,
+};
+use crate::queue::{self, OverflowPolicy};
 
 #[derive(Error, Debug)]
 enum BitstampError {
@@ -19,7 +26,15 @@ static EXCHANGE_URL: &str = "wss://ws.bitstamp.net";
 
 #[derive(Deserialize, Debug)]
 struct BitstampSnapshot {
-    pub data: FeedSnapshot,
+    pub data: BitstampBook,
+}
+
+#[derive(Deserialize, Debug)]
+struct BitstampBook {
+    #[serde(flatten)]
+    snapshot: FeedSnapshot,
+    /// Publication time in microseconds
+    microtimestamp: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -27,68 +42,177 @@ struct BitstampConnectResponse {
     event: String
 }
 
+/// A message of a `live_trades_<symbol>` channel, which also carries events other than trades
+#[derive(Deserialize, Debug)]
+struct BitstampTradeEvent {
+    event: String,
+    #[serde(default)]
+    data: serde_json::Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct BitstampTrade {
+    id: u64,
+    amount_str: String,
+    price_str: String,
+    /// 0 when the buyer crossed the spread, 1 when the seller did
+    #[serde(rename = "type")]
+    side: u8,
+    /// Trade time in microseconds
+    microtimestamp: String,
+}
+
+pub(crate) fn parse_trade(symbol: &str, text: &str) -> Result<Option<Trade>, Box<dyn Error + Send + Sync>> {
+    let event: BitstampTradeEvent = serde_json::from_str(text)?;
+    if event.event != "trade" {
+        return Ok(None);
+    }
+    let trade: BitstampTrade = serde_json::from_value(event.data)?;
+    Ok(Some(Trade {
+        exchange: ExchangeType::Bitstamp,
+        symbol: symbol.to_string(),
+        id: trade.id.to_string(),
+        price: trade.price_str.parse()?,
+        amount: trade.amount_str.parse()?,
+        aggressor: if trade.side == 0 { Aggressor::Buy } else { Aggressor::Sell },
+        traded_at: UNIX_EPOCH + Duration::from_micros(trade.microtimestamp.parse()?),
+        received_at: SystemTime::now(),
+    }))
+}
+
+pub(crate) fn parse_book(text: &str, received_at: SystemTime) -> Result<FeedSnapshot, Box<dyn Error + Send + Sync>> {
+    let book: BitstampSnapshot = serde_json::from_str(text)?;
+    let event_time = match book.data.microtimestamp {
+        Some(micros) => Some(UNIX_EPOCH + Duration::from_micros(micros.parse()?)),
+        None => None,
+    };
+    Ok(FeedSnapshot {
+        event_time,
+        received_at: Some(received_at),
+        ..book.data.snapshot
+    })
+}
+
+/// Connects to the websocket and subscribes to `channel`, waiting for the subscription to be confirmed.
+async fn subscribe(channel: &str) -> Result<SecureWebsocket, Box<dyn Error>> {
+    let (mut ws_stream, _) = connect_async(endpoint(ExchangeType::Bitstamp, EXCHANGE_URL)).await?;
+    ws_stream
+        .send(Message::text(format!(
+            "{{
+                \"event\": \"bts:subscribe\",
+                \"data\": {{
+                    \"channel\": \"{channel}\"
+                }}
+            }}"
+        )))
+        .await?;
+
+    // Confirm subscribed
+    let msg = futures::StreamExt::next(&mut ws_stream)
+        .await
+        .ok_or("didn't receive anything")??;
+    let data: BitstampConnectResponse = serde_json::from_str(msg.to_string().as_str())?;
+    if data.event.as_str() != "bts:subscription_succeeded" {
+        return Err(BitstampError::StreamFailed.into())
+    }
+    Ok(ws_stream)
+}
+
 pub struct Bitstamp {}
 
 #[async_trait]
 impl Exchange for Bitstamp {
     async fn connect(&self, symbol: String, max_depth: usize) -> Result<SnapshotStream, Box<dyn Error>> {
-        let (mut ws_stream, _) = connect_async(EXCHANGE_URL).await?;
-        ws_stream
-            .send(Message::text(format!(
-                "{{
-                    \"event\": \"bts:subscribe\",
-                    \"data\": {{
-                        \"channel\": \"order_book_{symbol}\"
-                    }}
-                }}"
-            )))
-            .await?;
-
-        // Confirm subscribed
-        let msg = futures::StreamExt::next(&mut ws_stream)
-            .await
-            .ok_or("didn't receive anything")??;
-        let data: BitstampConnectResponse = serde_json::from_str(msg.to_string().as_str())?;
-        if data.event.as_str() != "bts:subscription_succeeded" {
-            return Err(BitstampError::StreamFailed.into())
-        }
-        // Check the msg here
-        let (_, mut ws_receiver) = ws_stream.split();
+        let ws_stream = subscribe(&format!("order_book_{symbol}")).await?;
+        let (mut ws_sender, mut ws_receiver) = ws_stream.split();
         
-        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<
-            Result<FeedSnapshot, Box<dyn Error + Sync + Send>>,
-        >();
-        let rx = Box::pin(async_stream::stream! {
-              while let Some(item) = rx.recv().await {
-                  yield item;
-              }
-        }) as SnapshotStream;
+        let (tx, rx) = queue::bounded::<Result<FeedSnapshot, Box<dyn Error + Sync + Send>>>(
+            FEED_QUEUE_CAPACITY,
+            OverflowPolicy::Conflate,
+        );
+        let rx = Box::pin(rx) as SnapshotStream;
+        let feed = FeedGuard::open();
         tokio::spawn(async move {
+            let _feed = feed;
+            let symbol = &symbol;
             loop {
                 // Async closure for more egonomic error handling
-                let channel_result = async move |ws: &mut SecureWebsocketReceiver| -> Result<
-                    FeedSnapshot,
-                    Box<dyn Error + Send + Sync>,
-                > {
-                    let msg = futures::StreamExt::next(ws)
-                        .await
-                        .ok_or("didn't receive anything")??;
-                    let data: BitstampSnapshot = serde_json::from_str(msg.to_string().as_str())?;
-                    Ok(data.data)
-                }(&mut ws_receiver)
-                .await;
-
-                match channel_result {
-                    Err(e) => tx.send(Err(e)).unwrap(),
-                    Ok(snapshot) => tx.send(Ok(snapshot)).unwrap(),
+                let channel_result = tokio::select! {
+                    // The orderbook is no longer listening
+                    _ = tx.closed() => break,
+                    result = async move |ws: &mut SecureWebsocketReceiver| -> Result<
+                        Option<FeedSnapshot>,
+                        Box<dyn Error + Send + Sync>,
+                    > {
+                        let msg = match futures::StreamExt::next(ws).await {
+                            Some(msg) => msg?,
+                            None => return Ok(None),
+                        };
+                        let text = msg.to_string();
+                        if msg.is_text() {
+                            record(ExchangeType::Bitstamp, symbol, Channel::Book, &text);
+                        }
+                        Ok(Some(parse_book(&text, SystemTime::now())?))
+                    }(&mut ws_receiver) => result,
+                };
+
+                let pushed = match channel_result {
+                    Err(e) => tx.push(Err(e)),
+                    Ok(Some(snapshot)) => tx.push(Ok(snapshot)),
+                    // The websocket was closed, ending the stream
+                    Ok(None) => break,
                 };
+                if pushed.is_err() {
+                    // The orderbook is no longer listening
+                    break;
+                }
+            }
+            // Close the websocket rather than leaving the venue to time the connection out
+            if let Err(e) = ws_sender.close().await {
+                debug!("{} websocket did not close cleanly: {e}", ExchangeType::Bitstamp.to_string());
             }
         });
 
         Ok(rx)
     }
 
+    async fn connect_trades(&self, symbol: String) -> Result<TradeStream, Box<dyn Error>> {
+        let ws_stream = subscribe(&format!("live_trades_{symbol}")).await?;
+        Ok(trade_feed(ExchangeType::Bitstamp, symbol.clone(), ws_stream, move |text| parse_trade(&symbol, text)))
+    }
+
     fn name(&self) -> ExchangeType {
         ExchangeType::Bitstamp
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::exchanges::Aggressor;
+
+    use super::{parse_book, parse_trade};
+
+    #[test]
+    fn test_parse_book() {
+        let text = r#"{"data": {"timestamp": "1672515782", "microtimestamp": "1672515782134567", "bids": [["0.07", "1.5"]], "asks": [["0.08", "2"]]}, "channel": "order_book_ethbtc", "event": "data"}"#;
+        let snapshot = parse_book(text, UNIX_EPOCH).unwrap();
+        assert_eq!(snapshot.asks, vec![[0.08, 2.0]]);
+        assert_eq!(snapshot.event_time, Some(UNIX_EPOCH + Duration::from_micros(1672515782134567)));
+        assert_eq!(snapshot.received_at, Some(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn test_parse_trade() {
+        let text = r#"{"data": {"id": 261416871, "timestamp": "1672515782", "amount": 0.5, "amount_str": "0.50000000", "price": 0.07, "price_str": "0.07", "type": 0, "microtimestamp": "1672515782134567", "buy_order_id": 1, "sell_order_id": 2}, "channel": "live_trades_ethbtc", "event": "trade"}"#;
+        let trade = parse_trade("ethbtc", text).unwrap().unwrap();
+        assert_eq!(trade.id, "261416871");
+        assert_eq!(trade.amount, 0.5);
+        assert_eq!(trade.aggressor, Aggressor::Buy);
+        assert_eq!(trade.traded_at, UNIX_EPOCH + Duration::from_micros(1672515782134567));
+
+        let reconnect = r#"{"event": "bts:request_reconnect", "channel": "", "data": ""}"#;
+        assert!(parse_trade("ethbtc", reconnect).unwrap().is_none());
+    }
+}
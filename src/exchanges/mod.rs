@@ -1,9 +1,15 @@
+use std::collections::HashMap;
 use std::hash::Hash;
 use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    OnceLock,
+};
+use std::time::{Duration, SystemTime};
 use std::{error::Error, num::ParseFloatError, pin::Pin};
 
 use async_trait::async_trait;
-use async_tungstenite::{stream::Stream, tokio::TokioAdapter, WebSocketStream};
+use async_tungstenite::{stream::Stream, tokio::TokioAdapter, tungstenite::Message, WebSocketStream};
 
 use serde::{de, Deserialize, Deserializer};
 
@@ -11,24 +17,85 @@ use tokio::net::TcpStream;
 use tokio_native_tls::TlsStream;
 use tokio_stream::Stream as TokioStream;
 
-use futures::stream::SplitStream;
+use futures::{stream::SplitStream, SinkExt, StreamExt};
 
 use thiserror::Error as CustomError;
 
 pub mod binance;
 pub mod bitstamp;
+pub mod record;
+pub mod replay;
+pub mod status;
 
-type SecureWebsocketReceiver = SplitStream<
-    WebSocketStream<Stream<TokioAdapter<TcpStream>, TokioAdapter<TlsStream<TcpStream>>>>,
->;
+type SecureWebsocket =
+    WebSocketStream<Stream<TokioAdapter<TcpStream>, TokioAdapter<TlsStream<TcpStream>>>>;
+type SecureWebsocketReceiver = SplitStream<SecureWebsocket>;
+
+/// Snapshots an exchange may buffer before older ones are conflated away
+pub(crate) const FEED_QUEUE_CAPACITY: usize = 32;
+/// Trades an exchange may buffer before the oldest are dropped
+pub(crate) const TRADE_QUEUE_CAPACITY: usize = 1024;
 
 pub(crate) type SnapshotStream =
     Pin<Box<dyn TokioStream<Item = Result<FeedSnapshot, Box<dyn Error + Send + Sync>>> + Send>>;
+pub(crate) type TradeStream =
+    Pin<Box<dyn TokioStream<Item = Result<Trade, Box<dyn Error + Send + Sync>>> + Send>>;
+
+/// Websocket feeds whose task has not yet finished closing the connection
+static OPEN_FEEDS: AtomicUsize = AtomicUsize::new(0);
+/// URLs venues are connected to in place of their public endpoints
+static ENDPOINTS: OnceLock<HashMap<ExchangeType, String>> = OnceLock::new();
+
+/// Held by the task driving an exchange websocket for as long as the connection is open.
+pub(crate) struct FeedGuard {}
+
+impl FeedGuard {
+    pub(crate) fn open() -> Self {
+        OPEN_FEEDS.fetch_add(1, Ordering::SeqCst);
+        FeedGuard {}
+    }
+}
+
+impl Drop for FeedGuard {
+    fn drop(&mut self) {
+        OPEN_FEEDS.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Completes once every exchange websocket has been closed.
+pub async fn feeds_closed() {
+    while OPEN_FEEDS.load(Ordering::SeqCst) > 0 {
+        tokio::time::sleep(Duration::from_millis(50)).await;
+    }
+}
 
 #[derive(CustomError, Debug)]
 pub enum ExchangeTypeError {
     #[error("provided type is unrecognised")]
     UnknownTypeError,
+    #[error("synthetic levels are implied by other books rather than quoted by a venue")]
+    NotAVenue,
+}
+
+#[derive(CustomError, Debug)]
+pub enum EndpointError {
+    #[error("exchange endpoints have already been set")]
+    AlreadySet,
+}
+
+/// Connects each venue given to its URL rather than its public endpoint, such as a testnet or a proxy.
+///
+/// Only feeds connected afterwards use them, so they are set once at startup.
+pub fn set_endpoints(endpoints: HashMap<ExchangeType, String>) -> Result<(), EndpointError> {
+    ENDPOINTS.set(endpoints).map_err(|_| EndpointError::AlreadySet)
+}
+
+/// The URL a venue is connected to, its public endpoint unless another has been set.
+pub(crate) fn endpoint(exchange: ExchangeType, public: &'static str) -> &'static str {
+    ENDPOINTS
+        .get()
+        .and_then(|endpoints| endpoints.get(&exchange))
+        .map_or(public, String::as_str)
 }
 
 
@@ -38,6 +105,8 @@ pub enum ExchangeType {
     Default,
     Binance,
     Bitstamp,
+    /// Levels implied by the books of other symbols rather than quoted by a venue
+    Synthetic,
 }
 
 impl ToString for ExchangeType {
@@ -45,6 +114,7 @@ impl ToString for ExchangeType {
         match *self {
             Self::Binance => String::from("Binance"),
             Self::Bitstamp => String::from("Bitstamp"),
+            Self::Synthetic => String::from("Synthetic"),
             Self::Default => String::from("Default"),
         }
     }
@@ -57,25 +127,140 @@ impl FromStr for ExchangeType {
         match s.to_lowercase().as_str() {
             "binance" => Ok(ExchangeType::Binance),
             "bitstamp" => Ok(ExchangeType::Bitstamp),
+            "synthetic" => Ok(ExchangeType::Synthetic),
             _ => Err(ExchangeTypeError::UnknownTypeError)
         }
     }
 }
 
+/// Reads the name of a venue, which unlike `ExchangeType::from_str` refuses `synthetic`.
+pub fn parse_venue(name: &str) -> Result<ExchangeType, ExchangeTypeError> {
+    match ExchangeType::from_str(name)? {
+        ExchangeType::Synthetic => Err(ExchangeTypeError::NotAVenue),
+        exchange => Ok(exchange),
+    }
+}
+
+/// The adapter of a supported exchange.
+pub(crate) fn exchange(exchange: ExchangeType) -> Option<Box<dyn Exchange + Send + Sync>> {
+    match exchange {
+        ExchangeType::Binance => Some(Box::new(binance::Binance {})),
+        ExchangeType::Bitstamp => Some(Box::new(bitstamp::Bitstamp {})),
+        ExchangeType::Synthetic | ExchangeType::Default => None,
+    }
+}
+
 #[async_trait]
 pub(crate) trait Exchange {
-    /// Initiates a stream of `FeedSnapshot`, which yields an error if the stream gets interrupted
+    /// Initiates a stream of `FeedSnapshot`, which yields an error if the stream gets interrupted.
+    ///
+    /// The stream ends when the venue closes the websocket.
     async fn connect(&self, symbol: String, max_depth: usize) -> Result<SnapshotStream, Box<dyn Error>>;
 
+    /// Initiates a stream of the trades matched on the venue, on a websocket of its own.
+    ///
+    /// The stream ends when the venue closes the websocket.
+    async fn connect_trades(&self, symbol: String) -> Result<TradeStream, Box<dyn Error>>;
+
     fn name(&self) -> ExchangeType;
 }
 
+/// The side that crossed the spread to make a trade.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Aggressor {
+    /// Took the asks
+    Buy,
+    /// Took the bids
+    Sell,
+}
+
+/// A trade matched on a venue, normalised across exchanges.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trade {
+    pub exchange: ExchangeType,
+    pub symbol: String,
+    /// The venue's identifier of the trade
+    pub id: String,
+    pub price: f64,
+    pub amount: f64,
+    pub aggressor: Aggressor,
+    /// When the venue matched the trade
+    pub traded_at: SystemTime,
+    /// When the trade was received from the venue
+    pub received_at: SystemTime,
+}
+
+/// Reads trades from a venue's websocket until it closes or nobody is listening, then closes it.
+///
+/// `parse` turns a text frame into a trade, or `None` for frames that are not trades.
+pub(crate) fn trade_feed<F>(
+    exchange: ExchangeType,
+    symbol: String,
+    websocket: SecureWebsocket,
+    parse: F,
+) -> TradeStream
+where
+    F: Fn(&str) -> Result<Option<Trade>, Box<dyn Error + Send + Sync>> + Send + 'static,
+{
+    let (tx, rx) = crate::queue::bounded::<Result<Trade, Box<dyn Error + Send + Sync>>>(
+        TRADE_QUEUE_CAPACITY,
+        crate::queue::OverflowPolicy::DropOldest,
+    );
+    let (mut ws_sender, mut ws_receiver) = websocket.split();
+    let feed = FeedGuard::open();
+    tokio::spawn(async move {
+        let _feed = feed;
+        loop {
+            let msg = tokio::select! {
+                // The trade tape is no longer listening
+                _ = tx.closed() => break,
+                msg = ws_receiver.next() => match msg {
+                    Some(msg) => msg,
+                    // The websocket was closed, ending the stream
+                    None => break,
+                },
+            };
+            let trade = match msg {
+                Ok(Message::Text(text)) => {
+                    record::record(exchange, &symbol, record::Channel::Trades, &text);
+                    match parse(&text) {
+                        Ok(Some(trade)) => Ok(trade),
+                        Ok(None) => continue,
+                        Err(e) => Err(e),
+                    }
+                }
+                // Pings are answered by tungstenite
+                Ok(_) => continue,
+                Err(e) => Err(e.into()),
+            };
+            if tx.push(trade).is_err() {
+                break;
+            }
+        }
+        // Close the websocket rather than leaving the venue to time the connection out
+        if let Err(e) = ws_sender.close().await {
+            debug!("{} trade websocket did not close cleanly: {e}", exchange.to_string());
+        }
+    });
+    Box::pin(rx)
+}
+
 #[derive(Deserialize, Debug, Default)]
 pub struct FeedSnapshot {
     #[serde(deserialize_with = "from_str_floats")]
     pub(crate) bids: Vec<[f64; 2]>,
     #[serde(deserialize_with = "from_str_floats")]
     pub(crate) asks: Vec<[f64; 2]>,
+    /// When the venue published the book, if it says
+    #[serde(skip)]
+    pub(crate) event_time: Option<SystemTime>,
+    /// When the book was read from the websocket
+    #[serde(skip)]
+    pub(crate) received_at: Option<SystemTime>,
+    /// When other messages read from the websocket since the previous book were published and received, for venues
+    /// that do not stamp their books
+    #[serde(skip)]
+    pub(crate) latency_samples: Vec<(SystemTime, SystemTime)>,
 }
 
 fn from_str_floats<'de, D>(deserializer: D) -> Result<Vec<[f64; 2]>, D::Error>
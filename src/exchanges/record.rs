@@ -0,0 +1,185 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, TrySendError},
+        OnceLock,
+    },
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::ExchangeType;
+
+/// Frames that may wait to be written before newer ones are dropped
+const RECORD_QUEUE_CAPACITY: usize = 16_384;
+
+/// Where frames are sent to be written while recording
+static RECORDER: OnceLock<mpsc::SyncSender<RecordedFrame>> = OnceLock::new();
+/// Frames dropped since the writer last caught up, as it fell behind the venues
+static DROPPED_FRAMES: AtomicU64 = AtomicU64::new(0);
+
+/// The feed of a venue a frame was received on.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Channel {
+    Book,
+    Trades,
+}
+
+/// A text frame as received from a venue's websocket.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RecordedFrame {
+    pub venue: String,
+    pub symbol: String,
+    pub channel: Channel,
+    /// Microseconds since the Unix epoch at which the frame was received
+    pub received_at: u64,
+    pub payload: String,
+}
+
+/// Where and how often recordings are rotated.
+#[derive(Debug, Clone)]
+pub struct RecordConfig {
+    pub directory: PathBuf,
+    /// Size at which a recording is closed and another started
+    pub max_bytes: u64,
+    /// Age at which a recording is closed and another started
+    pub max_age: Duration,
+}
+
+/// Appends frames to the current recording, starting a new one whenever it grows too large or old.
+struct FrameWriter {
+    config: RecordConfig,
+    file: Option<(BufWriter<File>, u64, Instant)>,
+}
+
+impl FrameWriter {
+    fn new(config: RecordConfig) -> io::Result<Self> {
+        fs::create_dir_all(&config.directory)?;
+        Ok(Self { config, file: None })
+    }
+
+    fn write(&mut self, frame: &RecordedFrame) -> io::Result<()> {
+        let mut line = serde_json::to_vec(frame)?;
+        line.push(b'\n');
+        let expired = self.file.as_ref().is_some_and(|(_, written, opened)| {
+            *written >= self.config.max_bytes || opened.elapsed() >= self.config.max_age
+        });
+        if expired {
+            self.flush()?;
+            self.file = None;
+        }
+        let (file, written, _) = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert((self.open()?, 0, Instant::now())),
+        };
+        file.write_all(&line)?;
+        *written += line.len() as u64;
+        Ok(())
+    }
+
+    /// Opens a recording named after when it was started, so that recordings sort in the order they were made.
+    fn open(&self) -> io::Result<BufWriter<File>> {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let path = self
+            .config
+            .directory
+            .join(format!("frames-{}.jsonl", started.as_micros()));
+        info!("recording frames to {}", path.display());
+        Ok(BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &mut self.file {
+            Some((file, _, _)) => file.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Records every text frame the exchange adapters receive from now on, for as long as the process runs.
+///
+/// Frames are written on a thread of their own, which flushes whenever it has caught up. Frames received while it is
+/// too far behind are dropped rather than held in memory, and counted in the log.
+pub fn start(config: RecordConfig) -> io::Result<()> {
+    let mut writer = FrameWriter::new(config)?;
+    let (tx, rx) = mpsc::sync_channel::<RecordedFrame>(RECORD_QUEUE_CAPACITY);
+    if RECORDER.set(tx).is_err() {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "frames are already being recorded"));
+    }
+    std::thread::spawn(move || {
+        while let Ok(frame) = rx.recv() {
+            let mut result = writer.write(&frame);
+            for frame in rx.try_iter() {
+                result = result.and_then(|_| writer.write(&frame));
+            }
+            if let Err(e) = result.and_then(|_| writer.flush()) {
+                error!("could not record frames: {e}");
+            }
+            let dropped = DROPPED_FRAMES.swap(0, Ordering::Relaxed);
+            if dropped > 0 {
+                warn!("{dropped} frames were not recorded as the recording fell behind");
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Records a frame received from a venue, if frames are being recorded.
+pub(crate) fn record(exchange: ExchangeType, symbol: &str, channel: Channel, payload: &str) {
+    if let Some(recorder) = RECORDER.get() {
+        let received_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let frame = RecordedFrame {
+            venue: exchange.to_string(),
+            symbol: symbol.to_string(),
+            channel,
+            received_at: received_at.as_micros() as u64,
+            payload: payload.to_string(),
+        };
+        // Disconnected only if the writer has stopped, which it does not
+        if let Err(TrySendError::Full(_)) = recorder.try_send(frame) {
+            DROPPED_FRAMES.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{Channel, FrameWriter, RecordConfig, RecordedFrame};
+
+    #[test]
+    fn test_rotation() {
+        let directory = std::env::temp_dir().join(format!("frames-{}", std::process::id()));
+        let mut writer = FrameWriter::new(RecordConfig {
+            directory: directory.clone(),
+            max_bytes: 1,
+            max_age: Duration::from_secs(3600),
+        })
+        .unwrap();
+        let frame = RecordedFrame {
+            venue: String::from("Binance"),
+            symbol: String::from("ethbtc"),
+            channel: Channel::Book,
+            received_at: 1,
+            payload: String::from(r#"{"bids":[],"asks":[]}"#),
+        };
+        writer.write(&frame).unwrap();
+        // Recordings are named to the microsecond
+        std::thread::sleep(Duration::from_millis(1));
+        writer.write(&frame).unwrap();
+        writer.flush().unwrap();
+
+        let recordings: Vec<_> = std::fs::read_dir(&directory).unwrap().map(|entry| entry.unwrap().path()).collect();
+        assert_eq!(recordings.len(), 2);
+        let line = std::fs::read_to_string(&recordings[0]).unwrap();
+        let recorded: RecordedFrame = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(recorded, frame);
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use async_stream::stream;
+use async_trait::async_trait;
+use thiserror::Error;
+use tokio::{sync::Notify, time::Instant};
+
+use super::{
+    binance::{self, BookMessage},
+    bitstamp,
+    record::{Channel, RecordedFrame},
+    Exchange, ExchangeType, FeedSnapshot, SnapshotStream, Trade, TradeStream,
+};
+
+#[derive(Error, Debug)]
+pub enum ReplayError {
+    #[error("could not read recorded frames: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("{path} line {line} is not a recorded frame: {reason}")]
+    Malformed { path: PathBuf, line: usize, reason: String },
+}
+
+/// How quickly recorded frames are replayed.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub enum Pace {
+    /// Every frame as soon as the book has taken the one before it
+    #[default]
+    Unpaced,
+    /// Frames spaced as they were received, `speed` times faster
+    Recorded { speed: f64 },
+}
+
+/// Frames recorded with `--record`, replayed in the order they were received.
+#[derive(Debug, Clone)]
+pub struct ReplaySession {
+    frames: Vec<(ExchangeType, RecordedFrame)>,
+    pace: Pace,
+    /// When the book frame last handed to a book was received
+    replayed_at: Arc<Mutex<Option<SystemTime>>>,
+}
+
+impl ReplaySession {
+    /// Orders frames by when they were received, keeping the recorded order of frames received at once.
+    ///
+    /// Frames of venues without an adapter are left out.
+    pub fn new(frames: Vec<RecordedFrame>) -> Self {
+        let mut frames: Vec<(ExchangeType, RecordedFrame)> = frames
+            .into_iter()
+            .filter_map(|frame| {
+                let venue = ExchangeType::from_str(&frame.venue).ok()?;
+                matches!(venue, ExchangeType::Binance | ExchangeType::Bitstamp).then_some((venue, frame))
+            })
+            .collect();
+        frames.sort_by_key(|(_, frame)| frame.received_at);
+        Self {
+            frames,
+            pace: Pace::default(),
+            replayed_at: Arc::default(),
+        }
+    }
+
+    /// Reads a recording, or every recording in a directory in the order they were made.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, ReplayError> {
+        let path = path.as_ref();
+        let mut recordings = if path.is_dir() {
+            fs::read_dir(path)?
+                .map(|entry| entry.map(|entry| entry.path()))
+                .collect::<Result<Vec<_>, _>>()?
+                .into_iter()
+                .filter(|path| path.extension().is_some_and(|extension| extension == "jsonl"))
+                .collect()
+        } else {
+            vec![path.to_path_buf()]
+        };
+        recordings.sort();
+
+        let mut frames = Vec::new();
+        for recording in recordings {
+            for (i, line) in fs::read_to_string(&recording)?.lines().enumerate() {
+                if line.trim().is_empty() {
+                    continue;
+                }
+                let frame = serde_json::from_str(line).map_err(|e| ReplayError::Malformed {
+                    path: recording.clone(),
+                    line: i + 1,
+                    reason: e.to_string(),
+                })?;
+                frames.push(frame);
+            }
+        }
+        Ok(Self::new(frames))
+    }
+
+    pub fn with_pace(mut self, pace: Pace) -> Self {
+        self.pace = pace;
+        self
+    }
+
+    /// When the book frame most recently handed to a book built from the session was received.
+    ///
+    /// Unpaced, the book takes each frame before the next is replayed, so this is when the update it last
+    /// merged was received.
+    pub fn replayed_at(&self) -> Option<SystemTime> {
+        *self.replayed_at.lock().unwrap()
+    }
+
+    /// Venues with book frames recorded for `symbol`, in the order they first appear.
+    pub fn venues(&self, symbol: &str) -> Vec<ExchangeType> {
+        let mut venues = Vec::new();
+        for (venue, frame) in &self.frames {
+            if frame.symbol == symbol && frame.channel == Channel::Book && !venues.contains(venue) {
+                venues.push(*venue);
+            }
+        }
+        venues
+    }
+
+    /// An adapter for each venue of `symbol`, sharing a timeline so that together they replay its frames in order.
+    pub(crate) fn replays(&self, symbol: &str) -> Vec<Replay> {
+        let (books, trades): (Vec<_>, Vec<_>) = self
+            .frames
+            .iter()
+            .filter(|(_, frame)| frame.symbol == symbol)
+            .cloned()
+            .partition(|(_, frame)| frame.channel == Channel::Book);
+        let timeline = Arc::new(Timeline {
+            start: self
+                .frames
+                .iter()
+                .find(|(_, frame)| frame.symbol == symbol)
+                .map_or(0, |(_, frame)| frame.received_at),
+            books,
+            trades,
+            pace: self.pace,
+            cursor: Mutex::new(0),
+            started: Mutex::new(None),
+            advanced: Notify::new(),
+            replayed_at: self.replayed_at.clone(),
+        });
+        self.venues(symbol)
+            .into_iter()
+            .map(|venue| Replay {
+                venue,
+                timeline: timeline.clone(),
+            })
+            .collect()
+    }
+}
+
+/// The frames of a symbol, book frames handed out one at a time in the order they were received.
+#[derive(Debug)]
+struct Timeline {
+    books: Vec<(ExchangeType, RecordedFrame)>,
+    trades: Vec<(ExchangeType, RecordedFrame)>,
+    pace: Pace,
+    /// When the first frame was received, in microseconds since the Unix epoch
+    start: u64,
+    /// The book frame to be replayed next
+    cursor: Mutex<usize>,
+    /// When the first frame was replayed
+    started: Mutex<Option<Instant>>,
+    advanced: Notify,
+    replayed_at: Arc<Mutex<Option<SystemTime>>>,
+}
+
+impl Timeline {
+    /// Waits until the book frame at `position` is next, or returns false if it has already been replayed.
+    async fn wait_for(&self, position: usize) -> bool {
+        loop {
+            // Registered before checking, so that an advance in between is not missed
+            let advanced = self.advanced.notified();
+            let cursor = *self.cursor.lock().unwrap();
+            if cursor == position {
+                return true;
+            }
+            if cursor > position {
+                return false;
+            }
+            advanced.await;
+        }
+    }
+
+    /// Moves past the book frame at `position`.
+    fn advance(&self, position: usize) {
+        *self.replayed_at.lock().unwrap() = Some(received_at(&self.books[position].1));
+        *self.cursor.lock().unwrap() = position + 1;
+        self.advanced.notify_waiters();
+    }
+
+    /// Sleeps until a frame received at `received_at` is due.
+    async fn pace(&self, received_at: u64) {
+        let speed = match self.pace {
+            Pace::Unpaced => return,
+            Pace::Recorded { speed } => speed,
+        };
+        let started = *self.started.lock().unwrap().get_or_insert_with(Instant::now);
+        let offset = Duration::from_micros(received_at.saturating_sub(self.start));
+        tokio::time::sleep_until(started + offset.div_f64(speed)).await;
+    }
+
+    /// Positions of the frames of a venue among `frames`.
+    fn positions(frames: &[(ExchangeType, RecordedFrame)], venue: ExchangeType) -> Vec<usize> {
+        frames
+            .iter()
+            .enumerate()
+            .filter(|(_, (frame_venue, _))| *frame_venue == venue)
+            .map(|(position, _)| position)
+            .collect()
+    }
+}
+
+fn received_at(frame: &RecordedFrame) -> SystemTime {
+    UNIX_EPOCH + Duration::from_micros(frame.received_at)
+}
+
+/// Replays the recorded frames of a venue through the parsing of its adapter.
+#[derive(Debug)]
+pub(crate) struct Replay {
+    venue: ExchangeType,
+    timeline: Arc<Timeline>,
+}
+
+impl Replay {
+    fn parse_book(&self, frame: &RecordedFrame) -> Result<BookMessage, Box<dyn Error + Send + Sync>> {
+        match self.venue {
+            ExchangeType::Binance => binance::parse_message(&frame.payload, received_at(frame)),
+            _ => bitstamp::parse_book(&frame.payload, received_at(frame)).map(BookMessage::Depth),
+        }
+    }
+
+    fn parse_trade(&self, frame: &RecordedFrame) -> Result<Option<Trade>, Box<dyn Error + Send + Sync>> {
+        match self.venue {
+            ExchangeType::Binance => binance::parse_trade(&frame.symbol, &frame.payload).map(Some),
+            _ => bitstamp::parse_trade(&frame.symbol, &frame.payload),
+        }
+    }
+}
+
+#[async_trait]
+impl Exchange for Replay {
+    /// Replays the venue's book frames, each once every frame received before it has been replayed.
+    ///
+    /// The stream ends after the last of them, connecting again replays none that were already replayed.
+    async fn connect(&self, _symbol: String, _max_depth: usize) -> Result<SnapshotStream, Box<dyn Error>> {
+        let positions = Timeline::positions(&self.timeline.books, self.venue);
+        let replay = Replay {
+            venue: self.venue,
+            timeline: self.timeline.clone(),
+        };
+        Ok(Box::pin(stream! {
+            let mut latency_samples = Vec::new();
+            for position in positions {
+                if !replay.timeline.wait_for(position).await {
+                    continue;
+                }
+                let frame = &replay.timeline.books[position].1;
+                replay.timeline.pace(frame.received_at).await;
+                let message = replay.parse_book(frame);
+                replay.timeline.advance(position);
+                match message {
+                    Ok(BookMessage::Depth(snapshot)) => yield Ok(FeedSnapshot {
+                        latency_samples: std::mem::take(&mut latency_samples),
+                        ..snapshot
+                    }),
+                    Ok(BookMessage::Latency { sent_at, received_at }) => latency_samples.push((sent_at, received_at)),
+                    Ok(BookMessage::Reply) => {}
+                    Err(e) => yield Err(e),
+                }
+            }
+        }))
+    }
+
+    /// Replays the venue's trade frames, paced but not ordered against the book frames.
+    async fn connect_trades(&self, _symbol: String) -> Result<TradeStream, Box<dyn Error>> {
+        let positions = Timeline::positions(&self.timeline.trades, self.venue);
+        let replay = Replay {
+            venue: self.venue,
+            timeline: self.timeline.clone(),
+        };
+        Ok(Box::pin(stream! {
+            for position in positions {
+                let frame = &replay.timeline.trades[position].1;
+                replay.timeline.pace(frame.received_at).await;
+                match replay.parse_trade(frame) {
+                    Ok(Some(trade)) => yield Ok(trade),
+                    Ok(None) => continue,
+                    Err(e) => yield Err(e),
+                }
+            }
+        }))
+    }
+
+    fn name(&self) -> ExchangeType {
+        self.venue
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::{pin_mut, StreamExt};
+
+    use crate::{
+        exchanges::{
+            record::{Channel, RecordedFrame},
+            ExchangeType,
+        },
+        orderbook::{
+            builder::{Empty, OrderbookBuilder},
+            streaming_book::HeapedBook,
+            Orderbook,
+        },
+    };
+
+    use super::ReplaySession;
+
+    fn frame(venue: &str, received_at: u64, bid: &str) -> RecordedFrame {
+        let payload = match venue {
+            "Binance" => format!(r#"{{"lastUpdateId": 1, "bids": [["{bid}", "1"]], "asks": [["0.09", "1"]]}}"#),
+            _ => format!(
+                r#"{{"data": {{"timestamp": "1", "bids": [["{bid}", "2"]], "asks": [["0.1", "2"]]}}, "channel": "order_book_ethbtc", "event": "data"}}"#
+            ),
+        };
+        RecordedFrame {
+            venue: venue.to_string(),
+            symbol: String::from("ethbtc"),
+            channel: Channel::Book,
+            received_at,
+            payload,
+        }
+    }
+
+    async fn best_bids(session: &ReplaySession) -> Vec<(f64, ExchangeType)> {
+        let mut orderbook = OrderbookBuilder::<Empty>::new()
+            .with_max_depth(10)
+            .with_symbol("ethbtc")
+            .with_replay(session)
+            .build::<HeapedBook>()
+            .await
+            .unwrap();
+        let stream = orderbook.collect();
+        pin_mut!(stream);
+        let mut best_bids = Vec::new();
+        // The book ends with an error once every venue has been replayed
+        while let Some(Ok((bids, _))) = stream.next().await {
+            best_bids.push((bids[0].price, bids[0].exchange));
+        }
+        best_bids
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        // Listed out of the order they were received in
+        // Binance's book feed also carries the reply to its trade subscription
+        let reply = RecordedFrame {
+            payload: String::from(r#"{"result": null, "id": 1}"#),
+            ..frame("Binance", 0, "0.06")
+        };
+        let session = ReplaySession::new(vec![
+            reply,
+            frame("Bitstamp", 2, "0.07"),
+            frame("Binance", 1, "0.06"),
+            frame("Binance", 3, "0.08"),
+            frame("Bitstamp", 4, "0.05"),
+        ]);
+        assert_eq!(session.venues("ethbtc"), vec![ExchangeType::Binance, ExchangeType::Bitstamp]);
+
+        let expected = vec![
+            (0.06, ExchangeType::Binance),
+            (0.07, ExchangeType::Bitstamp),
+            (0.08, ExchangeType::Binance),
+            (0.08, ExchangeType::Binance),
+        ];
+        assert_eq!(best_bids(&session).await, expected);
+        assert_eq!(best_bids(&session).await, expected);
+    }
+}
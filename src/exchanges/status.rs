@@ -0,0 +1,512 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+
+use async_stream::stream;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio_stream::StreamExt;
+
+use super::{Exchange, ExchangeType, SnapshotStream};
+
+/// How long a venue may stay silent before it is reported as `Stale`
+const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(5);
+/// Window over which the message rate of a venue is measured
+const RATE_WINDOW: Duration = Duration::from_secs(10);
+/// Window over which the latency percentiles and clock skew of a venue are measured
+const LATENCY_WINDOW: Duration = Duration::from_secs(60);
+/// Window over which a venue's latency is compared against the threshold for flagging it as delayed
+const SPIKE_WINDOW: Duration = Duration::from_secs(5);
+
+/// The connection state of a single exchange feed.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VenueState {
+    /// The websocket connection is being established
+    Connecting,
+    /// The venue accepted the subscription but has not published a book yet
+    Subscribed,
+    /// Order books are arriving
+    Live,
+    /// No order book has arrived within the staleness threshold
+    Stale,
+    /// The connection dropped and is being re-established
+    Reconnecting,
+    /// The venue could not be (re)connected and has been given up on
+    Failed,
+}
+
+impl Display for VenueState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Connecting => write!(f, "connecting"),
+            Self::Subscribed => write!(f, "subscribed"),
+            Self::Live => write!(f, "live"),
+            Self::Stale => write!(f, "stale"),
+            Self::Reconnecting => write!(f, "reconnecting"),
+            Self::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+/// A point-in-time view of the health of a single exchange feed.
+#[derive(Debug, Clone)]
+pub struct VenueHealth {
+    pub exchange: ExchangeType,
+    pub symbol: String,
+    pub state: VenueState,
+    pub last_message: Option<SystemTime>,
+    pub messages: u64,
+    /// Messages per second over the last `RATE_WINDOW`
+    pub message_rate: f64,
+    pub errors: u64,
+    pub last_error: Option<String>,
+    /// Absent until a message carrying the venue's own timestamp has arrived
+    pub latency: Option<LatencySummary>,
+}
+
+/// The time messages took to arrive from a venue over the last `LATENCY_WINDOW`, measured against its timestamps.
+///
+/// Latencies include the offset between the venue's clock and ours, so may be negative.
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LatencySummary {
+    pub samples: usize,
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+    /// The lowest latency seen, which estimates how far the venue's clock is behind ours on the assumption
+    /// that the fastest messages spent next to no time in transit
+    pub clock_skew_ms: f64,
+}
+
+impl LatencySummary {
+    fn new(latencies: impl Iterator<Item = f64>) -> Option<Self> {
+        let mut sorted: Vec<f64> = latencies.collect();
+        sorted.sort_by(f64::total_cmp);
+        Some(Self {
+            samples: sorted.len(),
+            p50_ms: percentile(&sorted, 0.5)?,
+            p99_ms: percentile(&sorted, 0.99)?,
+            max_ms: *sorted.last()?,
+            clock_skew_ms: *sorted.first()?,
+        })
+    }
+}
+
+/// The nearest-rank percentile of sorted values.
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let rank = (p * sorted.len() as f64).ceil() as usize;
+    sorted.get(rank.saturating_sub(1)).copied()
+}
+
+struct VenueRecord {
+    state: VenueState,
+    last_message: Option<(SystemTime, Instant)>,
+    messages: u64,
+    recent: VecDeque<Instant>,
+    errors: u64,
+    last_error: Option<String>,
+    /// When each timestamped message arrived and its latency in milliseconds
+    latencies: VecDeque<(Instant, f64)>,
+}
+
+impl VenueRecord {
+    fn new() -> Self {
+        Self {
+            state: VenueState::Connecting,
+            last_message: None,
+            messages: 0,
+            recent: VecDeque::new(),
+            errors: 0,
+            last_error: None,
+            latencies: VecDeque::new(),
+        }
+    }
+
+    fn evict_before(&mut self, now: Instant) {
+        while let Some(oldest) = self.recent.front() {
+            if now.duration_since(*oldest) <= RATE_WINDOW {
+                break;
+            }
+            self.recent.pop_front();
+        }
+        while let Some((oldest, _)) = self.latencies.front() {
+            if now.duration_since(*oldest) <= LATENCY_WINDOW {
+                break;
+            }
+            self.latencies.pop_front();
+        }
+    }
+
+    /// Whether the median latency over the last `SPIKE_WINDOW`, above the lowest over the last `LATENCY_WINDOW`,
+    /// exceeds `threshold`. Measuring from the lowest latency discounts the venue's clock skew.
+    fn delayed(&self, now: Instant, threshold: Duration) -> bool {
+        let skew = match self.latencies.iter().map(|(_, latency)| *latency).min_by(f64::total_cmp) {
+            Some(skew) => skew,
+            None => return false,
+        };
+        let mut recent: Vec<f64> = self
+            .latencies
+            .iter()
+            .rev()
+            .take_while(|(at, _)| now.duration_since(*at) <= SPIKE_WINDOW)
+            .map(|(_, latency)| latency - skew)
+            .collect();
+        recent.sort_by(f64::total_cmp);
+        percentile(&recent, 0.5).is_some_and(|median| median > threshold.as_secs_f64() * 1000.0)
+    }
+}
+
+/// Tracks the connection state of every exchange feed and notifies subscribers of state changes.
+///
+/// Cloning a `VenueMonitor` yields another handle onto the same set of venues.
+#[derive(Clone)]
+pub struct VenueMonitor {
+    venues: Arc<Mutex<HashMap<(ExchangeType, String), VenueRecord>>>,
+    changes: broadcast::Sender<(ExchangeType, VenueState)>,
+    stale_after: Duration,
+}
+
+impl Default for VenueMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VenueMonitor {
+    pub fn new() -> Self {
+        Self::with_stale_after(DEFAULT_STALE_AFTER)
+    }
+
+    pub fn with_stale_after(stale_after: Duration) -> Self {
+        let (changes, _) = broadcast::channel(64);
+        Self {
+            venues: Arc::new(Mutex::new(HashMap::new())),
+            changes,
+            stale_after,
+        }
+    }
+
+    /// Returns a handle that a single feed uses to report its progress.
+    pub fn reporter<S: Into<String>>(&self, exchange: ExchangeType, symbol: S) -> VenueReporter {
+        VenueReporter {
+            monitor: self.clone(),
+            exchange,
+            symbol: symbol.into(),
+        }
+    }
+
+    /// Receives the exchange and new state every time a venue changes state.
+    pub fn subscribe(&self) -> broadcast::Receiver<(ExchangeType, VenueState)> {
+        self.changes.subscribe()
+    }
+
+    /// The current health of every known venue, ordered by exchange and symbol.
+    pub fn snapshot(&self) -> Vec<VenueHealth> {
+        let now = Instant::now();
+        let mut venues = self.venues.lock().unwrap();
+        let mut health = venues
+            .iter_mut()
+            .map(|((exchange, symbol), record)| {
+                record.evict_before(now);
+                let state = match (record.state, record.last_message) {
+                    (VenueState::Live, Some((_, received)))
+                        if now.duration_since(received) > self.stale_after =>
+                    {
+                        VenueState::Stale
+                    }
+                    (state, _) => state,
+                };
+                VenueHealth {
+                    exchange: *exchange,
+                    symbol: symbol.clone(),
+                    state,
+                    last_message: record.last_message.map(|(at, _)| at),
+                    messages: record.messages,
+                    message_rate: record.recent.len() as f64 / RATE_WINDOW.as_secs_f64(),
+                    errors: record.errors,
+                    last_error: record.last_error.clone(),
+                    latency: LatencySummary::new(record.latencies.iter().map(|(_, latency)| *latency)),
+                }
+            })
+            .collect::<Vec<VenueHealth>>();
+        health.sort_by(|a, b| {
+            (a.exchange.to_string(), &a.symbol).cmp(&(b.exchange.to_string(), &b.symbol))
+        });
+        health
+    }
+
+    /// The venues of `symbol` whose recent latency has risen more than `threshold` above their usual.
+    pub fn delayed(&self, symbol: &str, threshold: Duration) -> Vec<ExchangeType> {
+        let now = Instant::now();
+        let venues = self.venues.lock().unwrap();
+        venues
+            .iter()
+            .filter(|((_, venue_symbol), record)| venue_symbol == symbol && record.delayed(now, threshold))
+            .map(|((exchange, _), _)| *exchange)
+            .collect()
+    }
+
+    fn remove(&self, exchange: ExchangeType, symbol: &str) {
+        let removed = self
+            .venues
+            .lock()
+            .unwrap()
+            .remove(&(exchange, symbol.to_string()));
+        if removed.is_some() {
+            info!("{} {symbol} is no longer monitored", exchange.to_string());
+        }
+    }
+
+    fn update<F: FnOnce(&mut VenueRecord)>(&self, exchange: ExchangeType, symbol: &str, f: F) {
+        let state = {
+            let mut venues = self.venues.lock().unwrap();
+            let record = venues
+                .entry((exchange, symbol.to_string()))
+                .or_insert_with(VenueRecord::new);
+            let previous = record.state;
+            f(record);
+            (record.state != previous).then_some(record.state)
+        };
+        if let Some(state) = state {
+            info!("{} {symbol} is now {state:?}", exchange.to_string());
+            // Nobody listening is not an error
+            let _ = self.changes.send((exchange, state));
+        }
+    }
+}
+
+/// Reports the progress of a single feed to its `VenueMonitor`.
+#[derive(Clone)]
+pub struct VenueReporter {
+    monitor: VenueMonitor,
+    exchange: ExchangeType,
+    symbol: String,
+}
+
+impl VenueReporter {
+    pub fn connecting(&self) {
+        self.set_state(VenueState::Connecting);
+    }
+
+    pub fn subscribed(&self) {
+        self.set_state(VenueState::Subscribed);
+    }
+
+    pub fn reconnecting(&self) {
+        self.set_state(VenueState::Reconnecting);
+    }
+
+    pub fn failed<E: Display + ?Sized>(&self, error: &E) {
+        self.monitor.update(self.exchange, &self.symbol, |record| {
+            record.state = VenueState::Failed;
+            record.errors += 1;
+            record.last_error = Some(error.to_string());
+        });
+    }
+
+    pub fn message(&self) {
+        self.monitor.update(self.exchange, &self.symbol, |record| {
+            let now = Instant::now();
+            record.state = VenueState::Live;
+            record.last_message = Some((SystemTime::now(), now));
+            record.messages += 1;
+            record.recent.push_back(now);
+            record.evict_before(now);
+        });
+    }
+
+    /// Records the latency of a message the venue stamped with `sent_at`, which arrived at `received_at`.
+    pub fn latency(&self, sent_at: SystemTime, received_at: SystemTime) {
+        let latency = match received_at.duration_since(sent_at) {
+            Ok(ahead) => ahead.as_secs_f64() * 1000.0,
+            // The venue's clock is ahead of ours
+            Err(e) => -e.duration().as_secs_f64() * 1000.0,
+        };
+        self.monitor.update(self.exchange, &self.symbol, |record| {
+            let now = Instant::now();
+            record.latencies.push_back((now, latency));
+            record.evict_before(now);
+        });
+    }
+
+    pub fn error<E: Display + ?Sized>(&self, error: &E) {
+        self.monitor.update(self.exchange, &self.symbol, |record| {
+            record.errors += 1;
+            record.last_error = Some(error.to_string());
+        });
+    }
+
+    /// Stop reporting the venue, as the feed has been torn down.
+    pub fn disconnected(&self) {
+        self.monitor.remove(self.exchange, &self.symbol);
+    }
+
+    fn set_state(&self, state: VenueState) {
+        self.monitor
+            .update(self.exchange, &self.symbol, |record| record.state = state);
+    }
+}
+
+/// Stops a venue from being reported once the feed that owns this is dropped.
+struct FeedRegistration {
+    reporter: VenueReporter,
+}
+
+impl Drop for FeedRegistration {
+    fn drop(&mut self) {
+        self.reporter.disconnected();
+    }
+}
+
+/// Governs how often and how quickly a dropped feed is re-established.
+#[derive(Debug, Copy, Clone)]
+pub struct ReconnectPolicy {
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Wraps an already connected feed so that every message and error is reported, and the feed is
+/// reconnected according to `policy` whenever the venue closes it.
+///
+/// The returned stream only ends once the venue is `Failed`, dropping it stops the venue from being reported.
+pub(crate) fn supervise(
+    exchange: Box<dyn Exchange + Send + Sync>,
+    feed: SnapshotStream,
+    symbol: String,
+    max_depth: usize,
+    policy: ReconnectPolicy,
+    reporter: VenueReporter,
+) -> SnapshotStream {
+    let registration = FeedRegistration {
+        reporter: reporter.clone(),
+    };
+    Box::pin(stream! {
+        let _registration = registration;
+        let mut feed = feed;
+        loop {
+            while let Some(event) = feed.next().await {
+                match &event {
+                    Ok(snapshot) => {
+                        reporter.message();
+                        if let (Some(sent_at), Some(received_at)) = (snapshot.event_time, snapshot.received_at) {
+                            reporter.latency(sent_at, received_at);
+                        }
+                        for &(sent_at, received_at) in &snapshot.latency_samples {
+                            reporter.latency(sent_at, received_at);
+                        }
+                    }
+                    Err(e) => reporter.error(e),
+                }
+                yield event;
+            }
+
+            reporter.reconnecting();
+            let mut backoff = policy.initial_backoff;
+            let mut reconnected = None;
+            for attempt in 1..=policy.max_attempts {
+                tokio::time::sleep(backoff).await;
+                match exchange.connect(symbol.clone(), max_depth).await {
+                    Ok(stream) => {
+                        reconnected = Some(stream);
+                        break;
+                    }
+                    Err(e) => {
+                        warn!("{} reconnect attempt {attempt} failed: {e}", exchange.name().to_string());
+                        reporter.error(&e);
+                        backoff = (backoff * 2).min(policy.max_backoff);
+                    }
+                }
+            }
+            match reconnected {
+                Some(stream) => {
+                    reporter.subscribed();
+                    feed = stream;
+                }
+                None => {
+                    reporter.failed("exceeded the maximum number of reconnect attempts");
+                    break;
+                }
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use crate::exchanges::ExchangeType;
+
+    use super::{VenueMonitor, VenueState};
+
+    #[test]
+    fn test_venue_transitions() {
+        let monitor = VenueMonitor::new();
+        let reporter = monitor.reporter(ExchangeType::Binance, "ethbtc");
+        reporter.connecting();
+        reporter.subscribed();
+        assert_eq!(monitor.snapshot()[0].state, VenueState::Subscribed);
+
+        reporter.message();
+        reporter.message();
+        reporter.error("malformed message");
+        let venue = &monitor.snapshot()[0];
+        assert_eq!(venue.state, VenueState::Live);
+        assert_eq!(venue.messages, 2);
+        assert_eq!(venue.errors, 1);
+        assert_eq!(venue.last_error.as_deref(), Some("malformed message"));
+        assert!(venue.message_rate > 0.0);
+
+        reporter.disconnected();
+        assert!(monitor.snapshot().is_empty());
+    }
+
+    #[test]
+    fn test_venue_goes_stale() {
+        let monitor = VenueMonitor::with_stale_after(Duration::ZERO);
+        let reporter = monitor.reporter(ExchangeType::Bitstamp, "ethbtc");
+        reporter.message();
+        std::thread::sleep(Duration::from_millis(5));
+        assert_eq!(monitor.snapshot()[0].state, VenueState::Stale);
+    }
+
+    #[test]
+    fn test_latency() {
+        let monitor = VenueMonitor::new();
+        let reporter = monitor.reporter(ExchangeType::Bitstamp, "ethbtc");
+        let received_at = SystemTime::now();
+        // The venue's clock runs 10ms ahead of ours, and one message took 200ms longer than the rest
+        let sent = [
+            received_at + Duration::from_millis(10),
+            received_at,
+            received_at,
+            received_at - Duration::from_millis(190),
+        ];
+        for sent_at in sent {
+            reporter.latency(sent_at, received_at);
+        }
+        let latency = monitor.snapshot()[0].latency.unwrap();
+        assert_eq!(latency.samples, 4);
+        assert_eq!(latency.clock_skew_ms, -10.0);
+        assert_eq!(latency.p50_ms, 0.0);
+        assert_eq!(latency.max_ms, 190.0);
+        // Its median is only 10ms above the lowest
+        assert!(monitor.delayed("ethbtc", Duration::from_millis(5)).contains(&ExchangeType::Bitstamp));
+        assert!(monitor.delayed("ethbtc", Duration::from_millis(50)).is_empty());
+    }
+}
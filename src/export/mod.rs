@@ -0,0 +1,341 @@
+use std::{
+    error::Error,
+    fmt::Display,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, TrySendError},
+        Arc,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use futures::{pin_mut, stream::select_all, StreamExt};
+use prost::Message;
+use thiserror::Error;
+
+use crate::{
+    exchanges::{replay::ReplaySession, ExchangeType},
+    hub::{BookEvent, BookHub, HubError, MergedBook},
+    orderbook::{
+        builder::{Empty, OrderbookBuilder},
+        streaming_book::HeapedBook,
+        Orderbook,
+    },
+    queue::OverflowPolicy,
+    server::{orderbook_rpc::Summary, summarise},
+};
+
+/// Updates a live export may fall behind by before the oldest are dropped, leaving a gap in the sequence
+const EXPORT_QUEUE_CAPACITY: usize = 1024;
+/// Updates that may wait to be written to the file before newer ones are dropped
+const WRITE_QUEUE_CAPACITY: usize = 1024;
+
+#[derive(Error, Debug)]
+pub enum ExportError {
+    #[error("unknown export format {0}, expected csv or binary")]
+    UnknownFormat(String),
+    #[error("could not write the export: {0}")]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Hub(#[from] HubError),
+}
+
+/// How merged books are written.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ExportFormat {
+    /// A row per level of every update
+    Csv,
+    /// Length-delimited protobuf `Summary` records, one per update
+    Binary,
+}
+
+impl FromStr for ExportFormat {
+    type Err = ExportError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "csv" => Ok(Self::Csv),
+            "binary" | "bin" => Ok(Self::Binary),
+            _ => Err(ExportError::UnknownFormat(s.to_string())),
+        }
+    }
+}
+
+impl Display for ExportFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Csv => write!(f, "csv"),
+            Self::Binary => write!(f, "binary"),
+        }
+    }
+}
+
+/// Writes each merged book it is given.
+pub trait BookWriter: Send {
+    fn write(&mut self, book: &MergedBook) -> io::Result<()>;
+
+    fn flush(&mut self) -> io::Result<()>;
+}
+
+fn timestamp_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+/// Writes a row per level, best first on each side, under a header written before the first book.
+pub struct CsvWriter<W: Write + Send> {
+    out: W,
+    header: bool,
+}
+
+impl<W: Write + Send> CsvWriter<W> {
+    pub const HEADER: &'static str = "timestamp_ms,sequence,symbol,side,level,exchange,price,amount";
+
+    pub fn new(out: W) -> Self {
+        Self { out, header: false }
+    }
+
+    fn row(
+        &mut self,
+        book: &MergedBook,
+        side: &str,
+        level: usize,
+        exchange: ExchangeType,
+        price: f64,
+        amount: f64,
+    ) -> io::Result<()> {
+        writeln!(
+            self.out,
+            "{},{},{},{side},{level},{},{price},{amount}",
+            timestamp_ms(book.timestamp),
+            book.sequence,
+            book.symbol,
+            exchange.to_string(),
+        )
+    }
+}
+
+impl<W: Write + Send> BookWriter for CsvWriter<W> {
+    fn write(&mut self, book: &MergedBook) -> io::Result<()> {
+        if !self.header {
+            writeln!(self.out, "{}", Self::HEADER)?;
+            self.header = true;
+        }
+        for (level, bid) in book.bids.iter().enumerate() {
+            self.row(book, "bid", level, bid.exchange, bid.price, bid.amount)?;
+        }
+        for (level, ask) in book.asks.iter().enumerate() {
+            self.row(book, "ask", level, ask.exchange, ask.price, ask.amount)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Writes each book as a protobuf `Summary` prefixed by its length as a varint, as `read_summaries` reads them.
+pub struct BinaryWriter<W: Write + Send> {
+    out: W,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write + Send> BinaryWriter<W> {
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            buffer: Vec::new(),
+        }
+    }
+}
+
+impl<W: Write + Send> BookWriter for BinaryWriter<W> {
+    fn write(&mut self, book: &MergedBook) -> io::Result<()> {
+        self.buffer.clear();
+        summarise(book, usize::MAX)
+            .encode_length_delimited(&mut self.buffer)
+            .map_err(io::Error::other)?;
+        self.out.write_all(&self.buffer)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// Creates the file at `path` and a writer of `format` to it.
+pub fn create(format: ExportFormat, path: &Path) -> Result<Box<dyn BookWriter>, ExportError> {
+    let out = BufWriter::new(File::create(path)?);
+    Ok(match format {
+        ExportFormat::Csv => Box::new(CsvWriter::new(out)),
+        ExportFormat::Binary => Box::new(BinaryWriter::new(out)),
+    })
+}
+
+/// Reads back the records written by a `BinaryWriter`.
+pub fn read_summaries(mut bytes: &[u8]) -> Result<Vec<Summary>, prost::DecodeError> {
+    let mut summaries = Vec::new();
+    while !bytes.is_empty() {
+        summaries.push(Summary::decode_length_delimited(&mut bytes)?);
+    }
+    Ok(summaries)
+}
+
+/// Writes every update of the merged books of `symbols` until the hub shuts down.
+///
+/// Updates are written on a thread of their own, which flushes whenever it has caught up. Updates arriving while it is too
+/// far behind are dropped and counted in the log.
+pub async fn export_books(hub: BookHub, symbols: Vec<String>, mut writer: Box<dyn BookWriter>) -> Result<(), ExportError> {
+    let mut subscriptions = Vec::with_capacity(symbols.len());
+    for symbol in &symbols {
+        let subscription = hub
+            .subscribe(symbol, hub.config().max_depth_of(symbol), EXPORT_QUEUE_CAPACITY, OverflowPolicy::DropOldest)
+            .await?;
+        subscriptions.push(subscription);
+    }
+
+    let (tx, rx) = mpsc::sync_channel::<Arc<MergedBook>>(WRITE_QUEUE_CAPACITY);
+    let dropped = Arc::new(AtomicU64::new(0));
+    let missed = dropped.clone();
+    let writer_thread = std::thread::spawn(move || -> io::Result<()> {
+        while let Ok(book) = rx.recv() {
+            writer.write(&book)?;
+            for book in rx.try_iter() {
+                writer.write(&book)?;
+            }
+            writer.flush()?;
+            let missed = missed.swap(0, Ordering::Relaxed);
+            if missed > 0 {
+                warn!("{missed} updates were not exported as the export fell behind");
+            }
+        }
+        Ok(())
+    });
+
+    let mut events = select_all(subscriptions);
+    loop {
+        tokio::select! {
+            _ = hub.shutting_down() => break,
+            event = events.next() => match event {
+                Some(BookEvent::Update(book)) => match tx.try_send(book) {
+                    Ok(()) => {}
+                    Err(TrySendError::Full(_)) => {
+                        dropped.fetch_add(1, Ordering::Relaxed);
+                    }
+                    // The writer has stopped on an error, which joining it reports
+                    Err(TrySendError::Disconnected(_)) => break,
+                },
+                Some(_) => continue,
+                None => break,
+            },
+        }
+    }
+    // Closing the queue lets the writer finish what it holds and flush
+    drop(tx);
+    match tokio::task::spawn_blocking(move || writer_thread.join()).await {
+        Ok(Ok(result)) => Ok(result?),
+        _ => Err(io::Error::other("the export writer panicked").into()),
+    }
+}
+
+/// Merges the frames recorded for `symbol` as the server would and writes every update, returning how many there were.
+///
+/// Each book is stamped with when the frame that updated it was received.
+pub async fn export_replay(
+    session: &ReplaySession,
+    symbol: &str,
+    max_depth: usize,
+    writer: &mut dyn BookWriter,
+) -> Result<u64, Box<dyn Error>> {
+    let mut orderbook = OrderbookBuilder::<Empty>::new()
+        .with_max_depth(max_depth)
+        .with_symbol(symbol)
+        .with_replay(session)
+        .build::<HeapedBook>()
+        .await?;
+    let stream = orderbook.collect();
+    pin_mut!(stream);
+    let mut sequence = 0;
+    // The book ends with an error once the frames run out, so an error is only reported if an update follows it
+    let mut pending_error = None;
+    while let Some(event) = stream.next().await {
+        let (bids, asks) = match event {
+            Ok(levels) => levels,
+            Err(e) => {
+                pending_error = Some(e);
+                continue;
+            }
+        };
+        if let Some(e) = pending_error.take() {
+            warn!("skipped a recorded frame of {symbol}: {e}");
+        }
+        sequence += 1;
+        writer.write(&MergedBook {
+            symbol: symbol.to_string(),
+            sequence,
+            timestamp: session.replayed_at().unwrap_or(UNIX_EPOCH),
+            bids,
+            asks,
+            reserve_bids: Vec::new(),
+            reserve_asks: Vec::new(),
+            delayed: Vec::new(),
+        })?;
+    }
+    writer.flush()?;
+    Ok(sequence)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::{
+        exchanges::ExchangeType,
+        hub::{BookFixture, MergedBook},
+    };
+
+    use super::{read_summaries, BinaryWriter, BookWriter, CsvWriter};
+
+    fn book(sequence: u64) -> MergedBook {
+        BookFixture::new()
+            .sequence(sequence)
+            .timestamp(UNIX_EPOCH + Duration::from_millis(1_500))
+            .bid(0.07, 1.0, ExchangeType::Binance)
+            .bid(0.06, 2.0, ExchangeType::Bitstamp)
+            .ask(0.08, 3.0, ExchangeType::Bitstamp)
+            .build()
+    }
+
+    #[test]
+    fn test_csv_writer() {
+        let mut out = Vec::new();
+        let mut writer = CsvWriter::new(&mut out);
+        writer.write(&book(1)).unwrap();
+        writer.write(&book(2)).unwrap();
+        let csv = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], CsvWriter::<Vec<u8>>::HEADER);
+        assert_eq!(lines[2], "1500,1,ethbtc,bid,1,Bitstamp,0.06,2");
+        assert_eq!(lines[3], "1500,1,ethbtc,ask,0,Bitstamp,0.08,3");
+    }
+
+    #[test]
+    fn test_binary_writer() {
+        let mut out = Vec::new();
+        let mut writer = BinaryWriter::new(&mut out);
+        writer.write(&book(1)).unwrap();
+        writer.write(&book(2)).unwrap();
+        let summaries = read_summaries(&out).unwrap();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[1].sequence, 2);
+        assert_eq!(summaries[1].timestamp_ms, 1_500);
+        assert_eq!(summaries[1].bids[1].exchange, "Bitstamp");
+        assert_eq!(summaries[1].asks[0].price, 0.08);
+    }
+}
@@ -0,0 +1,135 @@
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+
+use super::MergedBook;
+
+/// How much of each symbol's past the hub retains, nothing unless either limit is set.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct HistoryLimit {
+    /// Books retained per symbol, the oldest being dropped first, or as many as `max_age` allows when zero
+    pub max_books: usize,
+    /// Age past which books are dropped even if there is room for them
+    pub max_age: Option<Duration>,
+}
+
+impl HistoryLimit {
+    pub fn is_enabled(&self) -> bool {
+        self.max_books > 0 || self.max_age.is_some()
+    }
+}
+
+/// The recent updates of a symbol's merged book, oldest first.
+#[derive(Debug, Clone)]
+pub struct BookHistory {
+    limit: HistoryLimit,
+    books: VecDeque<Arc<MergedBook>>,
+}
+
+impl BookHistory {
+    pub fn new(limit: HistoryLimit) -> Self {
+        Self {
+            limit,
+            books: VecDeque::new(),
+        }
+    }
+
+    pub fn push(&mut self, book: Arc<MergedBook>) {
+        if !self.limit.is_enabled() {
+            return;
+        }
+        let now = book.timestamp;
+        self.books.push_back(book);
+        while self.limit.max_books > 0 && self.books.len() > self.limit.max_books {
+            self.books.pop_front();
+        }
+        if let Some(start) = self.limit.max_age.and_then(|max_age| now.checked_sub(max_age)) {
+            // The book in effect when the retained period starts is kept, so that it can still be asked for
+            while self.books.get(1).is_some_and(|next| next.timestamp <= start) {
+                self.books.pop_front();
+            }
+        }
+    }
+
+    /// The book as it stood at `at`, if it was merged within the retained period.
+    pub fn at(&self, at: SystemTime) -> Option<Arc<MergedBook>> {
+        let after = self.books.partition_point(|book| book.timestamp <= at);
+        after.checked_sub(1).map(|index| self.books[index].clone())
+    }
+
+    /// The book as it stood at `from`, followed by every update up to and including `to`.
+    pub fn range(&self, from: SystemTime, to: SystemTime) -> Vec<Arc<MergedBook>> {
+        let start = self.books.partition_point(|book| book.timestamp <= from).saturating_sub(1);
+        self.books
+            .iter()
+            .skip(start)
+            .take_while(|book| book.timestamp <= to)
+            .cloned()
+            .collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.books.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.books.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::Arc,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use crate::hub::{BookFixture, MergedBook};
+
+    use super::{BookHistory, HistoryLimit};
+
+    fn book(sequence: u64) -> Arc<MergedBook> {
+        Arc::new(
+            BookFixture::new()
+                .sequence(sequence)
+                .timestamp(UNIX_EPOCH + Duration::from_secs(sequence * 10))
+                .build(),
+        )
+    }
+
+    #[test]
+    fn test_history() {
+        let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(secs);
+        let mut history = BookHistory::new(HistoryLimit {
+            max_books: 4,
+            max_age: Some(Duration::from_secs(15)),
+        });
+        for sequence in 1..=5 {
+            history.push(book(sequence));
+        }
+        // Book 1 made way for book 5, and book 2 was replaced before the last 15s began
+        assert_eq!(history.len(), 3);
+        assert!(history.at(at(29)).is_none());
+        assert_eq!(history.at(at(35)).unwrap().sequence, 3);
+        assert_eq!(history.at(at(50)).unwrap().sequence, 5);
+
+        let range: Vec<u64> = history.range(at(35), at(45)).iter().map(|book| book.sequence).collect();
+        assert_eq!(range, vec![3, 4]);
+
+        // Bounded by age alone
+        let mut aged = BookHistory::new(HistoryLimit {
+            max_books: 0,
+            max_age: Some(Duration::from_secs(15)),
+        });
+        for sequence in 1..=5 {
+            aged.push(book(sequence));
+        }
+        assert_eq!(aged.len(), 3);
+
+        let mut disabled = BookHistory::new(HistoryLimit::default());
+        disabled.push(book(1));
+        assert!(disabled.is_empty());
+    }
+}
@@ -0,0 +1,832 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+    time::{Duration, Instant, SystemTime},
+};
+
+pub mod history;
+
+use futures::{pin_mut, Stream, StreamExt};
+use thiserror::Error;
+use tokio::sync::{watch, Notify};
+
+use crate::exchanges::{
+    status::{ReconnectPolicy, VenueMonitor},
+    ExchangeType,
+};
+use crate::orderbook::{
+    builder::{Empty, OrderbookBuilder},
+    fees::FeeSchedule,
+    leadership::{LeadershipTracker, LeadershipWindow},
+    levels::{AskLevel, BidLevel},
+    streaming_book::HeapedBook,
+    summary::BookStatistics,
+    synthetic::{self, SyntheticSymbol},
+    Orderbook,
+};
+use crate::queue::{self, OverflowPolicy, QueueMetrics, QueueReceiver, QueueSender};
+use history::{BookHistory, HistoryLimit};
+
+#[derive(Error, Debug)]
+pub enum HubError {
+    #[error("{0} is not one of the symbols served")]
+    UnknownSymbol(String),
+    #[error("could not start the {symbol} orderbook: {reason}")]
+    StartFailed { symbol: String, reason: String },
+    #[error("the server is shutting down")]
+    ShuttingDown,
+    #[error("no {0} book arrived from the exchanges in time")]
+    NoBook(String),
+}
+
+/// How long a book fetched with `BookHub::current` is kept connected after it was last fetched
+const CURRENT_BOOK_LINGER: Duration = Duration::from_secs(60);
+/// How long `BookHub::current` waits for the first book of a newly connected symbol
+const FIRST_BOOK_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often `BookHub::current` checks whether the first book has arrived
+const FIRST_BOOK_POLL: Duration = Duration::from_millis(50);
+
+/// A merged book as published to every subscriber of a symbol.
+#[derive(Debug, Clone)]
+pub struct MergedBook {
+    pub symbol: String,
+    /// Increments with every update of the book, a gap means the subscriber dropped updates
+    pub sequence: u64,
+    pub timestamp: SystemTime,
+    /// Ordered best deal first
+    pub bids: Vec<BidLevel>,
+    /// Ordered best deal first
+    pub asks: Vec<AskLevel>,
+    /// Bids quoted worse than `bids` that may still rank among them after taker fees, ordered best deal first
+    pub reserve_bids: Vec<BidLevel>,
+    /// Asks quoted worse than `asks` that may still rank among them after taker fees, ordered best deal first
+    pub reserve_asks: Vec<AskLevel>,
+    /// Venues whose latency had risen past the hub's threshold when the book was merged
+    pub delayed: Vec<ExchangeType>,
+}
+
+impl MergedBook {
+    /// At most `depth` of the best levels on each side.
+    pub fn truncated(&self, depth: usize) -> (&[BidLevel], &[AskLevel]) {
+        (
+            &self.bids[..depth.min(self.bids.len())],
+            &self.asks[..depth.min(self.asks.len())],
+        )
+    }
+
+    /// The book with its levels ranked by price after each venue's taker fee, still reporting the quoted prices.
+    ///
+    /// Implied levels are left out, as their price does not account for the fees of the two trades behind them.
+    pub fn fee_adjusted(&self, fees: &FeeSchedule) -> MergedBook {
+        let mut book = self.clone();
+        let (depth_bids, depth_asks) = (book.bids.len(), book.asks.len());
+        book.bids.append(&mut book.reserve_bids);
+        book.asks.append(&mut book.reserve_asks);
+        book.bids.retain(|level| level.exchange != ExchangeType::Synthetic);
+        book.asks.retain(|level| level.exchange != ExchangeType::Synthetic);
+        fees.rank(&mut book.bids, &mut book.asks);
+        book.bids.truncate(depth_bids);
+        book.asks.truncate(depth_asks);
+        book
+    }
+
+    /// Statistics over at most `depth` of the best levels on each side.
+    pub fn statistics(&self, depth: usize) -> BookStatistics {
+        let (bids, asks) = self.truncated(depth);
+        BookStatistics::new(bids, asks)
+    }
+}
+
+/// Builds books by hand for tests, an empty ETH/BTC book published at the epoch unless told otherwise.
+#[cfg(test)]
+pub(crate) struct BookFixture {
+    book: MergedBook,
+}
+
+#[cfg(test)]
+impl BookFixture {
+    pub(crate) fn new() -> Self {
+        BookFixture {
+            book: MergedBook {
+                symbol: String::from("ethbtc"),
+                sequence: 1,
+                timestamp: std::time::UNIX_EPOCH,
+                bids: Vec::new(),
+                asks: Vec::new(),
+                reserve_bids: Vec::new(),
+                reserve_asks: Vec::new(),
+                delayed: Vec::new(),
+            },
+        }
+    }
+
+    pub(crate) fn sequence(mut self, sequence: u64) -> Self {
+        self.book.sequence = sequence;
+        self
+    }
+
+    pub(crate) fn timestamp(mut self, timestamp: SystemTime) -> Self {
+        self.book.timestamp = timestamp;
+        self
+    }
+
+    /// Adds a bid below those already added.
+    pub(crate) fn bid(mut self, price: f64, amount: f64, exchange: ExchangeType) -> Self {
+        self.book.bids.push(BidLevel::new(price, amount, exchange));
+        self
+    }
+
+    /// Adds an ask above those already added.
+    pub(crate) fn ask(mut self, price: f64, amount: f64, exchange: ExchangeType) -> Self {
+        self.book.asks.push(AskLevel::new(price, amount, exchange));
+        self
+    }
+
+    /// Adds a bid below those already added that is kept in reserve.
+    pub(crate) fn reserve_bid(mut self, price: f64, amount: f64, exchange: ExchangeType) -> Self {
+        self.book.reserve_bids.push(BidLevel::new(price, amount, exchange));
+        self
+    }
+
+    pub(crate) fn delayed(mut self, exchange: ExchangeType) -> Self {
+        self.book.delayed.push(exchange);
+        self
+    }
+
+    pub(crate) fn build(self) -> MergedBook {
+        self.book
+    }
+}
+
+/// What a subscriber to a symbol receives.
+#[derive(Debug, Clone)]
+pub enum BookEvent {
+    Update(Arc<MergedBook>),
+    /// An exchange feed reported an error, the book carries on with what it has
+    FeedError(String),
+    /// The subscriber fell behind a `Disconnect` queue and will receive nothing further
+    Overflowed,
+}
+
+/// How the hub connects to exchanges for each symbol.
+#[derive(Clone)]
+pub struct HubConfig {
+    /// Symbols that may be subscribed to, the first is the default
+    pub symbols: Vec<String>,
+    pub exchanges: Vec<ExchangeType>,
+    pub max_depth: usize,
+    pub reconnect: ReconnectPolicy,
+    /// Fees charged by each venue, used by analytics that trade across venues
+    pub fees: FeeSchedule,
+    /// Symbols whose books also carry the levels implied by two other symbols' books
+    pub synthetics: Vec<SyntheticSymbol>,
+    /// How far above its usual a venue's latency may rise before its levels are flagged as delayed
+    pub latency_threshold: Option<Duration>,
+    /// How many of each symbol's past books are retained for time-travel queries
+    pub history: HistoryLimit,
+    /// Symbols merged from other venues or to another depth than `exchanges` and `max_depth`
+    pub overrides: HashMap<String, SymbolOverride>,
+}
+
+impl HubConfig {
+    /// The venues whose books are merged into the symbol's.
+    pub fn exchanges_of(&self, symbol: &str) -> &[ExchangeType] {
+        self.overrides
+            .get(symbol)
+            .and_then(|symbol| symbol.exchanges.as_deref())
+            .unwrap_or(&self.exchanges)
+    }
+
+    /// The depth the symbol's book is merged to.
+    pub fn max_depth_of(&self, symbol: &str) -> usize {
+        self.overrides
+            .get(symbol)
+            .and_then(|symbol| symbol.max_depth)
+            .unwrap_or(self.max_depth)
+    }
+}
+
+/// The venues and depth of a symbol, each defaulting to the hub's own when absent.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SymbolOverride {
+    pub exchanges: Option<Vec<ExchangeType>>,
+    pub max_depth: Option<usize>,
+}
+
+#[derive(Default)]
+struct Subscribers {
+    senders: HashMap<u64, QueueSender<BookEvent>>,
+    /// Set once the last subscriber has left, after which the book may not be joined
+    stopped: bool,
+}
+
+/// The shared state of a single symbol's merged book.
+#[derive(Default)]
+struct Book {
+    subscribers: Mutex<Subscribers>,
+    latest: Mutex<Option<Arc<MergedBook>>>,
+    leadership: Mutex<LeadershipTracker>,
+    stop: Notify,
+}
+
+impl Book {
+    /// Ends every subscription and refuses new ones, so that anyone still joining starts a fresh book.
+    fn close(&self) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.stopped = true;
+        // Dropping the senders ends every remaining subscription
+        subscribers.senders.clear();
+    }
+
+    fn publish(&self, event: BookEvent) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.senders.retain(|_, tx| match tx.push(event.clone()) {
+            Ok(()) => true,
+            Err(queue::QueueError::Overflow) => {
+                tx.close_with(BookEvent::Overflowed);
+                false
+            }
+            Err(_) => false,
+        });
+    }
+}
+
+struct HubInner {
+    config: HubConfig,
+    monitor: VenueMonitor,
+    books: Mutex<HashMap<String, Arc<Book>>>,
+    /// When each symbol kept connected by `BookHub::current` was last fetched
+    fetched: Mutex<HashMap<String, Instant>>,
+    /// Retained past books of each symbol, kept when a book stops so that it can still be asked for
+    histories: Mutex<HashMap<String, BookHistory>>,
+    next_id: AtomicU64,
+    shutdown: watch::Sender<bool>,
+}
+
+/// Runs a single merged book per symbol, shared by every subscriber regardless of how they are served.
+///
+/// A symbol's exchange feeds are connected when it gains its first subscriber and closed when it loses its last.
+#[derive(Clone)]
+pub struct BookHub {
+    inner: Arc<HubInner>,
+}
+
+impl BookHub {
+    pub fn new(config: HubConfig) -> Self {
+        Self {
+            inner: Arc::new(HubInner {
+                config,
+                monitor: VenueMonitor::new(),
+                books: Mutex::new(HashMap::new()),
+                fetched: Mutex::new(HashMap::new()),
+                histories: Mutex::new(HashMap::new()),
+                next_id: AtomicU64::new(0),
+                shutdown: watch::channel(false).0,
+            }),
+        }
+    }
+
+    pub fn config(&self) -> &HubConfig {
+        &self.inner.config
+    }
+
+    /// The health of the exchange feeds behind every book.
+    pub fn venues(&self) -> &VenueMonitor {
+        &self.inner.monitor
+    }
+
+    /// The symbol served to clients that do not ask for one.
+    pub fn default_symbol(&self) -> &str {
+        &self.inner.config.symbols[0]
+    }
+
+    /// Resolves a requested symbol against those served, returning the canonical spelling.
+    pub fn resolve_symbol(&self, symbol: &str) -> Result<String, HubError> {
+        self.inner
+            .config
+            .symbols
+            .iter()
+            .find(|served| served.eq_ignore_ascii_case(symbol))
+            .cloned()
+            .ok_or_else(|| HubError::UnknownSymbol(symbol.to_string()))
+    }
+
+    /// The most recent book of a symbol, if anyone is subscribed to it.
+    pub fn latest(&self, symbol: &str) -> Option<Arc<MergedBook>> {
+        let book = self.inner.books.lock().unwrap().get(symbol).cloned()?;
+        let latest = book.latest.lock().unwrap().clone();
+        latest
+    }
+
+    /// Which venues have led the top of a symbol's book over each rolling window, if anyone is subscribed to it.
+    pub fn leadership(&self, symbol: &str) -> Option<Vec<LeadershipWindow>> {
+        let book = self.inner.books.lock().unwrap().get(symbol).cloned()?;
+        let windows = book.leadership.lock().unwrap().windows(SystemTime::now());
+        Some(windows)
+    }
+
+    /// The book of a symbol as it stood at `at`, if the hub retained it.
+    pub fn book_at(&self, symbol: &str, at: SystemTime) -> Option<Arc<MergedBook>> {
+        self.inner.histories.lock().unwrap().get(symbol)?.at(at)
+    }
+
+    /// The retained book of a symbol as it stood at `from`, followed by every retained update up to `to`.
+    pub fn history(&self, symbol: &str, from: SystemTime, to: SystemTime) -> Vec<Arc<MergedBook>> {
+        match self.inner.histories.lock().unwrap().get(symbol) {
+            Some(history) => history.range(from, to),
+            None => Vec::new(),
+        }
+    }
+
+    /// Keeps the book of `symbol` connected until the hub shuts down, so that its history is retained throughout.
+    pub async fn retain_history(&self, symbol: String) -> Result<(), HubError> {
+        let mut subscription = self.subscribe(&symbol, 1, 1, OverflowPolicy::Conflate).await?;
+        loop {
+            tokio::select! {
+                _ = self.shutting_down() => return Ok(()),
+                // Updates are only read to keep the queue moving
+                event = subscription.next() => if event.is_none() {
+                    return Ok(());
+                },
+            }
+        }
+    }
+
+    /// The latest merged book of `symbol`, connecting its exchange feeds if nobody is subscribed to it.
+    ///
+    /// Books connected this way are kept connected until `CURRENT_BOOK_LINGER` passes without another
+    /// fetch, so that polling clients are answered from the shared book.
+    pub async fn current(&self, symbol: &str) -> Result<Arc<MergedBook>, HubError> {
+        let symbol = self.resolve_symbol(symbol)?;
+        let connect = self
+            .inner
+            .fetched
+            .lock()
+            .unwrap()
+            .insert(symbol.clone(), Instant::now())
+            .is_none();
+        if connect {
+            if let Err(e) = self.keep_connected(&symbol).await {
+                self.inner.fetched.lock().unwrap().remove(&symbol);
+                return Err(e);
+            }
+        }
+        let waited = tokio::time::timeout(FIRST_BOOK_TIMEOUT, async {
+            loop {
+                if let Some(book) = self.latest(&symbol) {
+                    return book;
+                }
+                tokio::time::sleep(FIRST_BOOK_POLL).await;
+            }
+        })
+        .await;
+        waited.map_err(|_| HubError::NoBook(symbol))
+    }
+
+    /// Subscribes to the merged book of `symbol`, starting its exchange feeds if nobody else is subscribed.
+    ///
+    /// The latest book, if there is one, is the first event received. Updates are queued according to
+    /// `capacity` and `policy`. Background tasks such as exports, alerts and persistence subscribe like any client, so
+    /// the book stays connected for as long as any of them holds its subscription.
+    pub async fn subscribe(
+        &self,
+        symbol: &str,
+        depth: usize,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Result<BookSubscription, HubError> {
+        if self.is_shutting_down() {
+            return Err(HubError::ShuttingDown);
+        }
+        let symbol = self.resolve_symbol(symbol)?;
+        self.join(&symbol, depth, capacity, policy).await
+    }
+
+    /// Subscribes to the merged book of a symbol that may not be served to clients, such as a synthetic leg.
+    async fn join(
+        &self,
+        symbol: &str,
+        depth: usize,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Result<BookSubscription, HubError> {
+        let symbol = symbol.to_string();
+        loop {
+            let (book, start) = {
+                let mut books = self.inner.books.lock().unwrap();
+                match books.get(&symbol) {
+                    Some(book) => (book.clone(), false),
+                    None => {
+                        let book = Arc::new(Book::default());
+                        books.insert(symbol.clone(), book.clone());
+                        (book, true)
+                    }
+                }
+            };
+            let subscription = match self.attach(&book, &symbol, depth, capacity, policy) {
+                Some(subscription) => subscription,
+                // The book stopped as we joined it, try again with a fresh one
+                None => continue,
+            };
+            if start {
+                self.start(&symbol, book).await?;
+            }
+            return Ok(subscription);
+        }
+    }
+
+    /// Stops accepting subscriptions, letting consumers end their streams.
+    pub fn shutdown(&self) {
+        self.inner.shutdown.send_replace(true);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        *self.inner.shutdown.borrow()
+    }
+
+    /// Receives a change once `shutdown` is called.
+    pub fn shutdown_signal(&self) -> watch::Receiver<bool> {
+        self.inner.shutdown.subscribe()
+    }
+
+    /// Completes once `shutdown` has been called.
+    pub async fn shutting_down(&self) {
+        let mut shutdown = self.shutdown_signal();
+        while !*shutdown.borrow() {
+            if shutdown.changed().await.is_err() {
+                return;
+            }
+        }
+    }
+
+    /// Holds a subscription to `symbol` until it has not been fetched for `CURRENT_BOOK_LINGER`.
+    async fn keep_connected(&self, symbol: &str) -> Result<(), HubError> {
+        let mut subscription = self
+            .subscribe(symbol, self.inner.config.max_depth_of(symbol), 1, OverflowPolicy::Conflate)
+            .await?;
+        let hub = self.clone();
+        let symbol = symbol.to_string();
+        tokio::spawn(async move {
+            loop {
+                let last = match hub.inner.fetched.lock().unwrap().get(&symbol) {
+                    Some(last) => *last,
+                    None => break,
+                };
+                let expiry = tokio::time::Instant::from_std(last + CURRENT_BOOK_LINGER);
+                let released = tokio::select! {
+                    _ = hub.shutting_down() => true,
+                    _ = tokio::time::sleep_until(expiry) => {
+                        !hub.inner
+                            .fetched
+                            .lock()
+                            .unwrap()
+                            .get(&symbol)
+                            .is_some_and(|last| last.elapsed() < CURRENT_BOOK_LINGER)
+                    }
+                    // Updates are only read to keep the queue moving, fetches use the latest book
+                    event = subscription.next() => event.is_none(),
+                };
+                if released {
+                    hub.inner.fetched.lock().unwrap().remove(&symbol);
+                    break;
+                }
+            }
+            debug!("released the {symbol} orderbook");
+        });
+        Ok(())
+    }
+
+    fn attach(
+        &self,
+        book: &Arc<Book>,
+        symbol: &str,
+        depth: usize,
+        capacity: usize,
+        policy: OverflowPolicy,
+    ) -> Option<BookSubscription> {
+        let (tx, rx) = queue::bounded(capacity, policy);
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut subscribers = book.subscribers.lock().unwrap();
+        if subscribers.stopped {
+            return None;
+        }
+        if let Some(latest) = book.latest.lock().unwrap().clone() {
+            // Cannot overflow an empty queue
+            let _ = tx.push(BookEvent::Update(latest));
+        }
+        subscribers.senders.insert(id, tx);
+        Some(BookSubscription {
+            id,
+            symbol: symbol.to_string(),
+            depth: depth.min(self.inner.config.max_depth_of(symbol)),
+            receiver: rx,
+            book: book.clone(),
+            hub: self.clone(),
+        })
+    }
+
+    /// Connects the exchange feeds of a newly created book and publishes its updates until it is stopped.
+    async fn start(&self, symbol: &str, book: Arc<Book>) -> Result<(), HubError> {
+        let config = &self.inner.config;
+        // Each venue's best levels are kept so books can be ranked by their price after fees
+        let merged_depth = config.max_depth_of(symbol) * config.exchanges_of(symbol).len().max(1);
+        let orderbook = OrderbookBuilder::<Empty>::new()
+            .with_max_depth(config.max_depth_of(symbol))
+            .with_symbol(symbol)
+            .with_exchanges(config.exchanges_of(symbol))
+            .with_merged_depth(merged_depth)
+            .with_venue_monitor(self.inner.monitor.clone())
+            .with_reconnect_policy(config.reconnect)
+            .build::<HeapedBook>()
+            .await
+            .map_err(|e| e.to_string());
+        let mut orderbook = match orderbook {
+            Ok(orderbook) => orderbook,
+            Err(reason) => {
+                error!("{symbol} orderbook could not be created: {reason}");
+                // Anyone who joined in the meantime sees their stream end
+                book.close();
+                self.remove(symbol, &book);
+                return Err(HubError::StartFailed {
+                    symbol: symbol.to_string(),
+                    reason,
+                });
+            }
+        };
+        info!("{symbol} orderbook initialised");
+        let mut legs = match config.synthetics.iter().find(|synthetic| synthetic.symbol == symbol) {
+            Some(synthetic) => match self.legs(synthetic).await {
+                Ok(legs) => Some(legs),
+                Err(e) => {
+                    warn!("{symbol} is served without its synthetic levels: {e}");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let hub = self.clone();
+        let symbol = symbol.to_string();
+        let max_depth = config.max_depth_of(&symbol);
+        let latency_threshold = config.latency_threshold;
+        let history = config.history;
+        tokio::spawn(async move {
+            let orderbook_stream = orderbook.collect();
+            pin_mut!(orderbook_stream);
+            let mut sequence = 0;
+            // The levels quoted by the exchanges, merged with the synthetic levels whenever either changes
+            let mut direct: Option<(Vec<BidLevel>, Vec<AskLevel>)> = None;
+            loop {
+                tokio::select! {
+                    _ = book.stop.notified() => break,
+                    event = orderbook_stream.next() => match event {
+                        Some(Ok(levels)) => direct = Some(levels),
+                        Some(Err(e)) => {
+                            error!("{symbol} orderbook returned an error: {e}");
+                            book.publish(BookEvent::FeedError(e.to_string()));
+                            continue;
+                        }
+                        None => break,
+                    },
+                    updated = async {
+                        match legs.as_mut() {
+                            Some(legs) => legs.update().await,
+                            None => std::future::pending().await,
+                        }
+                    } => {
+                        if !updated {
+                            warn!("a synthetic leg of {symbol} closed, it is served without its synthetic levels");
+                            legs = None;
+                        }
+                    }
+                }
+                let (mut bids, mut asks) = match &direct {
+                    Some(direct) => direct.clone(),
+                    None => continue,
+                };
+                if let Some((implied_bids, implied_asks)) = legs.as_ref().and_then(|legs| legs.implied(max_depth)) {
+                    synthetic::merge(&mut bids, &mut asks, implied_bids, implied_asks, merged_depth);
+                }
+                let reserve_bids = bids.split_off(max_depth.min(bids.len()));
+                let reserve_asks = asks.split_off(max_depth.min(asks.len()));
+                sequence += 1;
+                let delayed = match latency_threshold {
+                    Some(threshold) => hub.inner.monitor.delayed(&symbol, threshold),
+                    None => Vec::new(),
+                };
+                let merged = Arc::new(MergedBook {
+                    symbol: symbol.clone(),
+                    sequence,
+                    timestamp: SystemTime::now(),
+                    bids,
+                    asks,
+                    reserve_bids,
+                    reserve_asks,
+                    delayed,
+                });
+                book.leadership
+                    .lock()
+                    .unwrap()
+                    .update(merged.timestamp, &merged.bids, &merged.asks);
+                if history.is_enabled() {
+                    hub.inner
+                        .histories
+                        .lock()
+                        .unwrap()
+                        .entry(symbol.clone())
+                        .or_insert_with(|| BookHistory::new(history))
+                        .push(merged.clone());
+                }
+                *book.latest.lock().unwrap() = Some(merged.clone());
+                book.publish(BookEvent::Update(merged));
+            }
+            info!("{symbol} orderbook closed");
+            book.close();
+            hub.remove(&symbol, &book);
+        });
+        Ok(())
+    }
+
+    /// Subscribes to the books of both legs of a synthetic symbol, keeping only their latest update.
+    async fn legs(&self, synthetic: &SyntheticSymbol) -> Result<SyntheticLegs, HubError> {
+        let config = &self.inner.config;
+        // Boxed as starting a leg's book is recursive
+        let base = Box::pin(self.join(&synthetic.base_leg, config.max_depth_of(&synthetic.base_leg), 1, OverflowPolicy::Conflate)).await?;
+        let quote = Box::pin(self.join(&synthetic.quote_leg, config.max_depth_of(&synthetic.quote_leg), 1, OverflowPolicy::Conflate)).await?;
+        Ok(SyntheticLegs {
+            base,
+            quote,
+            base_book: None,
+            quote_book: None,
+        })
+    }
+
+    fn remove(&self, symbol: &str, book: &Arc<Book>) {
+        let mut books = self.inner.books.lock().unwrap();
+        if books.get(symbol).is_some_and(|current| Arc::ptr_eq(current, book)) {
+            books.remove(symbol);
+        }
+    }
+}
+
+/// The books a synthetic symbol's levels are implied from.
+struct SyntheticLegs {
+    base: BookSubscription,
+    quote: BookSubscription,
+    base_book: Option<Arc<MergedBook>>,
+    quote_book: Option<Arc<MergedBook>>,
+}
+
+impl SyntheticLegs {
+    /// Waits for either leg to update, returning false once either has closed.
+    async fn update(&mut self) -> bool {
+        let (event, is_base) = tokio::select! {
+            event = self.base.next() => (event, true),
+            event = self.quote.next() => (event, false),
+        };
+        match event {
+            Some(BookEvent::Update(leg)) if is_base => self.base_book = Some(leg),
+            Some(BookEvent::Update(leg)) => self.quote_book = Some(leg),
+            Some(_) => {}
+            None => return false,
+        }
+        true
+    }
+
+    /// The levels implied by the latest books of both legs, once both have arrived.
+    fn implied(&self, max_depth: usize) -> Option<(Vec<BidLevel>, Vec<AskLevel>)> {
+        let (base, quote) = (self.base_book.as_ref()?, self.quote_book.as_ref()?);
+        Some((
+            synthetic::implied_bids(&base.bids, &quote.asks, max_depth),
+            synthetic::implied_asks(&base.asks, &quote.bids, max_depth),
+        ))
+    }
+}
+
+/// A subscription to a symbol's merged book, the book is closed when its last subscription is dropped.
+pub struct BookSubscription {
+    id: u64,
+    symbol: String,
+    depth: usize,
+    receiver: QueueReceiver<BookEvent>,
+    book: Arc<Book>,
+    hub: BookHub,
+}
+
+impl BookSubscription {
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// The number of levels the subscriber asked for on each side, capped at the hub's depth.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    pub fn metrics(&self) -> QueueMetrics {
+        self.receiver.metrics()
+    }
+}
+
+impl Stream for BookSubscription {
+    type Item = BookEvent;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.receiver).poll_next(cx)
+    }
+}
+
+impl Drop for BookSubscription {
+    fn drop(&mut self) {
+        let mut subscribers = self.book.subscribers.lock().unwrap();
+        subscribers.senders.remove(&self.id);
+        if subscribers.senders.is_empty() && !subscribers.stopped {
+            subscribers.stopped = true;
+            drop(subscribers);
+            self.hub.remove(&self.symbol, &self.book);
+            // Stores a permit if the book is still starting, so it stops as soon as it runs
+            self.book.stop.notify_one();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, sync::Arc};
+
+    use futures::StreamExt;
+
+    use crate::{
+        exchanges::{status::ReconnectPolicy, ExchangeType},
+        orderbook::fees::{FeeSchedule, Fees},
+        queue::OverflowPolicy,
+    };
+
+    use super::{history::HistoryLimit, BookFixture, BookHub, HubConfig};
+
+    /// A hub whose books have no venues, so each closes as soon as it starts.
+    fn hub() -> BookHub {
+        BookHub::new(HubConfig {
+            symbols: vec![String::from("ethbtc")],
+            exchanges: Vec::new(),
+            max_depth: 10,
+            reconnect: ReconnectPolicy::default(),
+            fees: FeeSchedule::default(),
+            synthetics: Vec::new(),
+            latency_threshold: None,
+            history: HistoryLimit::default(),
+            overrides: HashMap::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_join_closed_book() {
+        let hub = hub();
+        let mut subscription = hub.subscribe("ethbtc", 10, 4, OverflowPolicy::Conflate).await.unwrap();
+        // Held as by a join that found the book just before it closed
+        let book = hub.inner.books.lock().unwrap().get("ethbtc").unwrap().clone();
+        while subscription.next().await.is_some() {}
+
+        // Attaching to the closed book fails, so the join retries with a fresh one rather than waiting forever
+        assert!(hub.attach(&book, "ethbtc", 10, 4, OverflowPolicy::Conflate).is_none());
+        let mut rejoined = hub.subscribe("ethbtc", 10, 4, OverflowPolicy::Conflate).await.unwrap();
+        assert!(!Arc::ptr_eq(&rejoined.book, &book));
+        while rejoined.next().await.is_some() {}
+    }
+
+    #[test]
+    fn test_fee_adjusted() {
+        let fees = FeeSchedule::default().with_fees(
+            ExchangeType::Bitstamp,
+            Fees {
+                maker: 0.0,
+                taker: 0.01,
+            },
+        );
+        let book = BookFixture::new()
+            .bid(100.0, 1.0, ExchangeType::Bitstamp)
+            .reserve_bid(99.5, 1.0, ExchangeType::Binance)
+            .build();
+        // Binance's bid was quoted past the depth served but is the better deal after Bitstamp's 1% fee
+        let adjusted = book.fee_adjusted(&fees);
+        assert_eq!(adjusted.bids.len(), 1);
+        assert_eq!(adjusted.bids[0].exchange, ExchangeType::Binance);
+        assert!(adjusted.reserve_bids.is_empty());
+
+        // An implied level would otherwise be ranked as if trading its legs were free
+        let book = BookFixture::new()
+            .bid(100.5, 1.0, ExchangeType::Synthetic)
+            .bid(100.0, 1.0, ExchangeType::Bitstamp)
+            .reserve_bid(99.5, 1.0, ExchangeType::Binance)
+            .build();
+        let adjusted = book.fee_adjusted(&fees);
+        assert_eq!(adjusted.bids.len(), 2);
+        assert_eq!(adjusted.bids[0].exchange, ExchangeType::Binance);
+        assert_eq!(adjusted.bids[1].exchange, ExchangeType::Bitstamp);
+    }
+}
@@ -0,0 +1,268 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::alerts::Alert;
+use crate::exchanges::{
+    status::{LatencySummary, VenueHealth, VenueState},
+    ExchangeType,
+};
+use crate::hub::MergedBook;
+use crate::orderbook::{
+    leadership::LeadershipWindow,
+    levels::{AskLevel, BidLevel},
+    summary::BookStatistics,
+};
+
+/// Milliseconds since the Unix epoch, zero for times before it.
+fn epoch_millis(at: SystemTime) -> u64 {
+    at.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// A single level of the merged book.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct JsonLevel {
+    pub exchange: String,
+    pub price: f64,
+    pub amount: f64,
+    /// Set while the venue's latency has risen past the server's threshold
+    #[serde(default)]
+    pub delayed: bool,
+}
+
+impl JsonLevel {
+    /// Flags the level if its venue was delayed when `book` was merged.
+    fn flagged(mut self, book: &MergedBook) -> Self {
+        self.delayed = book
+            .delayed
+            .iter()
+            .any(|exchange| exchange.to_string() == self.exchange);
+        self
+    }
+}
+
+impl From<&BidLevel> for JsonLevel {
+    fn from(bid: &BidLevel) -> Self {
+        Self {
+            exchange: bid.exchange.to_string(),
+            price: bid.price,
+            amount: bid.amount,
+            delayed: false,
+        }
+    }
+}
+
+impl From<&AskLevel> for JsonLevel {
+    fn from(ask: &AskLevel) -> Self {
+        Self {
+            exchange: ask.exchange.to_string(),
+            price: ask.price,
+            amount: ask.amount,
+            delayed: false,
+        }
+    }
+}
+
+/// The merged book as served over JSON, mirroring the gRPC `Summary`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonBook {
+    pub symbol: String,
+    pub sequence: u64,
+    /// Milliseconds since the Unix epoch at which the book was merged
+    pub timestamp: u64,
+    pub spread: Option<f64>,
+    pub mid_price: Option<f64>,
+    pub microprice: Option<f64>,
+    pub total_bid_volume: f64,
+    pub total_ask_volume: f64,
+    pub bids: Vec<JsonLevel>,
+    pub asks: Vec<JsonLevel>,
+}
+
+impl JsonBook {
+    /// Represents at most `depth` of the best levels on each side of `book`.
+    pub fn new(book: &MergedBook, depth: usize) -> Self {
+        let (bids, asks) = book.truncated(depth);
+        let statistics = BookStatistics::new(bids, asks);
+        Self {
+            symbol: book.symbol.clone(),
+            sequence: book.sequence,
+            timestamp: epoch_millis(book.timestamp),
+            spread: statistics.spread,
+            mid_price: statistics.mid_price,
+            microprice: statistics.microprice,
+            total_bid_volume: statistics.total_bid_volume,
+            total_ask_volume: statistics.total_ask_volume,
+            bids: bids
+                .iter()
+                .map(|bid| JsonLevel::from(bid).flagged(book))
+                .collect(),
+            asks: asks
+                .iter()
+                .map(|ask| JsonLevel::from(ask).flagged(book))
+                .collect(),
+        }
+    }
+}
+
+/// The best bid and ask of a merged book, and the venues quoting them.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonTop {
+    pub symbol: String,
+    pub sequence: u64,
+    /// Milliseconds since the Unix epoch at which the book was merged
+    pub timestamp: u64,
+    pub best_bid: Option<JsonLevel>,
+    pub best_ask: Option<JsonLevel>,
+    pub spread: Option<f64>,
+    pub mid_price: Option<f64>,
+}
+
+impl JsonTop {
+    pub fn new(book: &MergedBook) -> Self {
+        let statistics = book.statistics(1);
+        Self {
+            symbol: book.symbol.clone(),
+            sequence: book.sequence,
+            timestamp: epoch_millis(book.timestamp),
+            best_bid: statistics.best_bid.as_ref().map(|bid| JsonLevel::from(bid).flagged(book)),
+            best_ask: statistics.best_ask.as_ref().map(|ask| JsonLevel::from(ask).flagged(book)),
+            spread: statistics.spread,
+            mid_price: statistics.mid_price,
+        }
+    }
+
+    /// Whether the best levels differ from `other`, ignoring when the books were merged.
+    pub fn moved_from(&self, other: &JsonTop) -> bool {
+        self.best_bid != other.best_bid || self.best_ask != other.best_ask
+    }
+}
+
+/// The health of a single exchange feed as served over JSON.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonVenue {
+    pub exchange: String,
+    pub symbol: String,
+    pub state: VenueState,
+    /// Milliseconds since the Unix epoch of the last message, absent if there has not been one
+    pub last_message: Option<u64>,
+    pub messages: u64,
+    pub message_rate: f64,
+    pub errors: u64,
+    pub last_error: Option<String>,
+    /// The venue's leadership of its symbol's book, empty unless the book is live
+    pub leadership: Vec<JsonLeadership>,
+    /// Absent until a message carrying the venue's own timestamp has arrived
+    pub latency: Option<LatencySummary>,
+}
+
+/// How a venue led the top of its symbol's book over a rolling window.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonLeadership {
+    pub window_seconds: u64,
+    /// Milliseconds of the window the book was live for
+    pub covered: u64,
+    pub best_bid_share: f64,
+    pub best_ask_share: f64,
+    pub bid_moves: u64,
+    pub ask_moves: u64,
+    pub spread_contribution: Option<f64>,
+}
+
+impl JsonVenue {
+    /// Adds the venue's share of each of its symbol's leadership windows.
+    pub fn with_leadership(mut self, windows: &[LeadershipWindow], exchange: ExchangeType) -> Self {
+        self.leadership = windows
+            .iter()
+            .filter_map(|window| {
+                let venue = window.venue(exchange)?;
+                Some(JsonLeadership {
+                    window_seconds: window.window.as_secs(),
+                    covered: window.covered.as_millis() as u64,
+                    best_bid_share: venue.best_bid_share,
+                    best_ask_share: venue.best_ask_share,
+                    bid_moves: venue.bid_moves,
+                    ask_moves: venue.ask_moves,
+                    spread_contribution: venue.spread_contribution,
+                })
+            })
+            .collect();
+        self
+    }
+}
+
+impl From<VenueHealth> for JsonVenue {
+    fn from(health: VenueHealth) -> Self {
+        Self {
+            exchange: health.exchange.to_string(),
+            symbol: health.symbol,
+            state: health.state,
+            last_message: health.last_message.map(epoch_millis),
+            messages: health.messages,
+            message_rate: health.message_rate,
+            errors: health.errors,
+            last_error: health.last_error,
+            leadership: Vec::new(),
+            latency: health.latency,
+        }
+    }
+}
+
+/// An alert firing or resolving, as written to alert sinks.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct JsonAlert {
+    pub rule: String,
+    pub symbol: String,
+    /// Absent for rules on the merged book
+    pub exchange: Option<String>,
+    /// `firing` or `resolved`
+    pub state: String,
+    pub value: Option<f64>,
+    /// Milliseconds since the Unix epoch at which the condition started to hold
+    pub since: u64,
+    /// Milliseconds since the Unix epoch at which the alert changed state
+    pub at: u64,
+}
+
+impl From<&Alert> for JsonAlert {
+    fn from(alert: &Alert) -> Self {
+        Self {
+            rule: alert.rule.clone(),
+            symbol: alert.symbol.clone(),
+            exchange: alert.exchange.map(|exchange| exchange.to_string()),
+            state: alert.state.to_string(),
+            value: alert.value,
+            since: epoch_millis(alert.since),
+            at: epoch_millis(alert.at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exchanges::ExchangeType,
+        hub::{BookFixture, MergedBook},
+    };
+
+    use super::JsonTop;
+
+    fn book(sequence: u64, bid_amount: f64) -> MergedBook {
+        BookFixture::new()
+            .sequence(sequence)
+            .bid(99.0, bid_amount, ExchangeType::Binance)
+            .ask(101.0, 1.0, ExchangeType::Bitstamp)
+            .build()
+    }
+
+    #[test]
+    fn test_top_of_book() {
+        let top = JsonTop::new(&book(1, 1.0));
+        assert_eq!(top.best_bid.as_ref().unwrap().exchange, "Binance");
+        assert_eq!(top.best_ask.as_ref().unwrap().price, 101.0);
+        assert_eq!(top.spread, Some(2.0));
+        assert!(!JsonTop::new(&book(2, 1.0)).moved_from(&top));
+        assert!(JsonTop::new(&book(3, 2.0)).moved_from(&top));
+    }
+}
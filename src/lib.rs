@@ -1,9 +1,21 @@
 #![feature(async_closure)]
 #![feature(return_position_impl_trait_in_trait)]
+pub mod alerts;
+pub mod analytics;
+pub mod client;
+pub mod config;
 pub mod exchanges;
+pub mod export;
+pub mod hub;
+pub mod json;
 pub mod orderbook;
+pub mod persistence;
 pub mod server;
 pub mod cli;
+pub mod queue;
+pub mod rest;
+pub mod trades;
+pub mod websocket;
 
 #[macro_use]
 extern crate log;
@@ -1,9 +1,16 @@
 use std::{collections::HashMap, error::Error};
 use tokio_stream::StreamMap;
 
-use crate::exchanges::{binance::Binance, bitstamp::Bitstamp, Exchange, ExchangeType};
+use crate::exchanges::{
+    replay::ReplaySession,
+    status::{supervise, ReconnectPolicy, VenueMonitor},
+    Exchange, ExchangeType,
+};
 
-use super::Orderbook;
+use super::{
+    fees::{BookOrdering, FeeSchedule},
+    Orderbook,
+};
 
 pub trait OrderbookBuilderState {}
 
@@ -27,6 +34,11 @@ pub struct OrderbookBuilder<State: OrderbookBuilderState> {
     exchanges: HashMap<ExchangeType, Box<dyn Exchange + Send + Sync>>,
     symbol: String,
     max_depth: usize,
+    merged_depth: Option<usize>,
+    monitor: Option<VenueMonitor>,
+    reconnect: ReconnectPolicy,
+    fees: FeeSchedule,
+    ordering: BookOrdering,
     state: std::marker::PhantomData<State>,
 }
 
@@ -36,6 +48,11 @@ impl<S: OrderbookBuilderState> OrderbookBuilder<S> {
             exchanges: HashMap::new(),
             symbol: String::new(),
             max_depth: 0,
+            merged_depth: None,
+            monitor: None,
+            reconnect: ReconnectPolicy::default(),
+            fees: FeeSchedule::default(),
+            ordering: BookOrdering::default(),
             state: std::marker::PhantomData,
         }
     }
@@ -45,6 +62,11 @@ impl<S: OrderbookBuilderState> OrderbookBuilder<S> {
             exchanges: self.exchanges,
             symbol: self.symbol,
             max_depth,
+            merged_depth: self.merged_depth,
+            monitor: self.monitor,
+            reconnect: self.reconnect,
+            fees: self.fees,
+            ordering: self.ordering,
             state: std::marker::PhantomData,
         }
     }
@@ -56,6 +78,11 @@ impl OrderbookBuilder<WithMaxDepth> {
             exchanges: self.exchanges,
             symbol: symbol.into(),
             max_depth: self.max_depth,
+            merged_depth: self.merged_depth,
+            monitor: self.monitor,
+            reconnect: self.reconnect,
+            fees: self.fees,
+            ordering: self.ordering,
             state: std::marker::PhantomData,
         }
     }
@@ -64,31 +91,105 @@ impl OrderbookBuilder<WithMaxDepth> {
 impl OrderbookBuilder<WithSymbol> {
     pub fn with_exchanges(mut self, exchanges: &[ExchangeType]) -> OrderbookBuilder<WithExchange> {
         exchanges.iter().for_each(|exchange| {
-            match exchange {
-                ExchangeType::Bitstamp => self.exchanges.insert(*exchange, Box::new(Bitstamp {})),
-                ExchangeType::Binance => self.exchanges.insert(*exchange, Box::new(Binance {})),
-                _ => None, // throw not supported error
-            };
+            // throw not supported error
+            if let Some(adapter) = crate::exchanges::exchange(*exchange) {
+                self.exchanges.insert(*exchange, adapter);
+            }
         });
 
         OrderbookBuilder {
             exchanges: self.exchanges,
             symbol: self.symbol,
             max_depth: self.max_depth,
+            merged_depth: self.merged_depth,
+            monitor: self.monitor,
+            reconnect: self.reconnect,
+            fees: self.fees,
+            ordering: self.ordering,
+            state: std::marker::PhantomData,
+        }
+    }
+
+    /// Replays the frames recorded for the symbol rather than connecting to the venues they were recorded from.
+    ///
+    /// Venues are not reconnected once their frames run out, so the book ends after the last of them.
+    pub fn with_replay(mut self, session: &ReplaySession) -> OrderbookBuilder<WithExchange> {
+        for replay in session.replays(&self.symbol) {
+            self.exchanges.insert(replay.name(), Box::new(replay));
+        }
+
+        OrderbookBuilder {
+            exchanges: self.exchanges,
+            symbol: self.symbol,
+            max_depth: self.max_depth,
+            merged_depth: self.merged_depth,
+            monitor: self.monitor,
+            reconnect: ReconnectPolicy {
+                max_attempts: 0,
+                ..self.reconnect
+            },
+            fees: self.fees,
+            ordering: self.ordering,
             state: std::marker::PhantomData,
         }
     }
 }
 
 impl OrderbookBuilder<WithExchange> {
+    /// Optionally report the connection state of every exchange feed to `monitor`.
+    pub fn with_venue_monitor(mut self, monitor: VenueMonitor) -> Self {
+        self.monitor = Some(monitor);
+        self
+    }
+
+    /// Optionally override how dropped exchange feeds are reconnected.
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    /// Optionally set the maker and taker fees charged by each venue, venues without fees are free.
+    pub fn with_fees(mut self, fees: FeeSchedule) -> Self {
+        self.fees = fees;
+        self
+    }
+
+    /// Optionally rank levels by their price after taker fees rather than the quoted price.
+    pub fn with_ordering(mut self, ordering: BookOrdering) -> Self {
+        self.ordering = ordering;
+        self
+    }
+
+    /// Optionally keep up to `depth` of the best levels on each side of the merged book, while still reading
+    /// `max_depth` levels from each venue.
+    pub fn with_merged_depth(mut self, depth: usize) -> Self {
+        self.merged_depth = Some(depth);
+        self
+    }
+
     /// Can only be called on fully constructed OrderbookBuilder.
     ///
     /// Returns an `Orderbook` where you can call `.collect()` to start streaming events
     pub async fn build<T: Orderbook>(self) -> Result<T, Box<dyn Error>> {
+        let monitor = self.monitor.unwrap_or_default();
         let mut exchange_streams = StreamMap::new();
-        for (name, exchange) in &self.exchanges {
-            exchange_streams.insert(*name, exchange.connect(self.symbol.clone(), self.max_depth).await?);
+        for (name, exchange) in self.exchanges {
+            let reporter = monitor.reporter(name, self.symbol.clone());
+            reporter.connecting();
+            let feed = match exchange.connect(self.symbol.clone(), self.max_depth).await {
+                Ok(feed) => feed,
+                Err(e) => {
+                    reporter.failed(&e);
+                    return Err(e);
+                }
+            };
+            reporter.subscribed();
+            exchange_streams.insert(
+                name,
+                supervise(exchange, feed, self.symbol.clone(), self.max_depth, self.reconnect, reporter),
+            );
         }
-        Ok(T::new(self.max_depth, exchange_streams))
+        let merged_depth = self.merged_depth.unwrap_or(self.max_depth);
+        Ok(T::new(merged_depth, exchange_streams, self.fees, self.ordering))
     }
 }
@@ -0,0 +1,148 @@
+use std::{collections::HashMap, str::FromStr};
+
+use thiserror::Error;
+
+use crate::exchanges::{self, ExchangeType};
+
+use super::levels::{AskLevel, BidLevel};
+
+#[derive(Error, Debug)]
+pub enum FeeError {
+    #[error("expected a fee of the form <exchange>=<basis points>, got {0}")]
+    Malformed(String),
+    #[error("fees were given for an unknown exchange {0}")]
+    UnknownExchange(String),
+    #[error("unknown book ordering {0}, expected raw or fee-adjusted")]
+    UnknownOrdering(String),
+}
+
+/// How the levels of a merged book are ranked.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum BookOrdering {
+    /// By quoted price
+    #[default]
+    Raw,
+    /// By the price a taker pays or receives after the venue's taker fee, while still reporting the quoted price
+    FeeAdjusted,
+}
+
+impl FromStr for BookOrdering {
+    type Err = FeeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(BookOrdering::Raw),
+            "fee-adjusted" => Ok(BookOrdering::FeeAdjusted),
+            _ => Err(FeeError::UnknownOrdering(s.to_string())),
+        }
+    }
+}
+
+/// The fees charged by a venue, as fractions of the traded notional.
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Fees {
+    /// Charged when resting orders are filled
+    pub maker: f64,
+    /// Charged when crossing the book
+    pub taker: f64,
+}
+
+/// The fees charged by each venue, venues without a schedule are free.
+#[derive(Debug, Default, Clone)]
+pub struct FeeSchedule {
+    fees: HashMap<ExchangeType, Fees>,
+}
+
+impl FeeSchedule {
+    pub fn with_fees(mut self, exchange: ExchangeType, fees: Fees) -> Self {
+        self.fees.insert(exchange, fees);
+        self
+    }
+
+    pub fn fees(&self, exchange: ExchangeType) -> Fees {
+        self.fees.get(&exchange).copied().unwrap_or_default()
+    }
+
+    pub fn taker(&self, exchange: ExchangeType) -> f64 {
+        self.fees(exchange).taker
+    }
+
+    /// Sets the taker fee of a venue from an entry such as `binance=10`, given in basis points.
+    pub fn with_taker_bps(mut self, entry: &str) -> Result<Self, FeeError> {
+        let (exchange, bps) = parse_bps(entry)?;
+        self.fees.entry(exchange).or_default().taker = bps / 10_000.0;
+        Ok(self)
+    }
+
+    /// Sets the maker fee of a venue from an entry such as `binance=2`, given in basis points.
+    pub fn with_maker_bps(mut self, entry: &str) -> Result<Self, FeeError> {
+        let (exchange, bps) = parse_bps(entry)?;
+        self.fees.entry(exchange).or_default().maker = bps / 10_000.0;
+        Ok(self)
+    }
+
+    /// Reorders levels best first by their effective price, ties keeping their quoted order.
+    pub fn rank(&self, bids: &mut [BidLevel], asks: &mut [AskLevel]) {
+        bids.sort_by(|a, b| b.effective_price(self).total_cmp(&a.effective_price(self)));
+        asks.sort_by(|a, b| a.effective_price(self).total_cmp(&b.effective_price(self)));
+    }
+}
+
+/// Reads a fee entry such as `binance=10` into its venue and basis points.
+pub fn parse_bps(entry: &str) -> Result<(ExchangeType, f64), FeeError> {
+    let (exchange, bps) = entry
+        .split_once('=')
+        .ok_or_else(|| FeeError::Malformed(entry.to_string()))?;
+    let exchange = exchanges::parse_venue(exchange.trim())
+        .map_err(|_| FeeError::UnknownExchange(exchange.to_string()))?;
+    let bps = bps
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|bps| bps.is_finite() && *bps >= 0.0)
+        .ok_or_else(|| FeeError::Malformed(entry.to_string()))?;
+    Ok((exchange, bps))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::exchanges::ExchangeType;
+    use crate::orderbook::levels::{AskLevel, BidLevel};
+
+    use super::{FeeSchedule, Fees};
+
+    #[test]
+    fn test_taker_bps() {
+        let schedule = FeeSchedule::default().with_taker_bps("bitstamp=25").unwrap();
+        assert_eq!(schedule.taker(ExchangeType::Bitstamp), 0.0025);
+        assert_eq!(schedule.taker(ExchangeType::Binance), 0.0);
+        assert!(FeeSchedule::default().with_taker_bps("bitstamp").is_err());
+        assert!(FeeSchedule::default().with_taker_bps("kraken=10").is_err());
+        assert!(FeeSchedule::default().with_taker_bps("synthetic=10").is_err());
+        assert!(FeeSchedule::default().with_taker_bps("binance=-1").is_err());
+    }
+
+    #[test]
+    fn test_fee_adjusted_ranking() {
+        let fees = FeeSchedule::default().with_fees(
+            ExchangeType::Bitstamp,
+            Fees {
+                maker: 0.0,
+                taker: 0.01,
+            },
+        );
+        let mut bids = vec![
+            BidLevel::new(100.0, 1.0, ExchangeType::Bitstamp),
+            BidLevel::new(99.5, 1.0, ExchangeType::Binance),
+        ];
+        let mut asks = vec![
+            AskLevel::new(100.0, 1.0, ExchangeType::Bitstamp),
+            AskLevel::new(100.5, 1.0, ExchangeType::Binance),
+        ];
+        fees.rank(&mut bids, &mut asks);
+        // Bitstamp's 1% fee makes its better quotes the worse deal, which are still reported as quoted
+        assert_eq!(bids[0].exchange, ExchangeType::Binance);
+        assert_eq!(asks[0].exchange, ExchangeType::Binance);
+        assert_eq!(bids[1].price, 100.0);
+    }
+}
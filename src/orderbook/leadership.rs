@@ -0,0 +1,290 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime},
+};
+
+use crate::exchanges::ExchangeType;
+
+use super::levels::{AskLevel, BidLevel};
+
+/// The rolling windows leadership is reported over
+pub const LEADERSHIP_WINDOWS: [Duration; 3] = [
+    Duration::from_secs(60),
+    Duration::from_secs(5 * 60),
+    Duration::from_secs(15 * 60),
+];
+
+/// How a venue stood in the merged book from one update until the next.
+#[derive(Debug, Clone)]
+struct VenueSample {
+    exchange: ExchangeType,
+    best_bid: bool,
+    best_ask: bool,
+    /// The best bid moved to a price first quoted by this venue
+    moved_bid: bool,
+    /// The best ask moved to a price first quoted by this venue
+    moved_ask: bool,
+    spread_contribution: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+struct Sample {
+    at: SystemTime,
+    venues: Vec<VenueSample>,
+}
+
+/// How a single venue led the top of the merged book over a window.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenueLeadership {
+    pub exchange: ExchangeType,
+    /// Fraction of the window the venue quoted the best bid, ties crediting every venue at the price
+    pub best_bid_share: f64,
+    /// Fraction of the window the venue quoted the best ask, ties crediting every venue at the price
+    pub best_ask_share: f64,
+    /// Times the best bid moved to a price the venue quoted first
+    pub bid_moves: u64,
+    /// Times the best ask moved to a price the venue quoted first
+    pub ask_moves: u64,
+    /// Time-weighted average of how much wider the merged spread would be without the venue,
+    /// absent if there was never a spread without it
+    pub spread_contribution: Option<f64>,
+}
+
+/// Leadership of every venue over a rolling window ending now.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LeadershipWindow {
+    pub window: Duration,
+    /// How much of the window the book was live for, shorter than the window while it starts
+    pub covered: Duration,
+    /// Venues in the order they first appear in the book
+    pub venues: Vec<VenueLeadership>,
+}
+
+impl LeadershipWindow {
+    pub fn venue(&self, exchange: ExchangeType) -> Option<&VenueLeadership> {
+        self.venues.iter().find(|venue| venue.exchange == exchange)
+    }
+}
+
+#[derive(Default)]
+struct Totals {
+    best_bid: Duration,
+    best_ask: Duration,
+    bid_moves: u64,
+    ask_moves: u64,
+    contribution: f64,
+    contributed: Duration,
+}
+
+/// Tracks which venues quote the top of a merged book, over each of `LEADERSHIP_WINDOWS`.
+#[derive(Debug, Clone)]
+pub struct LeadershipTracker {
+    windows: Vec<Duration>,
+    samples: VecDeque<Sample>,
+    best_bid: Option<f64>,
+    best_ask: Option<f64>,
+}
+
+impl Default for LeadershipTracker {
+    fn default() -> Self {
+        Self::new(LEADERSHIP_WINDOWS.to_vec())
+    }
+}
+
+impl LeadershipTracker {
+    pub fn new(windows: Vec<Duration>) -> Self {
+        Self {
+            windows,
+            samples: VecDeque::new(),
+            best_bid: None,
+            best_ask: None,
+        }
+    }
+
+    /// Records the levels of the merged book as of `at`, which hold until the next update.
+    pub fn update(&mut self, at: SystemTime, bids: &[BidLevel], asks: &[AskLevel]) {
+        let best_bid = bids.first().map(|bid| bid.price);
+        let best_ask = asks.first().map(|ask| ask.price);
+        let bid_moved = best_bid.is_some() && best_bid != self.best_bid;
+        let ask_moved = best_ask.is_some() && best_ask != self.best_ask;
+        let spread = match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => Some(ask - bid),
+            _ => None,
+        };
+
+        let mut venues: Vec<VenueSample> = Vec::new();
+        let exchanges = bids
+            .iter()
+            .map(|bid| bid.exchange)
+            .chain(asks.iter().map(|ask| ask.exchange));
+        for exchange in exchanges {
+            if venues.iter().any(|venue| venue.exchange == exchange) {
+                continue;
+            }
+            let best_bid = bids
+                .iter()
+                .take_while(|bid| Some(bid.price) == best_bid)
+                .any(|bid| bid.exchange == exchange);
+            let best_ask = asks
+                .iter()
+                .take_while(|ask| Some(ask.price) == best_ask)
+                .any(|ask| ask.exchange == exchange);
+            let bid_without = bids.iter().find(|bid| bid.exchange != exchange).map(|bid| bid.price);
+            let ask_without = asks.iter().find(|ask| ask.exchange != exchange).map(|ask| ask.price);
+            let spread_contribution = match (spread, bid_without, ask_without) {
+                (Some(spread), Some(bid), Some(ask)) => Some(ask - bid - spread),
+                _ => None,
+            };
+            venues.push(VenueSample {
+                exchange,
+                best_bid,
+                best_ask,
+                moved_bid: bid_moved && best_bid,
+                moved_ask: ask_moved && best_ask,
+                spread_contribution,
+            });
+        }
+
+        self.best_bid = best_bid;
+        self.best_ask = best_ask;
+        self.samples.push_back(Sample { at, venues });
+        self.prune(at);
+    }
+
+    /// Drops samples that ended before the longest window, keeping the one it starts within.
+    fn prune(&mut self, now: SystemTime) {
+        let longest = self.windows.iter().max().copied().unwrap_or_default();
+        let start = match now.checked_sub(longest) {
+            Some(start) => start,
+            None => return,
+        };
+        while self.samples.get(1).is_some_and(|next| next.at <= start) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The leadership of every venue over each window ending at `now`, the latest update holding until then.
+    pub fn windows(&self, now: SystemTime) -> Vec<LeadershipWindow> {
+        self.windows
+            .iter()
+            .map(|&window| {
+                let start = now.checked_sub(window).unwrap_or(SystemTime::UNIX_EPOCH);
+                let mut covered = Duration::ZERO;
+                let mut totals: Vec<(ExchangeType, Totals)> = Vec::new();
+                for (i, sample) in self.samples.iter().enumerate() {
+                    let end = self.samples.get(i + 1).map_or(now, |next| next.at);
+                    let held = end
+                        .duration_since(sample.at.max(start))
+                        .unwrap_or_default();
+                    let moved = sample.at >= start;
+                    if held.is_zero() && !moved {
+                        continue;
+                    }
+                    covered += held;
+                    for venue in &sample.venues {
+                        let index = match totals.iter().position(|(exchange, _)| *exchange == venue.exchange) {
+                            Some(index) => index,
+                            None => {
+                                totals.push((venue.exchange, Totals::default()));
+                                totals.len() - 1
+                            }
+                        };
+                        let totals = &mut totals[index].1;
+                        if venue.best_bid {
+                            totals.best_bid += held;
+                        }
+                        if venue.best_ask {
+                            totals.best_ask += held;
+                        }
+                        if moved && venue.moved_bid {
+                            totals.bid_moves += 1;
+                        }
+                        if moved && venue.moved_ask {
+                            totals.ask_moves += 1;
+                        }
+                        if let Some(contribution) = venue.spread_contribution {
+                            totals.contribution += contribution * held.as_secs_f64();
+                            totals.contributed += held;
+                        }
+                    }
+                }
+                let share = |held: Duration| {
+                    if covered.is_zero() {
+                        0.0
+                    } else {
+                        held.as_secs_f64() / covered.as_secs_f64()
+                    }
+                };
+                let venues = totals
+                    .into_iter()
+                    .map(|(exchange, totals)| VenueLeadership {
+                        exchange,
+                        best_bid_share: share(totals.best_bid),
+                        best_ask_share: share(totals.best_ask),
+                        bid_moves: totals.bid_moves,
+                        ask_moves: totals.ask_moves,
+                        spread_contribution: (!totals.contributed.is_zero())
+                            .then(|| totals.contribution / totals.contributed.as_secs_f64()),
+                    })
+                    .collect();
+                LeadershipWindow {
+                    window,
+                    covered,
+                    venues,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::{
+        exchanges::ExchangeType,
+        orderbook::levels::{AskLevel, BidLevel},
+    };
+
+    use super::LeadershipTracker;
+
+    #[test]
+    fn test_leadership() {
+        let at = |secs: u64| UNIX_EPOCH + Duration::from_secs(1_000 + secs);
+        let mut tracker = LeadershipTracker::new(vec![Duration::from_secs(5), Duration::from_secs(100)]);
+        // Binance leads the bid and Bitstamp the ask for 30s
+        tracker.update(
+            at(0),
+            &[BidLevel::new(99.0, 1.0, ExchangeType::Binance), BidLevel::new(98.0, 1.0, ExchangeType::Bitstamp)],
+            &[AskLevel::new(101.0, 1.0, ExchangeType::Bitstamp), AskLevel::new(102.0, 1.0, ExchangeType::Binance)],
+        );
+        // Then Bitstamp moves the bid up first and leads both sides for 10s
+        tracker.update(
+            at(30),
+            &[BidLevel::new(100.0, 1.0, ExchangeType::Bitstamp), BidLevel::new(99.0, 1.0, ExchangeType::Binance)],
+            &[AskLevel::new(101.0, 1.0, ExchangeType::Bitstamp), AskLevel::new(102.0, 1.0, ExchangeType::Binance)],
+        );
+        let windows = tracker.windows(at(40));
+
+        let short = &windows[0];
+        assert_eq!(short.covered, Duration::from_secs(5));
+        let bitstamp = short.venue(ExchangeType::Bitstamp).unwrap();
+        assert_eq!(bitstamp.best_bid_share, 1.0);
+        // Its move came before the window
+        assert_eq!(bitstamp.bid_moves, 0);
+        // Without Bitstamp the spread would be 102 - 99 rather than 1
+        assert_eq!(bitstamp.spread_contribution, Some(2.0));
+
+        let long = &windows[1];
+        assert_eq!(long.covered, Duration::from_secs(40));
+        let binance = long.venue(ExchangeType::Binance).unwrap();
+        assert_eq!(binance.best_bid_share, 0.75);
+        assert_eq!(binance.best_ask_share, 0.0);
+        assert_eq!(binance.bid_moves, 1);
+        // Binance tightened the spread from 3 to 2 for 30s, and not at all for 10s
+        assert_eq!(binance.spread_contribution, Some(0.75));
+        let bitstamp = long.venue(ExchangeType::Bitstamp).unwrap();
+        assert_eq!(bitstamp.bid_moves, 1);
+        assert_eq!(bitstamp.ask_moves, 1);
+    }
+}
@@ -2,6 +2,8 @@ use std::{cmp::Ordering, hash::{Hash, Hasher}};
 
 use crate::exchanges::ExchangeType;
 
+use super::fees::FeeSchedule;
+
 /// A representation of bid levels that can be ordered according to the best deal.
 #[derive(Debug, Default, Copy, Clone)]
 pub struct BidLevel {
@@ -10,6 +12,20 @@ pub struct BidLevel {
     pub exchange: ExchangeType,
 }
 
+impl BidLevel {
+    /// What a taker selling into the level receives per unit after the venue's taker fee.
+    pub fn effective_price(&self, fees: &FeeSchedule) -> f64 {
+        self.price * (1.0 - fees.taker(self.exchange))
+    }
+}
+
+#[cfg(test)]
+impl BidLevel {
+    pub(crate) fn new(price: f64, amount: f64, exchange: ExchangeType) -> Self {
+        BidLevel { price, amount, exchange }
+    }
+}
+
 impl Hash for BidLevel {
     fn hash<H: Hasher>(&self, state: &mut H) {
         format!("Price: {} | Amount: {}| Exchange: {}", self.price, self.amount, self.exchange.to_string()).hash(state)
@@ -44,6 +60,20 @@ pub struct AskLevel {
     pub exchange: ExchangeType,
 }
 
+impl AskLevel {
+    /// What a taker buying from the level pays per unit after the venue's taker fee.
+    pub fn effective_price(&self, fees: &FeeSchedule) -> f64 {
+        self.price * (1.0 + fees.taker(self.exchange))
+    }
+}
+
+#[cfg(test)]
+impl AskLevel {
+    pub(crate) fn new(price: f64, amount: f64, exchange: ExchangeType) -> Self {
+        AskLevel { price, amount, exchange }
+    }
+}
+
 impl Hash for AskLevel {
     fn hash<H: Hasher>(&self, state: &mut H) {
         format!("Price: {} | Amount: {}| Exchange: {}", self.price, self.amount, self.exchange.to_string()).hash(state)
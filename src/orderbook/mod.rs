@@ -1,6 +1,10 @@
 pub mod builder;
+pub mod fees;
+pub mod leadership;
 pub(crate) mod levels;
 pub mod streaming_book;
+pub mod summary;
+pub mod synthetic;
 mod hash_heap;
 
 use std::error::Error;
@@ -9,6 +13,7 @@ use futures::Stream;
 use tokio_stream::StreamMap;
 
 use crate::exchanges::{ExchangeType, SnapshotStream};
+use fees::{BookOrdering, FeeSchedule};
 
 #[derive(Error, Debug)]
 enum OrderbookError {
@@ -24,7 +29,12 @@ pub trait Orderbook {
     
 
     /// Used to construct the orderbook within the orderbook builder.
-    fn new(max_depth: usize, exchanges: StreamMap<ExchangeType, SnapshotStream>) -> Self;
+    fn new(
+        max_depth: usize,
+        exchanges: StreamMap<ExchangeType, SnapshotStream>,
+        fees: FeeSchedule,
+        ordering: BookOrdering,
+    ) -> Self;
 
     /// Collect a stream of orders, any errors from the stream are propagated up in the result.
     /// 
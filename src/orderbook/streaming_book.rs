@@ -4,6 +4,7 @@ use std::{cmp::Reverse, error::Error, mem};
 use tokio_stream::{StreamExt, StreamMap};
 
 use crate::{exchanges::{ExchangeType, SnapshotStream}, orderbook::{OrderbookError, hash_heap::HashHeap}};
+use super::fees::{BookOrdering, FeeSchedule};
 
 use super::{
     levels::{AskLevel, BidLevel},
@@ -14,17 +15,35 @@ use super::{
 
 pub struct HeapedBook {
     max_depth: usize,
+    /// Levels kept on each side before ranking, more than `max_depth` when fees may rank a level past those quoted
+    /// better
+    retained_depth: usize,
     exchange_streams: StreamMap<ExchangeType, SnapshotStream>,
+    fees: FeeSchedule,
+    ordering: BookOrdering,
 }
 
 impl Orderbook for HeapedBook {
     type AskOrder = AskLevel;
     type BidOrder = BidLevel;
 
-    fn new(max_depth: usize, exchanges: StreamMap<ExchangeType, SnapshotStream>) -> Self {
+    fn new(
+        max_depth: usize,
+        exchanges: StreamMap<ExchangeType, SnapshotStream>,
+        fees: FeeSchedule,
+        ordering: BookOrdering,
+    ) -> Self {
+        // The best levels after fees are among the best `max_depth` quoted by each venue
+        let retained_depth = match ordering {
+            BookOrdering::Raw => max_depth,
+            BookOrdering::FeeAdjusted => max_depth * exchanges.len().max(1),
+        };
         Self {
             max_depth,
+            retained_depth,
             exchange_streams: exchanges,
+            fees,
+            ordering,
         }
     }
 
@@ -34,8 +53,8 @@ impl Orderbook for HeapedBook {
         Item = Result<(Vec<Self::BidOrder>, Vec<Self::AskOrder>), Box<dyn Error + Send + Sync>>,
     > + '_ {
         stream! {
-            let mut ask_heap : HashHeap<Reverse<Self::AskOrder>> = HashHeap::with_capacity(self.max_depth);
-            let mut bid_heap : HashHeap<Reverse<Self::BidOrder>> = HashHeap::with_capacity(self.max_depth);
+            let mut ask_heap : HashHeap<Reverse<Self::AskOrder>> = HashHeap::with_capacity(self.retained_depth);
+            let mut bid_heap : HashHeap<Reverse<Self::BidOrder>> = HashHeap::with_capacity(self.retained_depth);
             loop {
                 match self.exchange_streams.next().await {
                     Some((exchange, event)) => {
@@ -58,17 +77,21 @@ impl Orderbook for HeapedBook {
                                     }));
                                     
                                 });
+                                let mut bids = bid_heap.into_sorted_vec()
+                                    .iter_mut()
+                                    .map(|x| mem::take(&mut x.0))
+                                    .collect::<Vec<BidLevel>>();
+                                let mut asks = ask_heap.into_sorted_vec()
+                                    .iter_mut()
+                                    .map(|x| mem::take(&mut x.0))
+                                    .collect::<Vec<AskLevel>>();
+                                if self.ordering == BookOrdering::FeeAdjusted {
+                                    self.fees.rank(&mut bids, &mut asks);
+                                }
+                                bids.truncate(self.max_depth);
+                                asks.truncate(self.max_depth);
                                 // Publish an event the moment an exchange publishes an updated orderbook
-                                yield Ok((
-                                    bid_heap.into_sorted_vec()
-                                        .iter_mut()
-                                        .map(|x| mem::take(&mut x.0))
-                                        .collect::<Vec<BidLevel>>(),
-                                    ask_heap.into_sorted_vec()
-                                        .iter_mut()
-                                        .map(|x| mem::take(&mut x.0))
-                                        .collect::<Vec<AskLevel>>(),
-                                ));
+                                yield Ok((bids, asks));
                             },
                             Err(e) => {
                                 yield Err(e)
@@ -77,10 +100,58 @@ impl Orderbook for HeapedBook {
 
                     },
                     None => {
-                        yield Err(OrderbookError::StreamCancelled.into())
+                        // Every exchange has failed, there is nothing left to merge
+                        yield Err(OrderbookError::StreamCancelled.into());
+                        break;
                     }
                 }
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::{stream, StreamExt};
+    use tokio_stream::StreamMap;
+
+    use crate::{
+        exchanges::{ExchangeType, FeedSnapshot, SnapshotStream},
+        orderbook::{
+            fees::{BookOrdering, FeeSchedule, Fees},
+            Orderbook,
+        },
+    };
+
+    use super::HeapedBook;
+
+    fn feed(bid: f64, ask: f64) -> SnapshotStream {
+        Box::pin(stream::iter([Ok(FeedSnapshot {
+            bids: vec![[bid, 1.0]],
+            asks: vec![[ask, 1.0]],
+            ..Default::default()
+        })]))
+    }
+
+    #[tokio::test]
+    async fn test_fee_adjusted_depth() {
+        let fees = FeeSchedule::default().with_fees(
+            ExchangeType::Bitstamp,
+            Fees {
+                maker: 0.0,
+                taker: 0.01,
+            },
+        );
+        let mut feeds = StreamMap::new();
+        feeds.insert(ExchangeType::Bitstamp, feed(100.0, 100.0));
+        feeds.insert(ExchangeType::Binance, feed(99.5, 100.5));
+        let mut book = HeapedBook::new(1, feeds, fees, BookOrdering::FeeAdjusted);
+        let books: Vec<_> = book.collect().take(2).collect().await;
+        let (bids, asks) = books.last().unwrap().as_ref().unwrap();
+        // Bitstamp quotes the best price on both sides, which is outside the top level once its 1% fee is paid
+        assert_eq!(bids.len(), 1);
+        assert_eq!(bids[0].exchange, ExchangeType::Binance);
+        assert_eq!(asks.len(), 1);
+        assert_eq!(asks[0].exchange, ExchangeType::Binance);
+    }
+}
@@ -0,0 +1,89 @@
+use super::levels::{AskLevel, BidLevel};
+
+/// Statistics describing a merged book, tolerant of either side being empty.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct BookStatistics {
+    pub best_bid: Option<BidLevel>,
+    pub best_ask: Option<AskLevel>,
+    /// Best ask less best bid, only present when both sides are
+    pub spread: Option<f64>,
+    /// Midpoint of the best bid and ask
+    pub mid_price: Option<f64>,
+    /// Mid price weighted towards the side with less size at the top of the book
+    pub microprice: Option<f64>,
+    /// Total amount bid across every shown level
+    pub total_bid_volume: f64,
+    /// Total amount offered across every shown level
+    pub total_ask_volume: f64,
+}
+
+impl BookStatistics {
+    /// Computes the statistics of `bids` and `asks`, which must be ordered best deal first.
+    pub fn new(bids: &[BidLevel], asks: &[AskLevel]) -> Self {
+        let best_bid = bids.first().copied();
+        let best_ask = asks.first().copied();
+        let (spread, mid_price, microprice) = match (best_bid, best_ask) {
+            (Some(bid), Some(ask)) => {
+                let size = bid.amount + ask.amount;
+                let microprice = if size > 0.0 {
+                    (bid.price * ask.amount + ask.price * bid.amount) / size
+                } else {
+                    (bid.price + ask.price) / 2.0
+                };
+                (
+                    Some(ask.price - bid.price),
+                    Some((bid.price + ask.price) / 2.0),
+                    Some(microprice),
+                )
+            }
+            _ => (None, None, None),
+        };
+        Self {
+            best_bid,
+            best_ask,
+            spread,
+            mid_price,
+            microprice,
+            total_bid_volume: bids.iter().map(|bid| bid.amount).sum(),
+            total_ask_volume: asks.iter().map(|ask| ask.amount).sum(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        exchanges::ExchangeType,
+        orderbook::levels::{AskLevel, BidLevel},
+    };
+
+    use super::BookStatistics;
+
+    #[test]
+    fn test_statistics() {
+        let bids = [
+            BidLevel::new(99.0, 3.0, ExchangeType::Binance),
+            BidLevel::new(98.0, 2.0, ExchangeType::Bitstamp),
+        ];
+        let asks = [AskLevel::new(101.0, 1.0, ExchangeType::Bitstamp)];
+        let statistics = BookStatistics::new(&bids, &asks);
+        assert_eq!(statistics.spread, Some(2.0));
+        assert_eq!(statistics.mid_price, Some(100.0));
+        // The heavier bid pulls the microprice towards the ask
+        assert_eq!(statistics.microprice, Some(100.5));
+        assert_eq!(statistics.total_bid_volume, 5.0);
+        assert_eq!(statistics.total_ask_volume, 1.0);
+    }
+
+    #[test]
+    fn test_one_sided_book() {
+        let bids = [BidLevel::new(99.0, 3.0, ExchangeType::Binance)];
+        let statistics = BookStatistics::new(&bids, &[]);
+        assert_eq!(statistics.best_bid.map(|bid| bid.price), Some(99.0));
+        assert!(statistics.best_ask.is_none());
+        assert!(statistics.spread.is_none());
+        assert!(statistics.mid_price.is_none());
+        assert!(statistics.microprice.is_none());
+        assert_eq!(statistics.total_ask_volume, 0.0);
+    }
+}
@@ -0,0 +1,221 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use crate::exchanges::ExchangeType;
+
+use super::levels::{AskLevel, BidLevel};
+
+/// Amounts smaller than this are treated as exhausted when walking the legs
+const NOTIONAL_EPSILON: f64 = 1e-12;
+
+#[derive(Error, Debug)]
+pub enum SyntheticError {
+    #[error("expected a synthetic symbol of the form <symbol>=<base leg>/<quote leg>, got {0}")]
+    Malformed(String),
+    #[error("{0} is built from itself")]
+    SelfReferential(String),
+    #[error("synthetic legs may not be synthetic themselves, {0} is")]
+    SyntheticLeg(String),
+}
+
+/// A symbol implied by two legs quoted in a common currency, such as ETH/BTC from ETH/USDT and BTC/USDT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntheticSymbol {
+    pub symbol: String,
+    /// Quotes the symbol's base asset in the common currency
+    pub base_leg: String,
+    /// Quotes the symbol's quote asset in the common currency
+    pub quote_leg: String,
+}
+
+impl FromStr for SyntheticSymbol {
+    type Err = SyntheticError;
+
+    /// Parses a definition such as `ethbtc=ethusdt/btcusdt`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let malformed = || SyntheticError::Malformed(s.to_string());
+        let (symbol, legs) = s.split_once('=').ok_or_else(malformed)?;
+        let (base_leg, quote_leg) = legs.split_once('/').ok_or_else(malformed)?;
+        let (symbol, base_leg, quote_leg) = (symbol.trim(), base_leg.trim(), quote_leg.trim());
+        if symbol.is_empty() || base_leg.is_empty() || quote_leg.is_empty() {
+            return Err(malformed());
+        }
+        if symbol == base_leg || symbol == quote_leg {
+            return Err(SyntheticError::SelfReferential(symbol.to_string()));
+        }
+        Ok(Self {
+            symbol: symbol.to_string(),
+            base_leg: base_leg.to_string(),
+            quote_leg: quote_leg.to_string(),
+        })
+    }
+}
+
+/// Walks two ladders best first, matching their notional in the common currency.
+///
+/// Each step yields the prices of both levels and the notional matched between them.
+fn walk(base: &[(f64, f64)], quote: &[(f64, f64)], max_depth: usize) -> Vec<(f64, f64, f64)> {
+    let (mut i, mut j) = (0, 0);
+    let mut base_left = base.first().map_or(0.0, |(price, amount)| price * amount);
+    let mut quote_left = quote.first().map_or(0.0, |(price, amount)| price * amount);
+    let mut steps = Vec::new();
+    while i < base.len() && j < quote.len() && steps.len() < max_depth {
+        let notional = base_left.min(quote_left);
+        if notional > NOTIONAL_EPSILON {
+            steps.push((base[i].0, quote[j].0, notional));
+        }
+        base_left -= notional;
+        quote_left -= notional;
+        if base_left <= NOTIONAL_EPSILON {
+            i += 1;
+            base_left = base.get(i).map_or(0.0, |(price, amount)| price * amount);
+        }
+        if quote_left <= NOTIONAL_EPSILON {
+            j += 1;
+            quote_left = quote.get(j).map_or(0.0, |(price, amount)| price * amount);
+        }
+    }
+    steps
+}
+
+/// The bids implied by selling the base asset on the base leg and buying the quote asset on the quote leg.
+pub fn implied_bids(base_bids: &[BidLevel], quote_asks: &[AskLevel], max_depth: usize) -> Vec<BidLevel> {
+    let base: Vec<(f64, f64)> = base_bids.iter().map(|bid| (bid.price, bid.amount)).collect();
+    let quote: Vec<(f64, f64)> = quote_asks.iter().map(|ask| (ask.price, ask.amount)).collect();
+    walk(&base, &quote, max_depth)
+        .into_iter()
+        .map(|(base_price, quote_price, notional)| BidLevel {
+            price: base_price / quote_price,
+            amount: notional / base_price,
+            exchange: ExchangeType::Synthetic,
+        })
+        .collect()
+}
+
+/// The asks implied by selling the quote asset on the quote leg and buying the base asset on the base leg.
+pub fn implied_asks(base_asks: &[AskLevel], quote_bids: &[BidLevel], max_depth: usize) -> Vec<AskLevel> {
+    let base: Vec<(f64, f64)> = base_asks.iter().map(|ask| (ask.price, ask.amount)).collect();
+    let quote: Vec<(f64, f64)> = quote_bids.iter().map(|bid| (bid.price, bid.amount)).collect();
+    walk(&base, &quote, max_depth)
+        .into_iter()
+        .map(|(base_price, quote_price, notional)| AskLevel {
+            price: base_price / quote_price,
+            amount: notional / base_price,
+            exchange: ExchangeType::Synthetic,
+        })
+        .collect()
+}
+
+/// Merges implied levels into a direct book, keeping at most `max_depth` of the best on each side.
+pub fn merge(
+    bids: &mut Vec<BidLevel>,
+    asks: &mut Vec<AskLevel>,
+    implied_bids: Vec<BidLevel>,
+    implied_asks: Vec<AskLevel>,
+    max_depth: usize,
+) {
+    bids.extend(implied_bids);
+    asks.extend(implied_asks);
+    bids.sort_by(|a, b| b.price.total_cmp(&a.price));
+    asks.sort_by(|a, b| a.price.total_cmp(&b.price));
+    bids.truncate(max_depth);
+    asks.truncate(max_depth);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::{
+        exchanges::ExchangeType,
+        orderbook::levels::{AskLevel, BidLevel},
+    };
+
+    use super::{implied_asks, implied_bids, merge, SyntheticSymbol};
+
+    #[test]
+    fn test_synthetic_symbol() {
+        let synthetic = SyntheticSymbol::from_str("ethbtc=ethusdt/btcusdt").unwrap();
+        assert_eq!(synthetic.base_leg, "ethusdt");
+        assert_eq!(synthetic.quote_leg, "btcusdt");
+        assert!(SyntheticSymbol::from_str("ethbtc=ethusdt").is_err());
+        assert!(SyntheticSymbol::from_str("ethbtc=ethbtc/btcusdt").is_err());
+    }
+
+    #[test]
+    fn test_implied_bids() {
+        // 2 ETH bid at 2000 USDT, then 3 at 1900
+        let eth_bids = [
+            BidLevel::new(2000.0, 2.0, ExchangeType::Binance),
+            BidLevel::new(1900.0, 3.0, ExchangeType::Bitstamp),
+        ];
+        // 0.1 BTC offered at 20000 USDT, then 1 at 25000
+        let btc_asks = [
+            AskLevel::new(20000.0, 0.1, ExchangeType::Binance),
+            AskLevel::new(25000.0, 1.0, ExchangeType::Binance),
+        ];
+        let bids = implied_bids(&eth_bids, &btc_asks, 10);
+        // 2000 USDT of the first ETH level buys all of the first BTC level
+        assert_eq!(bids[0].price, 0.1);
+        assert_eq!(bids[0].amount, 1.0);
+        // The rest of the first ETH level is matched against the second BTC level
+        assert_eq!(bids[1].price, 2000.0 / 25000.0);
+        assert_eq!(bids[1].amount, 1.0);
+        // Then all of the second ETH level
+        assert_eq!(bids[2].price, 1900.0 / 25000.0);
+        assert_eq!(bids[2].amount, 3.0);
+        assert!(bids.iter().all(|bid| bid.exchange == ExchangeType::Synthetic));
+    }
+
+    #[test]
+    fn test_implied_asks() {
+        // 1 ETH offered at 2100 USDT, then 2 at 2200
+        let eth_asks = [
+            AskLevel::new(2100.0, 1.0, ExchangeType::Binance),
+            AskLevel::new(2200.0, 2.0, ExchangeType::Bitstamp),
+        ];
+        // 0.05 BTC bid at 21000 USDT, then 1 at 20000
+        let btc_bids = [
+            BidLevel::new(21000.0, 0.05, ExchangeType::Binance),
+            BidLevel::new(20000.0, 1.0, ExchangeType::Bitstamp),
+        ];
+        let asks = implied_asks(&eth_asks, &btc_bids, 10);
+        // Selling the first BTC level raises 1050 USDT, half of the first ETH level
+        assert_eq!(asks[0].price, 0.1);
+        assert_eq!(asks[0].amount, 0.5);
+        // The rest of the first ETH level is paid for from the second BTC level
+        assert_eq!(asks[1].price, 0.105);
+        assert_eq!(asks[1].amount, 0.5);
+        // Then all of the second ETH level
+        assert_eq!(asks[2].price, 0.11);
+        assert_eq!(asks[2].amount, 2.0);
+        assert!(asks.iter().all(|ask| ask.exchange == ExchangeType::Synthetic));
+        // Only as many levels as asked for are walked
+        assert_eq!(implied_asks(&eth_asks, &btc_bids, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_merge() {
+        let mut bids = vec![
+            BidLevel::new(0.1, 1.0, ExchangeType::Binance),
+            BidLevel::new(0.09, 1.0, ExchangeType::Bitstamp),
+        ];
+        let mut asks = vec![AskLevel::new(0.11, 1.0, ExchangeType::Binance)];
+        merge(
+            &mut bids,
+            &mut asks,
+            vec![BidLevel::new(0.095, 1.0, ExchangeType::Synthetic)],
+            vec![
+                AskLevel::new(0.105, 1.0, ExchangeType::Synthetic),
+                AskLevel::new(0.12, 1.0, ExchangeType::Synthetic),
+            ],
+            2,
+        );
+        // Implied levels are ranked among the direct ones, and the worst beyond the depth are dropped
+        let bid_venues: Vec<_> = bids.iter().map(|bid| bid.exchange).collect();
+        assert_eq!(bid_venues, [ExchangeType::Binance, ExchangeType::Synthetic]);
+        let ask_prices: Vec<_> = asks.iter().map(|ask| ask.price).collect();
+        assert_eq!(ask_prices, [0.105, 0.11]);
+    }
+}
@@ -0,0 +1,540 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, TrySendError},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures::StreamExt;
+use rusqlite::{params, Connection};
+use thiserror::Error;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::alerts::{Alert, AlertEngine};
+use crate::exchanges::{
+    status::{VenueHealth, VenueState},
+    ExchangeType,
+};
+use crate::hub::{BookEvent, BookHub, MergedBook};
+use crate::queue::OverflowPolicy;
+
+/// How often venues are checked for transitions that are not announced, such as going stale
+const VENUE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// How often rows past the retention policy are deleted
+const PRUNE_INTERVAL: Duration = Duration::from_secs(60);
+/// Records that may wait to be written before newer ones are dropped
+const PERSIST_QUEUE_CAPACITY: usize = 1024;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS top_of_book (
+        id INTEGER PRIMARY KEY,
+        symbol TEXT NOT NULL,
+        timestamp_ms INTEGER NOT NULL,
+        sequence INTEGER NOT NULL,
+        bid_exchange TEXT,
+        bid_price REAL,
+        bid_amount REAL,
+        ask_exchange TEXT,
+        ask_price REAL,
+        ask_amount REAL
+    );
+    CREATE INDEX IF NOT EXISTS top_of_book_symbol_timestamp ON top_of_book (symbol, timestamp_ms);
+    CREATE TABLE IF NOT EXISTS venue_events (
+        id INTEGER PRIMARY KEY,
+        exchange TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        from_state TEXT,
+        to_state TEXT,
+        last_error TEXT,
+        at_ms INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS venue_events_at ON venue_events (at_ms);
+    CREATE TABLE IF NOT EXISTS alerts (
+        id INTEGER PRIMARY KEY,
+        rule TEXT NOT NULL,
+        symbol TEXT NOT NULL,
+        exchange TEXT,
+        state TEXT NOT NULL,
+        value REAL,
+        since_ms INTEGER NOT NULL,
+        at_ms INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS alerts_at ON alerts (at_ms);
+";
+
+/// Each table and the column its rows are aged by
+const TABLES: [(&str, &str); 3] = [
+    ("top_of_book", "timestamp_ms"),
+    ("venue_events", "at_ms"),
+    ("alerts", "at_ms"),
+];
+
+#[derive(Error, Debug)]
+pub enum PersistenceError {
+    #[error("could not persist to the database: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// How long persisted rows are kept, forever unless either limit is set.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub struct Retention {
+    /// Age past which rows are deleted
+    pub max_age: Option<Duration>,
+    /// Rows kept in each table, the oldest being deleted first
+    pub max_rows: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PersistenceConfig {
+    pub path: PathBuf,
+    /// The least time between two top of book rows of a symbol
+    pub top_interval: Duration,
+    pub retention: Retention,
+}
+
+/// A venue changing state, appearing, or disappearing once its feed is torn down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VenueTransition {
+    pub exchange: ExchangeType,
+    pub symbol: String,
+    /// Absent when the venue first appears
+    pub from: Option<VenueState>,
+    /// Absent once the venue's feed has been torn down
+    pub to: Option<VenueState>,
+    pub last_error: Option<String>,
+    pub at: SystemTime,
+}
+
+/// The transitions between the states venues were last seen in and their current health, updating `states`.
+pub fn transitions(
+    states: &mut HashMap<(ExchangeType, String), VenueState>,
+    venues: &[VenueHealth],
+    at: SystemTime,
+) -> Vec<VenueTransition> {
+    let mut transitions = Vec::new();
+    for venue in venues {
+        let from = states.insert((venue.exchange, venue.symbol.clone()), venue.state);
+        if from != Some(venue.state) {
+            transitions.push(VenueTransition {
+                exchange: venue.exchange,
+                symbol: venue.symbol.clone(),
+                from,
+                to: Some(venue.state),
+                last_error: venue.last_error.clone(),
+                at,
+            });
+        }
+    }
+    states.retain(|(exchange, symbol), state| {
+        let present = venues
+            .iter()
+            .any(|venue| venue.exchange == *exchange && venue.symbol == *symbol);
+        if !present {
+            transitions.push(VenueTransition {
+                exchange: *exchange,
+                symbol: symbol.clone(),
+                from: Some(*state),
+                to: None,
+                last_error: None,
+                at,
+            });
+        }
+        present
+    });
+    transitions
+}
+
+fn epoch_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as i64)
+}
+
+/// The tables of a SQLite database, created if they do not exist.
+pub struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, PersistenceError> {
+        Self::migrated(Connection::open(path)?)
+    }
+
+    pub fn in_memory() -> Result<Self, PersistenceError> {
+        Self::migrated(Connection::open_in_memory()?)
+    }
+
+    fn migrated(connection: Connection) -> Result<Self, PersistenceError> {
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection })
+    }
+
+    /// The underlying connection, for querying what was persisted.
+    pub fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    pub fn insert_top(&self, book: &MergedBook) -> Result<(), PersistenceError> {
+        let (bid, ask) = (book.bids.first(), book.asks.first());
+        self.connection.execute(
+            "INSERT INTO top_of_book
+                (symbol, timestamp_ms, sequence, bid_exchange, bid_price, bid_amount, ask_exchange, ask_price, ask_amount)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                book.symbol,
+                epoch_millis(book.timestamp),
+                book.sequence as i64,
+                bid.map(|bid| bid.exchange.to_string()),
+                bid.map(|bid| bid.price),
+                bid.map(|bid| bid.amount),
+                ask.map(|ask| ask.exchange.to_string()),
+                ask.map(|ask| ask.price),
+                ask.map(|ask| ask.amount),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_transition(&self, transition: &VenueTransition) -> Result<(), PersistenceError> {
+        self.connection.execute(
+            "INSERT INTO venue_events (exchange, symbol, from_state, to_state, last_error, at_ms)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                transition.exchange.to_string(),
+                transition.symbol,
+                transition.from.map(|state| state.to_string()),
+                transition.to.map(|state| state.to_string()),
+                transition.last_error,
+                epoch_millis(transition.at),
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn insert_alert(&self, alert: &Alert) -> Result<(), PersistenceError> {
+        self.connection.execute(
+            "INSERT INTO alerts (rule, symbol, exchange, state, value, since_ms, at_ms)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                alert.rule,
+                alert.symbol,
+                alert.exchange.map(|exchange| exchange.to_string()),
+                alert.state.to_string(),
+                alert.value,
+                epoch_millis(alert.since),
+                epoch_millis(alert.at),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Deletes the rows of every table past `retention` as of `now`, returning how many were deleted.
+    pub fn prune(&self, retention: Retention, now: SystemTime) -> Result<usize, PersistenceError> {
+        let mut deleted = 0;
+        for (table, column) in TABLES {
+            if let Some(start) = retention.max_age.and_then(|max_age| now.checked_sub(max_age)) {
+                deleted += self.connection.execute(
+                    &format!("DELETE FROM {table} WHERE {column} < ?1"),
+                    params![epoch_millis(start)],
+                )?;
+            }
+            if let Some(max_rows) = retention.max_rows {
+                deleted += self.connection.execute(
+                    &format!("DELETE FROM {table} WHERE id <= (SELECT MAX(id) FROM {table}) - ?1"),
+                    params![max_rows as i64],
+                )?;
+            }
+        }
+        Ok(deleted)
+    }
+}
+
+/// What the writer is asked to persist.
+enum Record {
+    Top(Arc<MergedBook>),
+    Transition(VenueTransition),
+    Alert(Arc<Alert>),
+    Prune,
+}
+
+/// Hands records to the writer, dropping them while it is too far behind.
+#[derive(Clone)]
+struct RecordSender {
+    tx: mpsc::SyncSender<Record>,
+    /// Records dropped since the writer last wrote one
+    dropped: Arc<AtomicU64>,
+}
+
+impl RecordSender {
+    /// Returns false once the writer has stopped.
+    fn send(&self, record: Record) -> bool {
+        match self.tx.try_send(record) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    }
+}
+
+/// Persists the throttled top of book of `symbols`, every venue transition and every alert until the hub shuts down.
+///
+/// Rows are written on a thread of their own, and records arriving while it is too far behind are dropped and counted in
+/// the log.
+pub fn start(
+    hub: BookHub,
+    alerts: &AlertEngine,
+    symbols: Vec<String>,
+    config: PersistenceConfig,
+) -> Result<(), PersistenceError> {
+    let store = Store::open(&config.path)?;
+    info!("persisting to {}", config.path.display());
+    let (tx, rx) = mpsc::sync_channel::<Record>(PERSIST_QUEUE_CAPACITY);
+    let tx = RecordSender {
+        tx,
+        dropped: Arc::new(AtomicU64::new(0)),
+    };
+    let retention = config.retention;
+    let dropped = tx.dropped.clone();
+    std::thread::spawn(move || {
+        for record in rx {
+            let missed = dropped.swap(0, Ordering::Relaxed);
+            if missed > 0 {
+                warn!("{missed} records were not persisted as the database fell behind");
+            }
+            let result = match record {
+                Record::Top(book) => store.insert_top(&book),
+                Record::Transition(transition) => store.insert_transition(&transition),
+                Record::Alert(alert) => store.insert_alert(&alert),
+                Record::Prune => store.prune(retention, SystemTime::now()).map(|deleted| {
+                    debug!("pruned {deleted} persisted rows");
+                }),
+            };
+            if let Err(e) = result {
+                error!("{e}");
+            }
+        }
+    });
+
+    for symbol in symbols {
+        let (hub, tx) = (hub.clone(), tx.clone());
+        let interval = config.top_interval;
+        tokio::spawn(async move {
+            if let Err(e) = persist_tops(&hub, &symbol, interval, tx).await {
+                error!("could not persist the top of the {symbol} book: {e}");
+            }
+        });
+    }
+
+    let (monitor, shutdown, venue_tx) = (hub.venues().clone(), hub.clone(), tx.clone());
+    tokio::spawn(async move {
+        let mut changes = monitor.subscribe();
+        let mut poll = tokio::time::interval(VENUE_POLL_INTERVAL);
+        let mut states = HashMap::new();
+        loop {
+            tokio::select! {
+                _ = shutdown.shutting_down() => break,
+                _ = poll.tick() => {},
+                change = changes.recv() => if let Err(RecvError::Closed) = change {
+                    break;
+                },
+            }
+            for transition in transitions(&mut states, &monitor.snapshot(), SystemTime::now()) {
+                if !venue_tx.send(Record::Transition(transition)) {
+                    return;
+                }
+            }
+        }
+    });
+
+    let (mut alerts, shutdown, alert_tx) = (alerts.subscribe(), hub.clone(), tx.clone());
+    tokio::spawn(async move {
+        loop {
+            let alert = tokio::select! {
+                _ = shutdown.shutting_down() => break,
+                alert = alerts.recv() => alert,
+            };
+            match alert {
+                Ok(alert) => {
+                    if !alert_tx.send(Record::Alert(alert)) {
+                        break;
+                    }
+                }
+                Err(RecvError::Lagged(missed)) => warn!("{missed} alerts were not persisted"),
+                Err(RecvError::Closed) => break,
+            }
+        }
+    });
+
+    if retention != Retention::default() {
+        tokio::spawn(async move {
+            let mut prune = tokio::time::interval(PRUNE_INTERVAL);
+            loop {
+                tokio::select! {
+                    _ = hub.shutting_down() => break,
+                    _ = prune.tick() => {},
+                }
+                if !tx.send(Record::Prune) {
+                    break;
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Sends the latest book of `symbol` at most once every `interval`, and only when its top has changed.
+async fn persist_tops(
+    hub: &BookHub,
+    symbol: &str,
+    interval: Duration,
+    tx: RecordSender,
+) -> Result<(), crate::hub::HubError> {
+    let mut subscription = hub.subscribe(symbol, 1, 1, OverflowPolicy::Conflate).await?;
+    let mut throttle = tokio::time::interval(interval);
+    let mut latest: Option<Arc<MergedBook>> = None;
+    let mut persisted: Option<Arc<MergedBook>> = None;
+    loop {
+        tokio::select! {
+            _ = hub.shutting_down() => return Ok(()),
+            event = subscription.next() => match event {
+                Some(BookEvent::Update(book)) => latest = Some(book),
+                Some(_) => {}
+                None => return Ok(()),
+            },
+            _ = throttle.tick() => {
+                let book = match &latest {
+                    Some(book) => book,
+                    None => continue,
+                };
+                if persisted.as_ref().is_none_or(|persisted| top_changed(persisted, book)) {
+                    if !tx.send(Record::Top(book.clone())) {
+                        return Ok(());
+                    }
+                    persisted = Some(book.clone());
+                }
+            }
+        }
+    }
+}
+
+/// Whether the best bid or ask differs in price, size or venue.
+///
+/// Level equality only compares price and venue, which would miss a change in size.
+fn top_changed(persisted: &MergedBook, book: &MergedBook) -> bool {
+    let bid = |book: &MergedBook| book.bids.first().map(|level| (level.price, level.amount, level.exchange));
+    let ask = |book: &MergedBook| book.asks.first().map(|level| (level.price, level.amount, level.exchange));
+    bid(persisted) != bid(book) || ask(persisted) != ask(book)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use crate::{
+        exchanges::{
+            status::{VenueHealth, VenueState},
+            ExchangeType,
+        },
+        hub::BookFixture,
+    };
+
+    use super::{top_changed, transitions, Retention, Store};
+
+    fn venue(state: VenueState) -> VenueHealth {
+        VenueHealth {
+            exchange: ExchangeType::Binance,
+            symbol: String::from("ethbtc"),
+            state,
+            last_message: None,
+            messages: 0,
+            message_rate: 0.0,
+            errors: 0,
+            last_error: None,
+            latency: None,
+        }
+    }
+
+    #[test]
+    fn test_transitions() {
+        let mut states = HashMap::new();
+        let first = transitions(&mut states, &[venue(VenueState::Connecting)], UNIX_EPOCH);
+        assert_eq!(first[0].from, None);
+        assert!(transitions(&mut states, &[venue(VenueState::Connecting)], UNIX_EPOCH).is_empty());
+
+        let stale = transitions(&mut states, &[venue(VenueState::Stale)], UNIX_EPOCH);
+        assert_eq!(stale[0].from, Some(VenueState::Connecting));
+        assert_eq!(stale[0].to, Some(VenueState::Stale));
+
+        let gone = transitions(&mut states, &[], UNIX_EPOCH);
+        assert_eq!(gone[0].to, None);
+        assert!(states.is_empty());
+    }
+
+    #[test]
+    fn test_store_retention() {
+        let store = Store::in_memory().unwrap();
+        for sequence in 1..=5 {
+            store
+                .insert_top(
+                    &BookFixture::new()
+                        .sequence(sequence)
+                        .timestamp(UNIX_EPOCH + Duration::from_secs(sequence * 10))
+                        .bid(0.07, 1.0, ExchangeType::Bitstamp)
+                        .build(),
+                )
+                .unwrap();
+        }
+        let exchange: String = store
+            .connection()
+            .query_row("SELECT bid_exchange FROM top_of_book WHERE sequence = 1", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(exchange, "Bitstamp");
+
+        // Rows from before 20s, then all but the newest 2
+        let retention = Retention {
+            max_age: Some(Duration::from_secs(40)),
+            max_rows: Some(2),
+        };
+        assert_eq!(store.prune(retention, UNIX_EPOCH + Duration::from_secs(60)).unwrap(), 3);
+        let sequences: Vec<i64> = store
+            .connection()
+            .prepare("SELECT sequence FROM top_of_book ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(sequences, vec![4, 5]);
+    }
+
+    #[test]
+    fn test_top_changed() {
+        let book = BookFixture::new()
+            .bid(0.07, 1.0, ExchangeType::Bitstamp)
+            .ask(0.08, 1.0, ExchangeType::Binance)
+            .build();
+        let same = BookFixture::new()
+            .sequence(2)
+            .bid(0.07, 1.0, ExchangeType::Bitstamp)
+            .ask(0.08, 1.0, ExchangeType::Binance)
+            .build();
+        assert!(!top_changed(&book, &same));
+
+        // Only the size of the best ask has changed
+        let resized = BookFixture::new()
+            .bid(0.07, 1.0, ExchangeType::Bitstamp)
+            .ask(0.08, 2.5, ExchangeType::Binance)
+            .build();
+        assert!(top_changed(&book, &resized));
+        assert!(top_changed(&book, &BookFixture::new().ask(0.08, 1.0, ExchangeType::Binance).build()));
+    }
+}
@@ -0,0 +1,310 @@
+use std::{
+    collections::VecDeque,
+    fmt::Display,
+    pin::Pin,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{Context, Poll},
+};
+
+use futures::{task::AtomicWaker, Stream};
+use thiserror::Error;
+use tokio::sync::Notify;
+
+#[derive(Error, Debug)]
+pub enum QueueError {
+    #[error("the queue has been closed")]
+    Closed,
+    #[error("the queue is full")]
+    Overflow,
+    #[error("queue capacity must be a positive integer")]
+    InvalidCapacity,
+    #[error("unknown overflow policy, expected one of drop-oldest, conflate or disconnect")]
+    UnknownPolicy,
+}
+
+/// What a bounded queue does when an item is pushed while it is full.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Discard the oldest queued item to make room for the new one
+    #[default]
+    DropOldest,
+    /// Discard everything queued, keeping only the newest item
+    Conflate,
+    /// Refuse the item, the producer is expected to disconnect the consumer
+    Disconnect,
+}
+
+impl Display for OverflowPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DropOldest => write!(f, "drop-oldest"),
+            Self::Conflate => write!(f, "conflate"),
+            Self::Disconnect => write!(f, "disconnect"),
+        }
+    }
+}
+
+impl FromStr for OverflowPolicy {
+    type Err = QueueError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "drop-oldest" => Ok(OverflowPolicy::DropOldest),
+            "conflate" => Ok(OverflowPolicy::Conflate),
+            "disconnect" => Ok(OverflowPolicy::Disconnect),
+            _ => Err(QueueError::UnknownPolicy),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    depth: AtomicUsize,
+    dropped: AtomicU64,
+    delivered: AtomicU64,
+}
+
+/// A cheap handle onto the counters of a queue, usable after either end has been dropped.
+#[derive(Clone)]
+pub struct QueueMetrics {
+    capacity: usize,
+    policy: OverflowPolicy,
+    counters: Arc<Counters>,
+}
+
+impl QueueMetrics {
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn policy(&self) -> OverflowPolicy {
+        self.policy
+    }
+
+    /// Items currently waiting to be received
+    pub fn depth(&self) -> usize {
+        self.counters.depth.load(Ordering::Relaxed)
+    }
+
+    /// Items discarded by the overflow policy
+    pub fn dropped(&self) -> u64 {
+        self.counters.dropped.load(Ordering::Relaxed)
+    }
+
+    /// Items handed to the receiver
+    pub fn delivered(&self) -> u64 {
+        self.counters.delivered.load(Ordering::Relaxed)
+    }
+}
+
+struct Shared<T> {
+    items: Mutex<VecDeque<T>>,
+    metrics: QueueMetrics,
+    sender_closed: AtomicBool,
+    receiver_closed: AtomicBool,
+    receiver_dropped: Notify,
+    waker: AtomicWaker,
+}
+
+/// Creates a queue holding at most `capacity` items, applying `policy` when it is full.
+pub fn bounded<T>(capacity: usize, policy: OverflowPolicy) -> (QueueSender<T>, QueueReceiver<T>) {
+    let capacity = capacity.max(1);
+    let shared = Arc::new(Shared {
+        items: Mutex::new(VecDeque::with_capacity(capacity)),
+        metrics: QueueMetrics {
+            capacity,
+            policy,
+            counters: Arc::new(Counters::default()),
+        },
+        sender_closed: AtomicBool::new(false),
+        receiver_closed: AtomicBool::new(false),
+        receiver_dropped: Notify::new(),
+        waker: AtomicWaker::new(),
+    });
+    (
+        QueueSender {
+            shared: shared.clone(),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+/// The producing half of a bounded queue, the queue is closed once it is dropped.
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    /// Enqueue an item without waiting, applying the overflow policy if the queue is full.
+    ///
+    /// Fails with `QueueError::Overflow` only under `OverflowPolicy::Disconnect`.
+    pub fn push(&self, item: T) -> Result<(), QueueError> {
+        if self.is_closed() {
+            return Err(QueueError::Closed);
+        }
+        let metrics = &self.shared.metrics;
+        {
+            let mut items = self.shared.items.lock().unwrap();
+            if items.len() >= metrics.capacity {
+                let dropped = match metrics.policy {
+                    OverflowPolicy::DropOldest => {
+                        items.pop_front();
+                        1
+                    }
+                    OverflowPolicy::Conflate => {
+                        let dropped = items.len();
+                        items.clear();
+                        dropped
+                    }
+                    OverflowPolicy::Disconnect => return Err(QueueError::Overflow),
+                };
+                metrics.counters.dropped.fetch_add(dropped as u64, Ordering::Relaxed);
+            }
+            items.push_back(item);
+            metrics.counters.depth.store(items.len(), Ordering::Relaxed);
+        }
+        self.shared.waker.wake();
+        Ok(())
+    }
+
+    /// Discards everything queued and closes the queue, so that `item` is the last thing received.
+    pub fn close_with(&self, item: T) {
+        {
+            let mut items = self.shared.items.lock().unwrap();
+            let discarded = items.len() as u64;
+            items.clear();
+            items.push_back(item);
+            self.shared.metrics.counters.dropped.fetch_add(discarded, Ordering::Relaxed);
+            self.shared.metrics.counters.depth.store(1, Ordering::Relaxed);
+        }
+        self.shared.sender_closed.store(true, Ordering::Release);
+        self.shared.waker.wake();
+    }
+
+    /// Whether the receiver has been dropped or the queue closed with `close_with`
+    pub fn is_closed(&self) -> bool {
+        self.shared.receiver_closed.load(Ordering::Acquire)
+            || self.shared.sender_closed.load(Ordering::Acquire)
+    }
+
+    /// Completes once the receiver has been dropped, letting producers stop as soon as nobody is listening.
+    pub async fn closed(&self) {
+        while !self.shared.receiver_closed.load(Ordering::Acquire) {
+            self.shared.receiver_dropped.notified().await;
+        }
+    }
+
+    pub fn metrics(&self) -> QueueMetrics {
+        self.shared.metrics.clone()
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        self.shared.sender_closed.store(true, Ordering::Release);
+        self.shared.waker.wake();
+    }
+}
+
+/// The consuming half of a bounded queue, yields items until the sender is dropped and the queue drained.
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    pub fn metrics(&self) -> QueueMetrics {
+        self.shared.metrics.clone()
+    }
+
+    fn pop(&self) -> Option<T> {
+        let mut items = self.shared.items.lock().unwrap();
+        let item = items.pop_front();
+        if item.is_some() {
+            let counters = &self.shared.metrics.counters;
+            counters.depth.store(items.len(), Ordering::Relaxed);
+            counters.delivered.fetch_add(1, Ordering::Relaxed);
+        }
+        item
+    }
+}
+
+impl<T> Stream for QueueReceiver<T> {
+    type Item = T;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if let Some(item) = self.pop() {
+            return Poll::Ready(Some(item));
+        }
+        self.shared.waker.register(cx.waker());
+        // Check again in case an item arrived before the waker was registered
+        if let Some(item) = self.pop() {
+            return Poll::Ready(Some(item));
+        }
+        if self.shared.sender_closed.load(Ordering::Acquire) {
+            return Poll::Ready(self.pop());
+        }
+        Poll::Pending
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_closed.store(true, Ordering::Release);
+        // Stores a permit if the sender is not waiting yet, so the drop is never missed
+        self.shared.receiver_dropped.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::{bounded, OverflowPolicy, QueueError};
+
+    #[tokio::test]
+    async fn test_drop_oldest() {
+        let (tx, rx) = bounded(2, OverflowPolicy::DropOldest);
+        for i in 0..5 {
+            tx.push(i).unwrap();
+        }
+        let metrics = tx.metrics();
+        assert_eq!(metrics.depth(), 2);
+        assert_eq!(metrics.dropped(), 3);
+        drop(tx);
+        assert_eq!(rx.collect::<Vec<i32>>().await, vec![3, 4]);
+        assert_eq!(metrics.delivered(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_conflate() {
+        let (tx, rx) = bounded(3, OverflowPolicy::Conflate);
+        for i in 0..4 {
+            tx.push(i).unwrap();
+        }
+        assert_eq!(tx.metrics().dropped(), 3);
+        drop(tx);
+        assert_eq!(rx.collect::<Vec<i32>>().await, vec![3]);
+    }
+
+    #[tokio::test]
+    async fn test_disconnect() {
+        let (tx, rx) = bounded(1, OverflowPolicy::Disconnect);
+        tx.push(1).unwrap();
+        assert!(matches!(tx.push(2), Err(QueueError::Overflow)));
+        tx.close_with(-1);
+        assert!(matches!(tx.push(3), Err(QueueError::Closed)));
+        assert_eq!(rx.collect::<Vec<i32>>().await, vec![-1]);
+    }
+
+    #[tokio::test]
+    async fn test_closed_receiver() {
+        let (tx, rx) = bounded::<i32>(1, OverflowPolicy::DropOldest);
+        drop(rx);
+        tx.closed().await;
+        assert!(matches!(tx.push(1), Err(QueueError::Closed)));
+    }
+}
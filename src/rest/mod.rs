@@ -0,0 +1,401 @@
+use std::{collections::HashMap, convert::Infallible, io, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::get,
+    BoxError, Json, Router,
+};
+use async_stream::stream;
+use futures::{stream::select_all, Stream, StreamExt};
+use hyper::server::accept::{self, Accept};
+use serde::Deserialize;
+use serde_json::json;
+use thiserror::Error;
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_native_tls::{TlsAcceptor, TlsStream};
+
+use crate::exchanges::status::VenueHealth;
+use crate::hub::{BookEvent, BookHub, HubError};
+use crate::json::{JsonBook, JsonTop, JsonVenue};
+use crate::queue::OverflowPolicy;
+use crate::server::auth::{authorize_symbol, AuthError, Authenticator, Principal};
+
+/// Header carrying an API key
+const API_KEY_HEADER: &str = "x-api-key";
+/// Interval between top of book events of a symbol when the client does not choose one
+const DEFAULT_TOP_INTERVAL: Duration = Duration::from_millis(500);
+/// The shortest interval a client may ask for between top of book events of a symbol
+const MIN_TOP_INTERVAL: Duration = Duration::from_millis(100);
+/// Secured connections that may wait to be served before further handshakes wait for them
+const TLS_ACCEPT_BACKLOG: usize = 16;
+
+#[derive(Error, Debug)]
+pub enum RestError {
+    #[error(transparent)]
+    Hub(#[from] HubError),
+    #[error(transparent)]
+    Auth(#[from] AuthError),
+}
+
+impl IntoResponse for RestError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            RestError::Hub(HubError::UnknownSymbol(_)) => StatusCode::NOT_FOUND,
+            RestError::Hub(HubError::StartFailed { .. }) => StatusCode::BAD_GATEWAY,
+            RestError::Hub(HubError::ShuttingDown | HubError::NoBook(_)) => StatusCode::SERVICE_UNAVAILABLE,
+            RestError::Auth(AuthError::MissingKey | AuthError::UnknownKey) => StatusCode::UNAUTHORIZED,
+            RestError::Auth(AuthError::SymbolNotPermitted(_)) => StatusCode::FORBIDDEN,
+            RestError::Auth(AuthError::TooManyStreams(_)) => StatusCode::TOO_MANY_REQUESTS,
+            RestError::Auth(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        (status, Json(json!({ "error": self.to_string() }))).into_response()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct BookQuery {
+    /// Levels to return on each side, every level held by the hub if absent
+    depth: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct TopQuery {
+    /// Comma separated symbols to stream, the default symbol if absent
+    symbols: Option<String>,
+    /// Least milliseconds between events of a symbol
+    interval_ms: Option<u64>,
+}
+
+/// Serves snapshots of the merged books of a `BookHub` and the health of their venues as JSON over HTTP.
+///
+/// Snapshots are fetched with `BookHub::current`, so repeated polling is answered from the shared book.
+#[derive(Clone)]
+pub struct RestGateway {
+    hub: BookHub,
+    authenticator: Authenticator,
+    tls: Option<TlsAcceptor>,
+}
+
+impl RestGateway {
+    pub fn new(hub: BookHub, authenticator: Authenticator) -> Self {
+        Self {
+            hub,
+            authenticator,
+            tls: None,
+        }
+    }
+
+    /// Serves over TLS rather than plain HTTP.
+    pub fn with_tls(mut self, tls: TlsAcceptor) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Routes `GET /books/{symbol}?depth=N`, `GET /top?symbols=a,b&interval_ms=N`, `GET /venues` and `GET /metrics`.
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/books/:symbol", get(book))
+            .route("/top", get(top_of_book))
+            .route("/venues", get(venues))
+            .route("/metrics", get(metrics))
+            .with_state(self)
+    }
+
+    /// Serves the gateway on `addr` until the hub is shut down.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), BoxError> {
+        let hub = self.hub.clone();
+        let shutdown = async move { hub.shutting_down().await };
+        match self.tls.clone() {
+            Some(tls) => {
+                let listener = TcpListener::bind(addr).await?;
+                info!("REST gateway listening on {addr} over TLS");
+                axum::Server::builder(secured(listener, tls))
+                    .serve(self.router().into_make_service())
+                    .with_graceful_shutdown(shutdown)
+                    .await?
+            }
+            None => {
+                info!("REST gateway listening on {addr}");
+                axum::Server::try_bind(&addr)?
+                    .serve(self.router().into_make_service())
+                    .with_graceful_shutdown(shutdown)
+                    .await?
+            }
+        }
+        Ok(())
+    }
+
+    fn authenticate(&self, headers: &HeaderMap) -> Result<Option<Arc<Principal>>, AuthError> {
+        let key = match headers.get(API_KEY_HEADER) {
+            Some(key) => key.to_str().ok(),
+            None => headers
+                .get(AUTHORIZATION)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.strip_prefix("Bearer ")),
+        };
+        self.authenticator.authenticate(key)
+    }
+}
+
+/// Accepts connections on `listener`, yielding each once its TLS handshake completes.
+///
+/// Handshakes run concurrently, so a client that stalls during one does not hold up the others.
+fn secured(listener: TcpListener, tls: TlsAcceptor) -> impl Accept<Conn = TlsStream<TcpStream>, Error = io::Error> {
+    let (tx, mut rx) = mpsc::channel(TLS_ACCEPT_BACKLOG);
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                // The gateway has stopped serving
+                _ = tx.closed() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        break;
+                    }
+                },
+            };
+            let (tls, tx) = (tls.clone(), tx.clone());
+            tokio::spawn(async move {
+                match tls.accept(stream).await {
+                    Ok(stream) => {
+                        let _ = tx.send(Ok(stream)).await;
+                    }
+                    Err(e) => debug!("TLS handshake with REST client {peer} failed: {e}"),
+                }
+            });
+        }
+    });
+    accept::from_stream(stream! {
+        while let Some(connection) = rx.recv().await {
+            yield connection;
+        }
+    })
+}
+
+async fn book(
+    State(gateway): State<RestGateway>,
+    Path(symbol): Path<String>,
+    Query(query): Query<BookQuery>,
+    headers: HeaderMap,
+) -> Result<Json<JsonBook>, RestError> {
+    let principal = gateway.authenticate(&headers)?;
+    let symbol = gateway.hub.resolve_symbol(&symbol)?;
+    authorize_symbol(principal.as_deref(), &symbol)?;
+    let book = gateway.hub.current(&symbol).await?;
+    let depth = query.depth.unwrap_or(gateway.hub.config().max_depth_of(&symbol));
+    Ok(Json(JsonBook::new(&book, depth)))
+}
+
+/// Streams Server-Sent Events of the best bid and ask of each symbol whenever they move, at most once per interval.
+async fn top_of_book(
+    State(gateway): State<RestGateway>,
+    Query(query): Query<TopQuery>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, RestError> {
+    let principal = gateway.authenticate(&headers)?;
+    let hub = &gateway.hub;
+    let requested = match &query.symbols {
+        Some(symbols) => symbols.split(',').map(str::trim).map(str::to_string).collect(),
+        None => vec![hub.default_symbol().to_string()],
+    };
+    let interval = query
+        .interval_ms
+        .map_or(DEFAULT_TOP_INTERVAL, Duration::from_millis)
+        .max(MIN_TOP_INTERVAL);
+
+    let mut streams = Vec::with_capacity(requested.len());
+    for symbol in requested {
+        let symbol = hub.resolve_symbol(&symbol)?;
+        authorize_symbol(principal.as_deref(), &symbol)?;
+        let permit = match &principal {
+            Some(principal) => Some(principal.open_stream()?),
+            None => None,
+        };
+        // A single conflated slot leaves the latest book waiting at the end of each interval
+        let mut subscription = hub
+            .subscribe(&symbol, 1, 1, OverflowPolicy::Conflate)
+            .await?;
+        streams.push(Box::pin(stream! {
+            let _permit = permit;
+            let mut last: Option<JsonTop> = None;
+            while let Some(event) = subscription.next().await {
+                let book = match event {
+                    BookEvent::Update(book) => book,
+                    _ => continue,
+                };
+                let top = JsonTop::new(&book);
+                if last.as_ref().is_some_and(|last| !top.moved_from(last)) {
+                    continue;
+                }
+                match Event::default().event("top").json_data(&top) {
+                    Ok(event) => yield Ok(event),
+                    Err(e) => error!("could not serialise the {symbol} top of book: {e}"),
+                }
+                last = Some(top);
+                tokio::time::sleep(interval).await;
+            }
+        }));
+    }
+    let hub = hub.clone();
+    let events = select_all(streams).take_until(async move { hub.shutting_down().await });
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
+
+async fn venues(
+    State(gateway): State<RestGateway>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<JsonVenue>>, RestError> {
+    gateway.authenticate(&headers)?;
+    let venues = gateway.hub.venues().snapshot();
+    let mut leadership = HashMap::new();
+    Ok(Json(
+        venues
+            .into_iter()
+            .map(|health| {
+                let windows = leadership
+                    .entry(health.symbol.clone())
+                    .or_insert_with(|| gateway.hub.leadership(&health.symbol).unwrap_or_default());
+                let exchange = health.exchange;
+                JsonVenue::from(health).with_leadership(windows, exchange)
+            })
+            .collect(),
+    ))
+}
+
+/// Serves the latency of every venue in the Prometheus text format.
+async fn metrics(State(gateway): State<RestGateway>, headers: HeaderMap) -> Result<String, RestError> {
+    gateway.authenticate(&headers)?;
+    Ok(render_metrics(&gateway.hub.venues().snapshot()))
+}
+
+fn render_metrics(venues: &[VenueHealth]) -> String {
+    let mut latency = String::from(
+        "# HELP orderbook_venue_latency_ms Time messages took to arrive from a venue over the last minute\n\
+         # TYPE orderbook_venue_latency_ms summary\n",
+    );
+    let mut skew = String::from(
+        "# HELP orderbook_venue_clock_skew_ms Estimated offset of a venue's clock behind ours\n\
+         # TYPE orderbook_venue_clock_skew_ms gauge\n",
+    );
+    for venue in venues {
+        let summary = match &venue.latency {
+            Some(summary) => summary,
+            None => continue,
+        };
+        let labels = format!("exchange=\"{}\",symbol=\"{}\"", venue.exchange.to_string(), venue.symbol);
+        for (quantile, value) in [("0.5", summary.p50_ms), ("0.99", summary.p99_ms), ("1", summary.max_ms)] {
+            latency.push_str(&format!("orderbook_venue_latency_ms{{{labels},quantile=\"{quantile}\"}} {value}\n"));
+        }
+        latency.push_str(&format!("orderbook_venue_latency_ms_count{{{labels}}} {}\n", summary.samples));
+        skew.push_str(&format!("orderbook_venue_clock_skew_ms{{{labels}}} {}\n", summary.clock_skew_ms));
+    }
+    latency + &skew
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum::{
+        extract::{Path, Query, State},
+        http::{HeaderMap, StatusCode},
+        response::IntoResponse,
+    };
+
+    use crate::{
+        exchanges::{
+            status::{LatencySummary, ReconnectPolicy, VenueHealth, VenueState},
+            ExchangeType,
+        },
+        hub::{history::HistoryLimit, BookHub, HubConfig},
+        orderbook::fees::FeeSchedule,
+        server::auth::{ApiKey, Authenticator},
+    };
+
+    use super::{book, render_metrics, venues, BookQuery, RestGateway};
+
+    fn hub() -> BookHub {
+        BookHub::new(HubConfig {
+            symbols: vec![String::from("ethbtc")],
+            exchanges: vec![ExchangeType::Binance],
+            max_depth: 10,
+            reconnect: ReconnectPolicy::default(),
+            fees: FeeSchedule::default(),
+            synthetics: Vec::new(),
+            latency_threshold: None,
+            history: HistoryLimit::default(),
+            overrides: HashMap::new(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_unknown_symbol() {
+        let gateway = RestGateway::new(hub(), Authenticator::allow_all());
+        let query = Query(BookQuery { depth: None });
+        let error = book(State(gateway), Path(String::from("dogeusd")), query, HeaderMap::new())
+            .await
+            .unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_venues_require_key() {
+        let authenticator = Authenticator::new(vec![ApiKey {
+            key: String::from("secret"),
+            name: String::from("desk"),
+            symbols: vec![],
+            max_streams: None,
+        }])
+        .unwrap();
+        let gateway = RestGateway::new(hub(), authenticator);
+
+        let error = venues(State(gateway.clone()), HeaderMap::new()).await.unwrap_err();
+        assert_eq!(error.into_response().status(), StatusCode::UNAUTHORIZED);
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", "secret".parse().unwrap());
+        let venues = venues(State(gateway), headers).await.unwrap();
+        assert!(venues.0.is_empty());
+    }
+
+    #[test]
+    fn test_render_metrics() {
+        let venue = |exchange, latency| VenueHealth {
+            exchange,
+            symbol: String::from("ethbtc"),
+            state: VenueState::Live,
+            last_message: None,
+            messages: 0,
+            message_rate: 0.0,
+            errors: 0,
+            last_error: None,
+            latency,
+        };
+        let latency = LatencySummary {
+            samples: 10,
+            p50_ms: 12.5,
+            p99_ms: 40.0,
+            max_ms: 55.0,
+            clock_skew_ms: -3.0,
+        };
+        let metrics = render_metrics(&[
+            venue(ExchangeType::Binance, None),
+            venue(ExchangeType::Bitstamp, Some(latency)),
+        ]);
+        assert!(metrics.contains("orderbook_venue_latency_ms{exchange=\"Bitstamp\",symbol=\"ethbtc\",quantile=\"0.99\"} 40\n"));
+        assert!(metrics.contains("# TYPE orderbook_venue_latency_ms summary\n"));
+        assert!(metrics.contains("orderbook_venue_latency_ms_count{exchange=\"Bitstamp\",symbol=\"ethbtc\"} 10\n"));
+        assert!(metrics.contains("orderbook_venue_clock_skew_ms{exchange=\"Bitstamp\",symbol=\"ethbtc\"} -3\n"));
+        // Venues without timestamped messages are left out
+        assert!(!metrics.contains("Binance"));
+    }
+}
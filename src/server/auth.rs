@@ -0,0 +1,250 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+use tonic::{service::Interceptor, Request, Status};
+
+/// Request metadata carrying an API key
+const API_KEY_HEADER: &str = "x-api-key";
+/// Request metadata carrying a bearer token
+const AUTHORIZATION_HEADER: &str = "authorization";
+
+#[derive(Error, Debug)]
+pub enum AuthError {
+    #[error("could not read the API key file: {0}")]
+    Unreadable(#[from] std::io::Error),
+    #[error("could not parse the API key file: {0}")]
+    Malformed(#[from] serde_json::Error),
+    #[error("API key {0} is defined more than once")]
+    DuplicateKey(String),
+    #[error("an API key or bearer token is required")]
+    MissingKey,
+    #[error("unknown API key")]
+    UnknownKey,
+    #[error("the key may not subscribe to {0}")]
+    SymbolNotPermitted(String),
+    #[error("the key already has {0} open streams")]
+    TooManyStreams(usize),
+}
+
+impl From<AuthError> for Status {
+    fn from(error: AuthError) -> Self {
+        match error {
+            AuthError::SymbolNotPermitted(_) => Status::permission_denied(error.to_string()),
+            AuthError::TooManyStreams(_) => Status::resource_exhausted(error.to_string()),
+            AuthError::MissingKey | AuthError::UnknownKey => Status::unauthenticated(error.to_string()),
+            _ => Status::internal(error.to_string()),
+        }
+    }
+}
+
+/// An API key as defined in the key file.
+#[derive(Deserialize, Debug)]
+pub struct ApiKey {
+    /// The secret presented by clients, either as `x-api-key` or a bearer token
+    pub key: String,
+    /// A name for the holder of the key, used in logs
+    pub name: String,
+    /// Symbols the key may subscribe to, every symbol if empty
+    #[serde(default)]
+    pub symbols: Vec<String>,
+    /// The most streams the key may hold open at once, unlimited if absent
+    pub max_streams: Option<usize>,
+}
+
+#[derive(Deserialize, Debug)]
+struct KeyFile {
+    keys: Vec<ApiKey>,
+}
+
+/// The identity and permissions of an authenticated client, shared between all of its requests.
+#[derive(Debug)]
+pub struct Principal {
+    pub name: String,
+    symbols: Vec<String>,
+    max_streams: Option<usize>,
+    open_streams: AtomicUsize,
+}
+
+impl Principal {
+    /// Whether the client may subscribe to the orderbook of `symbol`.
+    pub fn may_subscribe(&self, symbol: &str) -> bool {
+        self.symbols.is_empty()
+            || self
+                .symbols
+                .iter()
+                .any(|permitted| permitted.eq_ignore_ascii_case(symbol))
+    }
+
+    /// Reserves one of the client's concurrent streams for as long as the permit is held.
+    pub fn open_stream(self: &Arc<Self>) -> Result<StreamPermit, AuthError> {
+        let open = self.open_streams.fetch_add(1, Ordering::SeqCst);
+        if let Some(max_streams) = self.max_streams {
+            if open >= max_streams {
+                self.open_streams.fetch_sub(1, Ordering::SeqCst);
+                return Err(AuthError::TooManyStreams(open));
+            }
+        }
+        Ok(StreamPermit {
+            principal: self.clone(),
+        })
+    }
+}
+
+/// Checks an authenticated client may read `symbol`, logging any refusal.
+///
+/// Clients are only authenticated when API keys are configured, so without a principal every symbol may be read.
+pub(crate) fn authorize_symbol(principal: Option<&Principal>, symbol: &str) -> Result<(), AuthError> {
+    match principal {
+        Some(principal) if !principal.may_subscribe(symbol) => {
+            warn!("{} may not subscribe to {symbol}", principal.name);
+            Err(AuthError::SymbolNotPermitted(symbol.to_string()))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Releases a stream reserved with `Principal::open_stream` when dropped.
+pub struct StreamPermit {
+    principal: Arc<Principal>,
+}
+
+impl Drop for StreamPermit {
+    fn drop(&mut self) {
+        self.principal.open_streams.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// Checks that every request presents a known API key, attaching its `Principal` to the request.
+#[derive(Clone)]
+pub struct Authenticator {
+    /// Every request is let through when there are no keys
+    keys: Option<Arc<HashMap<String, Arc<Principal>>>>,
+}
+
+impl Authenticator {
+    /// An authenticator that lets every request through unauthenticated.
+    pub fn allow_all() -> Self {
+        Self { keys: None }
+    }
+
+    pub fn new(keys: Vec<ApiKey>) -> Result<Self, AuthError> {
+        let mut principals = HashMap::with_capacity(keys.len());
+        for key in keys {
+            let principal = Arc::new(Principal {
+                name: key.name,
+                symbols: key.symbols,
+                max_streams: key.max_streams,
+                open_streams: AtomicUsize::new(0),
+            });
+            if principals.insert(key.key, principal.clone()).is_some() {
+                return Err(AuthError::DuplicateKey(principal.name.clone()));
+            }
+        }
+        Ok(Self {
+            keys: Some(Arc::new(principals)),
+        })
+    }
+
+    /// Loads keys from a JSON file of the form `{"keys": [{"key": "..", "name": "..", "symbols": [..], "max_streams": 2}]}`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, AuthError> {
+        let file: KeyFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Self::new(file.keys)
+    }
+
+    /// Looks up the principal holding `key`, which is `None` when every client is let through.
+    pub fn authenticate(&self, key: Option<&str>) -> Result<Option<Arc<Principal>>, AuthError> {
+        let keys = match &self.keys {
+            Some(keys) => keys,
+            None => return Ok(None),
+        };
+        let key = key.ok_or(AuthError::MissingKey)?;
+        keys.get(key.trim()).cloned().map(Some).ok_or(AuthError::UnknownKey)
+    }
+
+    fn presented_key<T>(request: &Request<T>) -> Option<&str> {
+        let metadata = request.metadata();
+        if let Some(key) = metadata.get(API_KEY_HEADER) {
+            return key.to_str().ok();
+        }
+        metadata
+            .get(AUTHORIZATION_HEADER)?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")
+    }
+}
+
+impl Interceptor for Authenticator {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        if let Some(principal) = self.authenticate(Self::presented_key(&request))? {
+            request.extensions_mut().insert(principal);
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tonic::{service::Interceptor, Request};
+
+    use super::{ApiKey, Authenticator, Principal};
+
+    fn authenticator() -> Authenticator {
+        Authenticator::new(vec![ApiKey {
+            key: String::from("secret"),
+            name: String::from("desk"),
+            symbols: vec![String::from("ethbtc")],
+            max_streams: Some(1),
+        }])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_authentication() {
+        let mut auth = authenticator();
+        assert!(auth.call(Request::new(())).is_err());
+
+        let mut request = Request::new(());
+        request.metadata_mut().insert("x-api-key", "wrong".parse().unwrap());
+        assert!(auth.call(request).is_err());
+
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", "Bearer secret".parse().unwrap());
+        let request = auth.call(request).unwrap();
+        let principal = request.extensions().get::<std::sync::Arc<Principal>>().unwrap();
+        assert_eq!(principal.name, "desk");
+        assert!(principal.may_subscribe("ETHBTC"));
+        assert!(!principal.may_subscribe("btcusdt"));
+    }
+
+    #[test]
+    fn test_allow_all() {
+        let request = Authenticator::allow_all().call(Request::new(())).unwrap();
+        assert!(request.extensions().get::<std::sync::Arc<Principal>>().is_none());
+    }
+
+    #[test]
+    fn test_stream_limit() {
+        let mut auth = authenticator();
+        let mut request = Request::new(());
+        request.metadata_mut().insert("x-api-key", "secret".parse().unwrap());
+        let request = auth.call(request).unwrap();
+        let principal = request.extensions().get::<std::sync::Arc<Principal>>().unwrap();
+
+        let permit = principal.open_stream().unwrap();
+        assert!(principal.open_stream().is_err());
+        drop(permit);
+        assert!(principal.open_stream().is_ok());
+    }
+}
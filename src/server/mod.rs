@@ -1,104 +1,468 @@
 pub mod orderbook_rpc {
     tonic::include_proto!("orderbook");
 }
+pub mod auth;
+pub mod subscriptions;
+pub mod v1;
 
-use futures::{pin_mut, StreamExt};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, UNIX_EPOCH};
+
+use async_stream::stream;
+use futures::{Stream, StreamExt};
 use orderbook_rpc::orderbook_aggregator_server::OrderbookAggregator;
-use orderbook_rpc::{Empty, Level, Summary};
-use tokio::sync::mpsc;
-use tokio_stream::wrappers::UnboundedReceiverStream;
+use orderbook_rpc::{
+    Empty, Level, Subscription as SubscriptionStatus, SubscriptionList, Summary, Venue,
+    VenueStatusUpdate,
+};
+use tokio::sync::broadcast::{self, error::RecvError};
 use tonic::{Request, Response, Status};
 
-use crate::exchanges::ExchangeType;
+use crate::analytics::execution::ExecutionError;
+use crate::exchanges::status::{VenueHealth, VenueState};
+use crate::hub::{BookEvent, BookHub, BookSubscription, HubError, MergedBook};
 use crate::orderbook::{
-    builder::{Empty as EmptyOrderbook, OrderbookBuilder},
-    streaming_book::HeapedBook,
-    Orderbook,
+    levels::{AskLevel, BidLevel},
+    summary::BookStatistics,
 };
+use crate::queue::{self, OverflowPolicy, QueueError, QueueReceiver, QueueSender};
+use auth::{authorize_symbol, AuthError, Principal, StreamPermit};
+use subscriptions::{Subscription, SubscriptionGuard, SubscriptionRegistry};
+
+/// How often venue status subscribers are sent a refreshed view when nothing changes state
+const VENUE_STATUS_INTERVAL: Duration = Duration::from_secs(1);
+/// Updates queued for a client before the overflow policy applies, unless configured otherwise
+pub const DEFAULT_QUEUE_CAPACITY: usize = 64;
+/// Request metadata a client may set to override the capacity of its queue
+const QUEUE_CAPACITY_HEADER: &str = "x-queue-capacity";
+/// Request metadata a client may set to override the overflow policy of its queue
+const OVERFLOW_POLICY_HEADER: &str = "x-overflow-policy";
+/// The final status sent to every open stream when the server shuts down
+const SHUTDOWN_MESSAGE: &str = "server is shutting down";
 
+type ResponseStream<T> = Pin<Box<dyn Stream<Item = Result<T, Status>> + Send>>;
+
+/// Serves the merged books of a `BookHub` over gRPC.
+///
+/// Open streams end with `Unavailable` once the hub is shut down.
 pub struct OrderbookSummaryService {
-    max_depth: usize,
-    symbol: String,
-    exchanges: Vec<ExchangeType>
+    hub: BookHub,
+    queue_capacity: usize,
+    overflow_policy: OverflowPolicy,
+    subscriptions: SubscriptionRegistry,
 }
 
 impl OrderbookSummaryService {
-    pub fn new<S: Into<String>>(symbol: S, max_depth:usize, exchanges: &[ExchangeType]) -> Self {
-        Self { 
-            symbol: symbol.into(),
-            max_depth,
-            exchanges: exchanges.into()
-         }
+    pub fn new(hub: BookHub) -> Self {
+        Self {
+            hub,
+            queue_capacity: DEFAULT_QUEUE_CAPACITY,
+            overflow_policy: OverflowPolicy::default(),
+            subscriptions: SubscriptionRegistry::default(),
+        }
+    }
+
+    /// Shuts down the hub, ending every open stream with `Unavailable` and tearing down their exchange feeds.
+    pub fn shutdown(&self) {
+        info!("closing {} client streams", self.subscriptions.snapshot().len());
+        self.hub.shutdown();
+    }
+
+    /// Sets the default queue each client stream is given, which clients may override with request metadata.
+    pub fn with_client_queue(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.queue_capacity = capacity;
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// The v1 service, sharing the books, subscriptions and queue defaults of this one.
+    pub fn v1(&self) -> v1::OrderbookService {
+        v1::OrderbookService::new(
+            self.hub.clone(),
+            self.subscriptions.clone(),
+            self.queue_capacity,
+            self.overflow_policy,
+        )
+    }
+
+    /// The queue capacity and overflow policy of a new client stream, honouring any overrides in the request metadata.
+    fn queue_settings<R>(
+        &self,
+        kind: &'static str,
+        request: &Request<R>,
+    ) -> Result<(usize, OverflowPolicy), QueueError> {
+        let metadata = request.metadata();
+        let capacity = match metadata.get(QUEUE_CAPACITY_HEADER) {
+            Some(value) => value
+                .to_str()
+                .ok()
+                .and_then(|value| value.parse::<usize>().ok())
+                .filter(|capacity| *capacity > 0)
+                .ok_or(QueueError::InvalidCapacity)?,
+            None => self.queue_capacity,
+        };
+        let policy = match metadata.get(OVERFLOW_POLICY_HEADER) {
+            Some(value) => OverflowPolicy::from_str(
+                value.to_str().map_err(|_| QueueError::UnknownPolicy)?,
+            )?,
+            None => self.overflow_policy,
+        };
+        info!("{} subscribed to {kind} with a {policy} queue of {capacity}", peer(request));
+        Ok((capacity, policy))
+    }
+}
+
+/// Checks an authenticated client may stream `symbol`, reserving one of its concurrent streams.
+///
+/// Requests are only authenticated when the service is wrapped in an `Authenticator`.
+fn authorize<R>(
+    request: &Request<R>,
+    symbol: Option<&str>,
+) -> Result<Option<StreamPermit>, AuthError> {
+    if let Some(symbol) = symbol {
+        authorize_symbol(principal(request), symbol)?;
+    }
+    match request.extensions().get::<Arc<Principal>>() {
+        Some(principal) => principal.open_stream().map(Some),
+        None => Ok(None),
+    }
+}
+
+/// The client a request was authenticated as, if the service is wrapped in an `Authenticator`.
+fn principal<R>(request: &Request<R>) -> Option<&Principal> {
+    request.extensions().get::<Arc<Principal>>().map(Arc::as_ref)
+}
+
+fn peer<R>(request: &Request<R>) -> String {
+    request
+        .remote_addr()
+        .map_or_else(|| String::from("unknown"), |addr| addr.to_string())
+}
+
+/// Pushes an update to a client, returning false once nothing more should be sent to it.
+///
+/// A client that overflows a `Disconnect` queue is sent `ResourceExhausted` and closed.
+fn deliver<T>(tx: &QueueSender<Result<T, Status>>, update: Result<T, Status>) -> bool {
+    match tx.push(update) {
+        Ok(()) => true,
+        Err(QueueError::Overflow) => {
+            warn!("disconnecting a client that is not keeping up");
+            tx.close_with(Err(Status::resource_exhausted(
+                "client is not consuming updates quickly enough",
+            )));
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+/// Streams the book behind `subscription`, rendering each update until the client falls behind, the book
+/// closes or the hub shuts down.
+fn book_stream<T, F>(
+    hub: &BookHub,
+    mut subscription: BookSubscription,
+    registration: SubscriptionGuard,
+    permit: Option<StreamPermit>,
+    mut render: F,
+) -> ResponseStream<T>
+where
+    T: Send + 'static,
+    F: FnMut(&MergedBook, usize) -> T + Send + 'static,
+{
+    let mut shutdown = hub.shutdown_signal();
+    Box::pin(stream! {
+        let _registration = registration;
+        let _permit = permit;
+        loop {
+            let event = tokio::select! {
+                _ = shutdown.changed() => {
+                    yield Err(Status::unavailable(SHUTDOWN_MESSAGE));
+                    break;
+                }
+                event = subscription.next() => event,
+            };
+            match event {
+                Some(BookEvent::Update(book)) => yield Ok(render(&book, subscription.depth())),
+                // Exchange errors are reported by VenueStatus, the book carries on without them
+                Some(BookEvent::FeedError(_)) => {}
+                Some(BookEvent::Overflowed) => {
+                    warn!("disconnecting a client that is not keeping up");
+                    yield Err(Status::resource_exhausted(
+                        "client is not consuming updates quickly enough",
+                    ));
+                    break;
+                }
+                None => {
+                    yield Err(Status::data_loss("could not retrieve update from orderbook"));
+                    break;
+                }
+            }
+        }
+    })
+}
+
+/// Publishes the health of every venue to `tx` whenever one changes state, and periodically otherwise.
+fn venue_stream<T, F>(
+    hub: &BookHub,
+    tx: QueueSender<Result<T, Status>>,
+    registration: SubscriptionGuard,
+    permit: Option<StreamPermit>,
+    render: F,
+) where
+    T: Send + 'static,
+    F: Fn(Vec<VenueHealth>) -> T + Send + 'static,
+{
+    let venues = hub.venues().clone();
+    let mut changes = venues.subscribe();
+    let mut shutdown = hub.shutdown_signal();
+    tokio::spawn(async move {
+        let _registration = registration;
+        let _permit = permit;
+        let mut refresh = tokio::time::interval(VENUE_STATUS_INTERVAL);
+        loop {
+            // Publish immediately on a state change, otherwise periodically to refresh rates
+            tokio::select! {
+                _ = refresh.tick() => {},
+                _ = tx.closed() => break,
+                _ = shutdown.changed() => {
+                    tx.close_with(Err(Status::unavailable(SHUTDOWN_MESSAGE)));
+                    break;
+                }
+                change = changes.recv() => {
+                    if let Err(RecvError::Closed) = change {
+                        break;
+                    }
+                }
+            }
+            if !deliver(&tx, Ok(render(venues.snapshot()))) {
+                break;
+            }
+        }
+    });
+}
+
+/// Forwards what a shared analysis or trade tape publishes to a client until either closes or the hub shuts down.
+///
+/// Outputs the client's receiver missed because the publisher outpaced it are skipped, as are those `render`
+/// does not render.
+fn broadcast_stream<O, T, F>(
+    hub: &BookHub,
+    mut outputs: broadcast::Receiver<O>,
+    tx: QueueSender<Result<T, Status>>,
+    registration: SubscriptionGuard,
+    permit: Option<StreamPermit>,
+    render: F,
+) where
+    O: Clone + Send + 'static,
+    T: Send + 'static,
+    F: Fn(O) -> Option<T> + Send + 'static,
+{
+    let mut shutdown = hub.shutdown_signal();
+    tokio::spawn(async move {
+        let _registration = registration;
+        let _permit = permit;
+        loop {
+            let output = tokio::select! {
+                _ = tx.closed() => break,
+                _ = shutdown.changed() => {
+                    tx.close_with(Err(Status::unavailable(SHUTDOWN_MESSAGE)));
+                    break;
+                }
+                output = outputs.recv() => match output {
+                    Ok(output) => output,
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("a client missed {missed} broadcast updates");
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                },
+            };
+            let rendered = match render(output) {
+                Some(rendered) => rendered,
+                None => continue,
+            };
+            if !deliver(&tx, Ok(rendered)) {
+                break;
+            }
+        }
+    });
+}
+
+impl From<ExecutionError> for Status {
+    fn from(error: ExecutionError) -> Self {
+        match error {
+            ExecutionError::InvalidSize(_) => Status::invalid_argument(error.to_string()),
+            ExecutionError::EmptySide(_) => Status::failed_precondition(error.to_string()),
+        }
+    }
+}
+
+impl From<HubError> for Status {
+    fn from(error: HubError) -> Self {
+        match error {
+            HubError::UnknownSymbol(_) => Status::not_found(error.to_string()),
+            HubError::StartFailed { .. } => Status::aborted("could not create orderbook"),
+            HubError::ShuttingDown => Status::unavailable(SHUTDOWN_MESSAGE),
+            HubError::NoBook(_) => Status::unavailable(error.to_string()),
+        }
+    }
+}
+
+impl From<&BidLevel> for Level {
+    fn from(bid: &BidLevel) -> Self {
+        Level {
+            price: bid.price,
+            amount: bid.amount,
+            exchange: bid.exchange.to_string(),
+        }
+    }
+}
+
+impl From<&AskLevel> for Level {
+    fn from(ask: &AskLevel) -> Self {
+        Level {
+            price: ask.price,
+            amount: ask.amount,
+            exchange: ask.exchange.to_string(),
+        }
+    }
+}
+
+/// Builds the `Summary` sent to clients from at most `depth` levels of each side of a merged book.
+pub(crate) fn summarise(book: &MergedBook, depth: usize) -> Summary {
+    let (bids, asks) = book.truncated(depth);
+    let statistics = BookStatistics::new(bids, asks);
+    Summary {
+        spread: statistics.spread,
+        bids: bids.iter().map(Level::from).collect(),
+        asks: asks.iter().map(Level::from).collect(),
+        best_bid: statistics.best_bid.as_ref().map(Level::from),
+        best_ask: statistics.best_ask.as_ref().map(Level::from),
+        mid_price: statistics.mid_price,
+        microprice: statistics.microprice,
+        total_bid_volume: statistics.total_bid_volume,
+        total_ask_volume: statistics.total_ask_volume,
+        sequence: book.sequence,
+        symbol: book.symbol.clone(),
+        timestamp_ms: book
+            .timestamp
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64),
+    }
+}
+
+impl From<Subscription> for SubscriptionStatus {
+    fn from(subscription: Subscription) -> Self {
+        SubscriptionStatus {
+            id: subscription.id,
+            kind: subscription.kind.to_string(),
+            peer: subscription.peer,
+            policy: subscription.queue.policy().to_string(),
+            capacity: subscription.queue.capacity() as u64,
+            depth: subscription.queue.depth() as u64,
+            dropped: subscription.queue.dropped(),
+            delivered: subscription.queue.delivered(),
+        }
+    }
+}
+
+impl From<VenueState> for orderbook_rpc::VenueState {
+    fn from(state: VenueState) -> Self {
+        match state {
+            VenueState::Connecting => Self::Connecting,
+            VenueState::Subscribed => Self::Subscribed,
+            VenueState::Live => Self::Live,
+            VenueState::Stale => Self::Stale,
+            VenueState::Reconnecting => Self::Reconnecting,
+            VenueState::Failed => Self::Failed,
+        }
+    }
+}
+
+impl From<VenueHealth> for Venue {
+    fn from(health: VenueHealth) -> Self {
+        Venue {
+            exchange: health.exchange.to_string(),
+            symbol: health.symbol,
+            state: orderbook_rpc::VenueState::from(health.state).into(),
+            last_message_ms: health
+                .last_message
+                .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_millis() as u64),
+            messages: health.messages,
+            message_rate: health.message_rate,
+            errors: health.errors,
+            last_error: health.last_error.unwrap_or_default(),
+        }
     }
 }
 
 #[tonic::async_trait]
 impl OrderbookAggregator for OrderbookSummaryService {
-    type BookSummaryStream = UnboundedReceiverStream<Result<Summary, Status>>;
+    type BookSummaryStream = ResponseStream<Summary>;
+    type VenueStatusStream = QueueReceiver<Result<VenueStatusUpdate, Status>>;
 
     async fn book_summary(
         &self,
-        _request: Request<Empty>,
+        request: Request<Empty>,
     ) -> Result<Response<Self::BookSummaryStream>, Status> {
-        let (tx, rx) = mpsc::unbounded_channel::<Result<Summary, Status>>();
-        let orderbook_builder = OrderbookBuilder::<EmptyOrderbook>::new();
-        let orderbook_builder = orderbook_builder
-            .with_max_depth(self.max_depth)
-            .with_symbol(self.symbol.clone())
-            .with_exchanges(&self.exchanges);
-
-        let mut orderbook = match orderbook_builder.build::<HeapedBook>().await {
-            Ok(orderbook) => orderbook,
-            Err(e) => {
-                error!("orderbook could not be created: {e}");
-                return Err(Status::aborted("could not create orderbook"));
-            }
-        };
-        info!("new orderbook initialised");
-        tokio::spawn(async move {
-            let orderbook_stream = orderbook.collect();
-            pin_mut!(orderbook_stream);
-            while let Some(event) = orderbook_stream.next().await {
-                match event {
-                    Ok(snapshot) => {
-                        let summary_bids = snapshot
-                            .0
-                            .iter()
-                            .map(|bid| Level {
-                                price: bid.price,
-                                amount: bid.amount,
-                                exchange: bid.exchange.to_string(),
-                            })
-                            .collect::<Vec<Level>>();
-
-                        let summary_asks = snapshot
-                            .1
-                            .iter()
-                            .map(|ask| Level {
-                                price: ask.price,
-                                amount: ask.amount,
-                                exchange: ask.exchange.to_string(),
-                            })
-                            .collect::<Vec<Level>>();
-
-                        tx.send(Ok(Summary {
-                            spread: summary_asks[0].price - summary_bids[0].price,
-                            bids: summary_bids,
-                            asks: summary_asks,
-                        }))
-                        .unwrap();
-                    }
-                    Err(e) => {
-                        error!("stream returned none: {e}");
-                        tx.send(Err(Status::data_loss(
-                            "could not retrieve update from orderbook",
-                        )))
-                        .unwrap();
-                    }
-                };
-            }
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let symbol = self.hub.default_symbol().to_string();
+        let permit = authorize(&request, Some(&symbol))?;
+        let (capacity, policy) = self
+            .queue_settings("book_summary", &request)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let depth = self.hub.config().max_depth_of(&symbol);
+        let subscription = self.hub.subscribe(&symbol, depth, capacity, policy).await?;
+        let registration = self.subscriptions.register(
+            "book_summary",
+            peer(&request),
+            subscription.metrics(),
+        );
+        let summaries = book_stream(&self.hub, subscription, registration, permit, summarise);
+
+        Ok(Response::new(summaries))
+    }
+
+    async fn venue_status(
+        &self,
+        request: Request<Empty>,
+    ) -> Result<Response<Self::VenueStatusStream>, Status> {
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let permit = authorize(&request, None)?;
+        let (capacity, policy) = self
+            .queue_settings("venue_status", &request)
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let (tx, rx) = queue::bounded::<Result<VenueStatusUpdate, Status>>(capacity, policy);
+        let subscription = self.subscriptions.register(
+            "venue_status",
+            peer(&request),
+            tx.metrics(),
+        );
+        venue_stream(&self.hub, tx, subscription, permit, |venues| VenueStatusUpdate {
+            venues: venues.into_iter().map(Venue::from).collect(),
         });
 
-        Ok(Response::new(UnboundedReceiverStream::new(rx)))
+        Ok(Response::new(rx))
+    }
+
+    async fn subscriptions(
+        &self,
+        _request: Request<Empty>,
+    ) -> Result<Response<SubscriptionList>, Status> {
+        Ok(Response::new(SubscriptionList {
+            subscriptions: self
+                .subscriptions
+                .snapshot()
+                .into_iter()
+                .map(SubscriptionStatus::from)
+                .collect(),
+        }))
     }
 }
@@ -0,0 +1,72 @@
+use std::{
+    collections::BTreeMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use crate::queue::QueueMetrics;
+
+/// A live client subscription and the queue feeding it.
+#[derive(Clone)]
+pub struct Subscription {
+    pub id: u64,
+    /// The RPC the client subscribed to
+    pub kind: &'static str,
+    pub peer: String,
+    pub queue: QueueMetrics,
+}
+
+/// Keeps track of every open client stream so that their queues can be inspected.
+#[derive(Clone, Default)]
+pub struct SubscriptionRegistry {
+    next_id: Arc<AtomicU64>,
+    subscriptions: Arc<Mutex<BTreeMap<u64, Subscription>>>,
+}
+
+impl SubscriptionRegistry {
+    /// Registers a subscription, which is removed again once the returned guard is dropped.
+    pub fn register<S: Into<String>>(
+        &self,
+        kind: &'static str,
+        peer: S,
+        queue: QueueMetrics,
+    ) -> SubscriptionGuard {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let subscription = Subscription {
+            id,
+            kind,
+            peer: peer.into(),
+            queue,
+        };
+        self.subscriptions.lock().unwrap().insert(id, subscription);
+        SubscriptionGuard {
+            id,
+            registry: self.clone(),
+        }
+    }
+
+    /// Every open subscription, oldest first.
+    pub fn snapshot(&self) -> Vec<Subscription> {
+        self.subscriptions.lock().unwrap().values().cloned().collect()
+    }
+}
+
+/// Removes its subscription from the registry when dropped.
+pub struct SubscriptionGuard {
+    id: u64,
+    registry: SubscriptionRegistry,
+}
+
+impl SubscriptionGuard {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+}
+
+impl Drop for SubscriptionGuard {
+    fn drop(&mut self) {
+        self.registry.subscriptions.lock().unwrap().remove(&self.id);
+    }
+}
@@ -0,0 +1,832 @@
+pub mod rpc {
+    tonic::include_proto!("orderbook.v1");
+}
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_stream::stream;
+
+use rpc::orderbook_service_server::OrderbookService as OrderbookServiceRpc;
+use rpc::{
+    book_update::Update, estimate_execution_request::Size as SizeMessage, opportunity_event::Event,
+    AlertEvent, AlertsRequest, Allocation as AllocationMessage, BookDelta, BookMetricsRequest,
+    BookMetricsUpdate, BookSnapshot, BookStatistics as StatisticsMessage, BookUpdate,
+    DepthBand as DepthBandMessage, EstimateExecutionRequest, ExecutionEstimate, GetBookAtRequest,
+    GetBookSnapshotRequest, GetVenueLeadershipRequest, HistoryRequest,
+    Imbalance as ImbalanceMessage, Latency as LatencyMessage,
+    LeadershipWindow as LeadershipWindowMessage, Level, ListSubscriptionsRequest,
+    ListSubscriptionsResponse, OpportunitiesRequest, Opportunity as OpportunityMessage,
+    OpportunityEnded, OpportunityEvent as OpportunityEventMessage, QueueOptions,
+    StreamVenueStatusRequest, SubscribeBookRequest, Subscription as SubscriptionStatus,
+    Trade as TradeMessage, TradesRequest, Venue, VenueLeadership as VenueLeadershipMessage,
+    VenueLeadershipReport, VenueShare as VenueShareMessage, VenueStatusUpdate,
+};
+use tonic::{Request, Response, Status};
+
+use crate::alerts::{Alert, AlertEngine, AlertState};
+use crate::analytics::{
+    arbitrage::{ArbitrageDetector, Opportunity, OpportunityEvent},
+    execution::{self, Allocation, Estimate, Side, Size},
+    metrics::{BookMetrics, MetricsAnalysis, MetricsConfig},
+    SharedAnalysis,
+};
+use crate::exchanges::{
+    status::{VenueHealth, VenueState},
+    Aggressor, Trade,
+};
+use crate::hub::{BookHub, HubError, MergedBook};
+use crate::orderbook::{
+    fees::FeeSchedule,
+    leadership::{LeadershipWindow, VenueLeadership},
+    levels::{AskLevel, BidLevel},
+    summary::BookStatistics,
+};
+use crate::queue::{self, OverflowPolicy, QueueReceiver};
+use crate::trades::TradeTape;
+
+use super::auth::authorize_symbol;
+use super::subscriptions::{Subscription, SubscriptionRegistry};
+use super::{
+    authorize, book_stream, broadcast_stream, peer, principal, venue_stream, ResponseStream, SHUTDOWN_MESSAGE,
+};
+
+/// Returned by the time-travel RPCs when the server retains no history
+const HISTORY_DISABLED_MESSAGE: &str = "the server retains no book history, see --history-books and --history-secs";
+
+/// Serves the `orderbook.v1.OrderbookService` API from the same books and subscriptions as the legacy service.
+pub struct OrderbookService {
+    hub: BookHub,
+    subscriptions: SubscriptionRegistry,
+    queue_capacity: usize,
+    overflow_policy: OverflowPolicy,
+    arbitrage: SharedAnalysis<ArbitrageDetector>,
+    metrics: SharedAnalysis<MetricsAnalysis>,
+    trades: TradeTape,
+    alerts: AlertEngine,
+}
+
+impl OrderbookService {
+    pub fn new(
+        hub: BookHub,
+        subscriptions: SubscriptionRegistry,
+        queue_capacity: usize,
+        overflow_policy: OverflowPolicy,
+    ) -> Self {
+        let fees = hub.config().fees.clone();
+        let arbitrage = SharedAnalysis::new(hub.clone(), move |symbol| {
+            ArbitrageDetector::new(symbol, fees.clone())
+        });
+        let metrics = SharedAnalysis::new(hub.clone(), |_: &str| MetricsAnalysis::new(MetricsConfig::default()));
+        Self {
+            trades: TradeTape::new(hub.clone()),
+            alerts: AlertEngine::new(hub.clone(), Vec::new()),
+            hub,
+            subscriptions,
+            queue_capacity,
+            overflow_policy,
+            arbitrage,
+            metrics,
+        }
+    }
+
+    /// Streams metrics from `metrics` rather than an analysis with the default configuration, so they can be shared.
+    pub fn with_metrics(mut self, metrics: SharedAnalysis<MetricsAnalysis>) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Streams the alerts of `alerts` rather than an engine without rules.
+    pub fn with_alerts(mut self, alerts: AlertEngine) -> Self {
+        self.alerts = alerts;
+        self
+    }
+
+    /// Resolves a requested symbol, an empty one meaning the default symbol.
+    fn symbol(&self, requested: &str) -> Result<String, HubError> {
+        if requested.is_empty() {
+            return Ok(self.hub.default_symbol().to_string());
+        }
+        self.hub.resolve_symbol(requested)
+    }
+
+    /// Levels on each side of a symbol's book for a requested depth, zero meaning every level held.
+    fn depth(&self, symbol: &str, requested: u32) -> usize {
+        let max_depth = self.hub.config().max_depth_of(symbol);
+        match requested as usize {
+            0 => max_depth,
+            depth => depth.min(max_depth),
+        }
+    }
+
+    /// The fees to rank levels by, when a client asks for fee-adjusted ordering.
+    fn ranking(&self, ordering: i32) -> Option<FeeSchedule> {
+        match rpc::BookOrdering::from_i32(ordering) {
+            Some(rpc::BookOrdering::FeeAdjusted) => Some(self.hub.config().fees.clone()),
+            _ => None,
+        }
+    }
+
+    /// The queue capacity and overflow policy of a new client stream, with unset options taking the defaults.
+    fn queue_settings<R>(
+        &self,
+        kind: &'static str,
+        request: &Request<R>,
+        options: Option<&QueueOptions>,
+    ) -> (usize, OverflowPolicy) {
+        let capacity = options
+            .map(|options| options.capacity as usize)
+            .filter(|capacity| *capacity > 0)
+            .unwrap_or(self.queue_capacity);
+        let policy = options
+            .and_then(|options| rpc::OverflowPolicy::from_i32(options.overflow_policy))
+            .and_then(|policy| match policy {
+                rpc::OverflowPolicy::Unspecified => None,
+                rpc::OverflowPolicy::DropOldest => Some(OverflowPolicy::DropOldest),
+                rpc::OverflowPolicy::Conflate => Some(OverflowPolicy::Conflate),
+                rpc::OverflowPolicy::Disconnect => Some(OverflowPolicy::Disconnect),
+            })
+            .unwrap_or(self.overflow_policy);
+        info!("{} subscribed to {kind} with a {policy} queue of {capacity}", peer(request));
+        (capacity, policy)
+    }
+}
+
+impl From<&BidLevel> for Level {
+    fn from(bid: &BidLevel) -> Self {
+        Level {
+            exchange: bid.exchange.to_string(),
+            price: bid.price,
+            amount: bid.amount,
+            delayed: false,
+        }
+    }
+}
+
+impl From<&AskLevel> for Level {
+    fn from(ask: &AskLevel) -> Self {
+        Level {
+            exchange: ask.exchange.to_string(),
+            price: ask.price,
+            amount: ask.amount,
+            delayed: false,
+        }
+    }
+}
+
+impl From<BookStatistics> for StatisticsMessage {
+    fn from(statistics: BookStatistics) -> Self {
+        StatisticsMessage {
+            best_bid: statistics.best_bid.as_ref().map(Level::from),
+            best_ask: statistics.best_ask.as_ref().map(Level::from),
+            spread: statistics.spread,
+            mid_price: statistics.mid_price,
+            microprice: statistics.microprice,
+            total_bid_volume: statistics.total_bid_volume,
+            total_ask_volume: statistics.total_ask_volume,
+        }
+    }
+}
+
+impl From<OverflowPolicy> for rpc::OverflowPolicy {
+    fn from(policy: OverflowPolicy) -> Self {
+        match policy {
+            OverflowPolicy::DropOldest => Self::DropOldest,
+            OverflowPolicy::Conflate => Self::Conflate,
+            OverflowPolicy::Disconnect => Self::Disconnect,
+        }
+    }
+}
+
+impl From<Subscription> for SubscriptionStatus {
+    fn from(subscription: Subscription) -> Self {
+        SubscriptionStatus {
+            id: subscription.id,
+            kind: subscription.kind.to_string(),
+            peer: subscription.peer,
+            overflow_policy: rpc::OverflowPolicy::from(subscription.queue.policy()).into(),
+            capacity: subscription.queue.capacity() as u64,
+            depth: subscription.queue.depth() as u64,
+            dropped: subscription.queue.dropped(),
+            delivered: subscription.queue.delivered(),
+        }
+    }
+}
+
+impl From<VenueState> for rpc::VenueState {
+    fn from(state: VenueState) -> Self {
+        match state {
+            VenueState::Connecting => Self::Connecting,
+            VenueState::Subscribed => Self::Subscribed,
+            VenueState::Live => Self::Live,
+            VenueState::Stale => Self::Stale,
+            VenueState::Reconnecting => Self::Reconnecting,
+            VenueState::Failed => Self::Failed,
+        }
+    }
+}
+
+impl From<VenueHealth> for Venue {
+    fn from(health: VenueHealth) -> Self {
+        Venue {
+            exchange: health.exchange.to_string(),
+            symbol: health.symbol,
+            state: rpc::VenueState::from(health.state).into(),
+            last_message_ms: health
+                .last_message
+                .and_then(|at| at.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_millis() as u64),
+            messages: health.messages,
+            message_rate: health.message_rate,
+            errors: health.errors,
+            last_error: health.last_error.unwrap_or_default(),
+            leadership: Vec::new(),
+            latency: health.latency.map(|latency| LatencyMessage {
+                samples: latency.samples as u64,
+                p50_ms: latency.p50_ms,
+                p99_ms: latency.p99_ms,
+                max_ms: latency.max_ms,
+                clock_skew_ms: latency.clock_skew_ms,
+            }),
+        }
+    }
+}
+
+impl From<&VenueLeadership> for VenueLeadershipMessage {
+    fn from(leadership: &VenueLeadership) -> Self {
+        Self {
+            exchange: leadership.exchange.to_string(),
+            best_bid_share: leadership.best_bid_share,
+            best_ask_share: leadership.best_ask_share,
+            bid_moves: leadership.bid_moves,
+            ask_moves: leadership.ask_moves,
+            spread_contribution: leadership.spread_contribution,
+        }
+    }
+}
+
+impl From<&LeadershipWindow> for LeadershipWindowMessage {
+    fn from(window: &LeadershipWindow) -> Self {
+        Self {
+            window_seconds: window.window.as_secs(),
+            covered_ms: window.covered.as_millis() as u64,
+            venues: window.venues.iter().map(VenueLeadershipMessage::from).collect(),
+        }
+    }
+}
+
+/// The health of every venue, with its leadership of the books that are live.
+fn venue_status(hub: &BookHub, venues: Vec<VenueHealth>) -> VenueStatusUpdate {
+    // Venues of the same symbol share its leadership windows, so they are looked up once per symbol
+    let mut leadership: HashMap<String, Vec<LeadershipWindow>> = HashMap::new();
+    let venues = venues
+        .into_iter()
+        .map(|health| {
+            let windows = leadership
+                .entry(health.symbol.clone())
+                .or_insert_with(|| hub.leadership(&health.symbol).unwrap_or_default());
+            let exchange = health.exchange;
+            let mut venue = Venue::from(health);
+            venue.leadership = windows
+                .iter()
+                .filter_map(|window| {
+                    let mut message = LeadershipWindowMessage::from(window);
+                    message.venues = vec![VenueLeadershipMessage::from(window.venue(exchange)?)];
+                    Some(message)
+                })
+                .collect();
+            venue
+        })
+        .collect();
+    VenueStatusUpdate { venues }
+}
+
+/// Milliseconds since the Unix epoch, or zero for times before it.
+fn timestamp_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_millis() as u64)
+}
+
+impl From<Opportunity> for OpportunityMessage {
+    fn from(opportunity: Opportunity) -> Self {
+        let crossing = opportunity.crossing;
+        Self {
+            id: opportunity.id,
+            symbol: opportunity.symbol,
+            buy_exchange: opportunity.buy_exchange.to_string(),
+            sell_exchange: opportunity.sell_exchange.to_string(),
+            buy_price: crossing.buy_price,
+            sell_price: crossing.sell_price,
+            size: crossing.size,
+            gross_profit: crossing.gross_profit,
+            fees: crossing.fees,
+            net_profit: crossing.net_profit(),
+            started_at_ms: timestamp_ms(opportunity.started_at),
+        }
+    }
+}
+
+impl From<OpportunityEvent> for OpportunityEventMessage {
+    fn from(event: OpportunityEvent) -> Self {
+        let event = match event {
+            OpportunityEvent::Started(opportunity) => Event::Started(opportunity.into()),
+            OpportunityEvent::Ended {
+                opportunity,
+                peak_size,
+                peak_net_profit,
+                ended_at,
+                duration,
+            } => Event::Ended(OpportunityEnded {
+                opportunity: Some(opportunity.into()),
+                ended_at_ms: timestamp_ms(ended_at),
+                duration_ms: duration.as_millis() as u64,
+                peak_size,
+                peak_net_profit,
+            }),
+        };
+        Self { event: Some(event) }
+    }
+}
+
+impl From<Side> for rpc::Side {
+    fn from(side: Side) -> Self {
+        match side {
+            Side::Buy => rpc::Side::Buy,
+            Side::Sell => rpc::Side::Sell,
+        }
+    }
+}
+
+impl From<Allocation> for AllocationMessage {
+    fn from(allocation: Allocation) -> Self {
+        Self {
+            exchange: allocation.exchange.to_string(),
+            quantity: allocation.quantity,
+            notional: allocation.notional,
+        }
+    }
+}
+
+impl From<Arc<Trade>> for TradeMessage {
+    fn from(trade: Arc<Trade>) -> Self {
+        Self {
+            symbol: trade.symbol.clone(),
+            exchange: trade.exchange.to_string(),
+            id: trade.id.clone(),
+            price: trade.price,
+            amount: trade.amount,
+            aggressor: match trade.aggressor {
+                Aggressor::Buy => rpc::Side::Buy,
+                Aggressor::Sell => rpc::Side::Sell,
+            } as i32,
+            traded_at_ms: timestamp_ms(trade.traded_at),
+            received_at_ms: timestamp_ms(trade.received_at),
+        }
+    }
+}
+
+impl From<Arc<BookMetrics>> for BookMetricsUpdate {
+    fn from(metrics: Arc<BookMetrics>) -> Self {
+        Self {
+            symbol: metrics.symbol.clone(),
+            sequence: metrics.sequence,
+            timestamp_ms: timestamp_ms(metrics.timestamp),
+            imbalances: metrics
+                .imbalances
+                .iter()
+                .map(|imbalance| ImbalanceMessage {
+                    levels: imbalance.levels as u32,
+                    imbalance: imbalance.imbalance,
+                })
+                .collect(),
+            depth_bands: metrics
+                .depth_bands
+                .iter()
+                .map(|band| DepthBandMessage {
+                    bps: band.bps,
+                    bid_volume: band.bid_volume,
+                    ask_volume: band.ask_volume,
+                })
+                .collect(),
+            weighted_mid: metrics.weighted_mid,
+            bid_slope: metrics.bid_slope,
+            ask_slope: metrics.ask_slope,
+            venue_shares: metrics
+                .venue_shares
+                .iter()
+                .map(|share| VenueShareMessage {
+                    exchange: share.exchange.to_string(),
+                    bid_share: share.bid_share,
+                    ask_share: share.ask_share,
+                })
+                .collect(),
+        }
+    }
+}
+
+impl From<&Alert> for AlertEvent {
+    fn from(alert: &Alert) -> Self {
+        Self {
+            rule: alert.rule.clone(),
+            symbol: alert.symbol.clone(),
+            exchange: alert.exchange.map(|exchange| exchange.to_string()).unwrap_or_default(),
+            state: match alert.state {
+                AlertState::Firing => rpc::AlertState::Firing,
+                AlertState::Resolved => rpc::AlertState::Resolved,
+            } as i32,
+            value: alert.value,
+            since_ms: timestamp_ms(alert.since),
+            at_ms: timestamp_ms(alert.at),
+        }
+    }
+}
+
+fn execution_estimate(book: &MergedBook, estimate: Estimate) -> ExecutionEstimate {
+    ExecutionEstimate {
+        symbol: book.symbol.clone(),
+        sequence: book.sequence,
+        side: rpc::Side::from(estimate.side) as i32,
+        quantity: estimate.quantity,
+        notional: estimate.notional,
+        average_price: estimate.average_price,
+        worst_price: estimate.worst_price,
+        mid_price: estimate.mid_price,
+        slippage_bps: estimate.slippage_bps,
+        complete: estimate.is_complete(),
+        unfilled: estimate.unfilled,
+        allocations: estimate.allocations.into_iter().map(AllocationMessage::from).collect(),
+    }
+}
+
+/// At most `depth` of the best levels on each side of `book`, with their statistics.
+fn snapshot(book: &MergedBook, depth: usize) -> BookSnapshot {
+    let (bids, asks) = book.truncated(depth);
+    BookSnapshot {
+        symbol: book.symbol.clone(),
+        sequence: book.sequence,
+        timestamp_ms: timestamp_ms(book.timestamp),
+        bids: bids
+            .iter()
+            .map(|bid| Level {
+                delayed: book.delayed.contains(&bid.exchange),
+                ..Level::from(bid)
+            })
+            .collect(),
+        asks: asks
+            .iter()
+            .map(|ask| Level {
+                delayed: book.delayed.contains(&ask.exchange),
+                ..Level::from(ask)
+            })
+            .collect(),
+        statistics: Some(BookStatistics::new(bids, asks).into()),
+    }
+}
+
+/// The levels of `current` that are new or resized since `previous`, followed by those that are no longer
+/// shown with an amount of zero.
+fn level_changes(previous: &[Level], current: &[Level]) -> Vec<Level> {
+    let same_level = |a: &Level, b: &Level| a.exchange == b.exchange && a.price == b.price;
+    let mut changes: Vec<Level> = current
+        .iter()
+        .filter(|level| !previous.contains(level))
+        .cloned()
+        .collect();
+    changes.extend(
+        previous
+            .iter()
+            .filter(|level| !current.iter().any(|shown| same_level(level, shown)))
+            .map(|level| Level {
+                amount: 0.0,
+                ..level.clone()
+            }),
+    );
+    changes
+}
+
+/// The delta that turns `previous` into `current`.
+fn delta(previous: &BookSnapshot, current: &BookSnapshot) -> BookDelta {
+    BookDelta {
+        symbol: current.symbol.clone(),
+        sequence: current.sequence,
+        previous_sequence: previous.sequence,
+        timestamp_ms: current.timestamp_ms,
+        bids: level_changes(&previous.bids, &current.bids),
+        asks: level_changes(&previous.asks, &current.asks),
+        statistics: current.statistics.clone(),
+    }
+}
+
+#[tonic::async_trait]
+impl OrderbookServiceRpc for OrderbookService {
+    type SubscribeBookStream = ResponseStream<BookUpdate>;
+    type StreamVenueStatusStream = QueueReceiver<Result<VenueStatusUpdate, Status>>;
+    type OpportunitiesStream = QueueReceiver<Result<OpportunityEventMessage, Status>>;
+    type BookMetricsStream = QueueReceiver<Result<BookMetricsUpdate, Status>>;
+    type TradesStream = QueueReceiver<Result<TradeMessage, Status>>;
+    type AlertsStream = QueueReceiver<Result<AlertEvent, Status>>;
+    type HistoryStream = ResponseStream<BookSnapshot>;
+
+    async fn subscribe_book(
+        &self,
+        request: Request<SubscribeBookRequest>,
+    ) -> Result<Response<Self::SubscribeBookStream>, Status> {
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let message = request.get_ref();
+        let symbol = self.symbol(&message.symbol)?;
+        let depth = self.depth(&symbol, message.depth);
+        let deltas = message.deltas;
+        let ranking = self.ranking(message.ordering);
+        let permit = authorize(&request, Some(&symbol))?;
+        let (capacity, policy) = self.queue_settings("subscribe_book", &request, message.queue.as_ref());
+        let subscription = self.hub.subscribe(&symbol, depth, capacity, policy).await?;
+        let registration = self.subscriptions.register(
+            "subscribe_book",
+            peer(&request),
+            subscription.metrics(),
+        );
+        // Deltas are taken against the last update sent, so updates dropped from the queue do not break them
+        let mut previous: Option<BookSnapshot> = None;
+        let updates = book_stream(&self.hub, subscription, registration, permit, move |book, depth| {
+            let current = match &ranking {
+                Some(fees) => snapshot(&book.fee_adjusted(fees), depth),
+                None => snapshot(book, depth),
+            };
+            let update = match &previous {
+                Some(previous) if deltas => Update::Delta(delta(previous, &current)),
+                _ => Update::Snapshot(current.clone()),
+            };
+            if deltas {
+                previous = Some(current);
+            }
+            BookUpdate {
+                update: Some(update),
+            }
+        });
+
+        Ok(Response::new(updates))
+    }
+
+    async fn get_book_snapshot(
+        &self,
+        request: Request<GetBookSnapshotRequest>,
+    ) -> Result<Response<BookSnapshot>, Status> {
+        let symbol = self.symbol(&request.get_ref().symbol)?;
+        authorize_symbol(principal(&request), &symbol)?;
+        let book = self.hub.current(&symbol).await?;
+        let depth = self.depth(&symbol, request.get_ref().depth);
+        Ok(Response::new(match self.ranking(request.get_ref().ordering) {
+            Some(fees) => snapshot(&book.fee_adjusted(&fees), depth),
+            None => snapshot(&book, depth),
+        }))
+    }
+
+    async fn stream_venue_status(
+        &self,
+        request: Request<StreamVenueStatusRequest>,
+    ) -> Result<Response<Self::StreamVenueStatusStream>, Status> {
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let permit = authorize(&request, None)?;
+        let options = request.get_ref().queue.as_ref();
+        let (capacity, policy) = self.queue_settings("stream_venue_status", &request, options);
+        let (tx, rx) = queue::bounded::<Result<VenueStatusUpdate, Status>>(capacity, policy);
+        let registration = self.subscriptions.register(
+            "stream_venue_status",
+            peer(&request),
+            tx.metrics(),
+        );
+        let hub = self.hub.clone();
+        venue_stream(&self.hub, tx, registration, permit, move |venues| venue_status(&hub, venues));
+
+        Ok(Response::new(rx))
+    }
+
+    async fn list_subscriptions(
+        &self,
+        _request: Request<ListSubscriptionsRequest>,
+    ) -> Result<Response<ListSubscriptionsResponse>, Status> {
+        Ok(Response::new(ListSubscriptionsResponse {
+            subscriptions: self
+                .subscriptions
+                .snapshot()
+                .into_iter()
+                .map(SubscriptionStatus::from)
+                .collect(),
+        }))
+    }
+
+    async fn opportunities(
+        &self,
+        request: Request<OpportunitiesRequest>,
+    ) -> Result<Response<Self::OpportunitiesStream>, Status> {
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let symbol = self.symbol(&request.get_ref().symbol)?;
+        let permit = authorize(&request, Some(&symbol))?;
+        let options = request.get_ref().queue.as_ref();
+        let (capacity, policy) = self.queue_settings("opportunities", &request, options);
+        let outputs = self.arbitrage.subscribe(&symbol).await?;
+        let (tx, rx) = queue::bounded::<Result<OpportunityEventMessage, Status>>(capacity, policy);
+        let registration = self.subscriptions.register("opportunities", peer(&request), tx.metrics());
+        broadcast_stream(&self.hub, outputs, tx, registration, permit, |event| {
+            Some(OpportunityEventMessage::from(event))
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn estimate_execution(
+        &self,
+        request: Request<EstimateExecutionRequest>,
+    ) -> Result<Response<ExecutionEstimate>, Status> {
+        let message = request.get_ref();
+        let symbol = self.symbol(&message.symbol)?;
+        authorize_symbol(principal(&request), &symbol)?;
+        let side = match rpc::Side::from_i32(message.side) {
+            Some(rpc::Side::Buy) => Side::Buy,
+            Some(rpc::Side::Sell) => Side::Sell,
+            _ => return Err(Status::invalid_argument("a side must be given")),
+        };
+        let size = match message.size {
+            Some(SizeMessage::Quantity(quantity)) => Size::Quantity(quantity),
+            Some(SizeMessage::Notional(notional)) => Size::Notional(notional),
+            None => return Err(Status::invalid_argument("a quantity or notional must be given")),
+        };
+        let book = self.hub.current(&symbol).await?;
+        let estimate = execution::estimate(&book, side, size)?;
+        Ok(Response::new(execution_estimate(&book, estimate)))
+    }
+
+    async fn book_metrics(
+        &self,
+        request: Request<BookMetricsRequest>,
+    ) -> Result<Response<Self::BookMetricsStream>, Status> {
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let symbol = self.symbol(&request.get_ref().symbol)?;
+        let permit = authorize(&request, Some(&symbol))?;
+        let options = request.get_ref().queue.as_ref();
+        let (capacity, policy) = self.queue_settings("book_metrics", &request, options);
+        let outputs = self.metrics.subscribe(&symbol).await?;
+        let (tx, rx) = queue::bounded::<Result<BookMetricsUpdate, Status>>(capacity, policy);
+        let registration = self.subscriptions.register("book_metrics", peer(&request), tx.metrics());
+        broadcast_stream(&self.hub, outputs, tx, registration, permit, |metrics| {
+            Some(BookMetricsUpdate::from(metrics))
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn trades(
+        &self,
+        request: Request<TradesRequest>,
+    ) -> Result<Response<Self::TradesStream>, Status> {
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let symbol = self.symbol(&request.get_ref().symbol)?;
+        let permit = authorize(&request, Some(&symbol))?;
+        let options = request.get_ref().queue.as_ref();
+        let (capacity, policy) = self.queue_settings("trades", &request, options);
+        let trades = self.trades.subscribe(&symbol)?;
+        let (tx, rx) = queue::bounded::<Result<TradeMessage, Status>>(capacity, policy);
+        let registration = self.subscriptions.register("trades", peer(&request), tx.metrics());
+        broadcast_stream(&self.hub, trades, tx, registration, permit, |trade| {
+            Some(TradeMessage::from(trade))
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn get_venue_leadership(
+        &self,
+        request: Request<GetVenueLeadershipRequest>,
+    ) -> Result<Response<VenueLeadershipReport>, Status> {
+        let symbol = self.symbol(&request.get_ref().symbol)?;
+        authorize_symbol(principal(&request), &symbol)?;
+        // Leadership is only tracked while the book is live, so this keeps it connected as a snapshot would
+        self.hub.current(&symbol).await?;
+        let windows = self.hub.leadership(&symbol).unwrap_or_default();
+        Ok(Response::new(VenueLeadershipReport {
+            symbol,
+            timestamp_ms: timestamp_ms(SystemTime::now()),
+            windows: windows.iter().map(LeadershipWindowMessage::from).collect(),
+        }))
+    }
+
+    async fn alerts(
+        &self,
+        request: Request<AlertsRequest>,
+    ) -> Result<Response<Self::AlertsStream>, Status> {
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let symbol = self.symbol(&request.get_ref().symbol)?;
+        let permit = authorize(&request, Some(&symbol))?;
+        let options = request.get_ref().queue.as_ref();
+        let (capacity, policy) = self.queue_settings("alerts", &request, options);
+        let alerts = self.alerts.subscribe();
+        let (tx, rx) = queue::bounded::<Result<AlertEvent, Status>>(capacity, policy);
+        let registration = self.subscriptions.register("alerts", peer(&request), tx.metrics());
+        broadcast_stream(&self.hub, alerts, tx, registration, permit, move |alert| {
+            (alert.symbol == symbol).then(|| AlertEvent::from(alert.as_ref()))
+        });
+
+        Ok(Response::new(rx))
+    }
+
+    async fn get_book_at(
+        &self,
+        request: Request<GetBookAtRequest>,
+    ) -> Result<Response<BookSnapshot>, Status> {
+        let symbol = self.symbol(&request.get_ref().symbol)?;
+        authorize_symbol(principal(&request), &symbol)?;
+        if !self.hub.config().history.is_enabled() {
+            return Err(Status::failed_precondition(HISTORY_DISABLED_MESSAGE));
+        }
+        let at_ms = request.get_ref().timestamp_ms;
+        let book = self
+            .hub
+            .book_at(&symbol, UNIX_EPOCH + Duration::from_millis(at_ms))
+            .ok_or_else(|| Status::not_found(format!("no {symbol} book is retained from {at_ms}")))?;
+        let depth = self.depth(&symbol, request.get_ref().depth);
+        Ok(Response::new(match self.ranking(request.get_ref().ordering) {
+            Some(fees) => snapshot(&book.fee_adjusted(&fees), depth),
+            None => snapshot(&book, depth),
+        }))
+    }
+
+    async fn history(
+        &self,
+        request: Request<HistoryRequest>,
+    ) -> Result<Response<Self::HistoryStream>, Status> {
+        if self.hub.is_shutting_down() {
+            return Err(Status::unavailable(SHUTDOWN_MESSAGE));
+        }
+        let symbol = self.symbol(&request.get_ref().symbol)?;
+        let permit = authorize(&request, Some(&symbol))?;
+        if !self.hub.config().history.is_enabled() {
+            return Err(Status::failed_precondition(HISTORY_DISABLED_MESSAGE));
+        }
+        let from = UNIX_EPOCH + Duration::from_millis(request.get_ref().from_ms);
+        let to = match request.get_ref().to_ms {
+            0 => SystemTime::now(),
+            to_ms => UNIX_EPOCH + Duration::from_millis(to_ms),
+        };
+        if from > to {
+            return Err(Status::invalid_argument("from_ms must not be after to_ms"));
+        }
+        let books = self.hub.history(&symbol, from, to);
+        let depth = self.depth(&symbol, request.get_ref().depth);
+        let fees = self.ranking(request.get_ref().ordering);
+        Ok(Response::new(Box::pin(stream! {
+            let _permit = permit;
+            for book in books {
+                yield Ok(match &fees {
+                    Some(fees) => snapshot(&book.fee_adjusted(fees), depth),
+                    None => snapshot(&book, depth),
+                });
+            }
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rpc::Level;
+    use super::level_changes;
+
+    fn level(exchange: &str, price: f64, amount: f64) -> Level {
+        Level {
+            exchange: exchange.to_string(),
+            price,
+            amount,
+            delayed: false,
+        }
+    }
+
+    #[test]
+    fn test_level_changes() {
+        let previous = [level("Binance", 100.0, 1.0), level("Bitstamp", 99.0, 2.0)];
+        let current = [
+            level("Binance", 100.0, 1.5),
+            level("Bitstamp", 99.0, 2.0),
+            level("Binance", 98.0, 3.0),
+        ];
+        assert_eq!(
+            level_changes(&previous, &current),
+            vec![level("Binance", 100.0, 1.5), level("Binance", 98.0, 3.0)]
+        );
+        assert_eq!(
+            level_changes(&current, &previous[1..]),
+            vec![level("Binance", 100.0, 0.0), level("Binance", 98.0, 0.0)]
+        );
+    }
+}
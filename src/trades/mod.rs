@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use async_stream::stream;
+use futures::StreamExt;
+use tokio::sync::broadcast;
+use tokio_stream::StreamMap;
+
+use crate::exchanges::{self, status::ReconnectPolicy, ExchangeType, Trade, TradeStream};
+use crate::hub::{BookHub, HubError};
+
+/// How long trades are held so that those of different venues are published in the order they were matched
+const REORDER_WINDOW: Duration = Duration::from_millis(250);
+/// How often held trades are checked for release
+const RELEASE_INTERVAL: Duration = Duration::from_millis(50);
+/// Trades buffered for each subscriber to a tape before it starts missing them
+const TAPE_CHANNEL_CAPACITY: usize = 1024;
+
+type Channels = Arc<Mutex<HashMap<String, Arc<broadcast::Sender<Arc<Trade>>>>>>;
+
+/// The trades of every venue a symbol is sourced from, merged into a single time-ordered tape.
+///
+/// A symbol's trade feeds are connected with its first subscriber and closed once the tape publishes to none.
+#[derive(Clone)]
+pub struct TradeTape {
+    hub: BookHub,
+    channels: Channels,
+}
+
+impl TradeTape {
+    /// Sources trades from the exchanges and symbols configured for the hub.
+    pub fn new(hub: BookHub) -> Self {
+        Self {
+            hub,
+            channels: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Receives the merged trades of `symbol`, connecting its trade feeds if nobody else is subscribed.
+    pub fn subscribe(&self, symbol: &str) -> Result<broadcast::Receiver<Arc<Trade>>, HubError> {
+        if self.hub.is_shutting_down() {
+            return Err(HubError::ShuttingDown);
+        }
+        let symbol = self.hub.resolve_symbol(symbol)?;
+        let mut channels = self.channels.lock().unwrap();
+        if let Some(tx) = channels.get(&symbol) {
+            return Ok(tx.subscribe());
+        }
+        let (tx, rx) = broadcast::channel(TAPE_CHANNEL_CAPACITY);
+        let tx = Arc::new(tx);
+        channels.insert(symbol.clone(), tx.clone());
+
+        let config = self.hub.config();
+        let mut venues = StreamMap::new();
+        for &exchange in config.exchanges_of(&symbol) {
+            venues.insert(exchange, venue_trades(exchange, symbol.clone(), config.reconnect));
+        }
+        tokio::spawn(publish(self.hub.clone(), self.channels.clone(), symbol, venues, tx));
+        Ok(rx)
+    }
+}
+
+/// The trades of a single venue, reconnecting whenever its websocket closes until the reconnect policy gives up.
+fn venue_trades(exchange: ExchangeType, symbol: String, policy: ReconnectPolicy) -> TradeStream {
+    Box::pin(stream! {
+        let adapter = match exchanges::exchange(exchange) {
+            Some(adapter) => adapter,
+            None => return,
+        };
+        let mut backoff = policy.initial_backoff;
+        let mut failures = 0;
+        loop {
+            // The connection error is not `Send`, so it may not be held across the awaits below
+            let connected = adapter.connect_trades(symbol.clone()).await.map_err(|e| e.to_string());
+            match connected {
+                Ok(mut trades) => {
+                    info!("{} {symbol} trades connected", exchange.to_string());
+                    while let Some(trade) = trades.next().await {
+                        // A delivered trade shows the connection is healthy again
+                        if trade.is_ok() {
+                            failures = 0;
+                            backoff = policy.initial_backoff;
+                        }
+                        yield trade;
+                    }
+                }
+                Err(e) => warn!("{} {symbol} trades could not connect: {e}", exchange.to_string()),
+            }
+            failures += 1;
+            if failures > policy.max_attempts {
+                error!("{} {symbol} trades exceeded the maximum number of reconnect attempts", exchange.to_string());
+                break;
+            }
+            tokio::time::sleep(backoff).await;
+            backoff = (backoff * 2).min(policy.max_backoff);
+        }
+    })
+}
+
+/// Takes the trades held for at least `REORDER_WINDOW` as of `now`, ordered by when they were matched.
+fn release(held: &mut Vec<Trade>, now: SystemTime) -> Vec<Trade> {
+    let (mut ready, waiting): (Vec<Trade>, Vec<Trade>) = held.drain(..).partition(|trade| {
+        now.duration_since(trade.received_at)
+            .is_ok_and(|held_for| held_for >= REORDER_WINDOW)
+    });
+    *held = waiting;
+    ready.sort_by_key(|trade| trade.traded_at);
+    ready
+}
+
+/// Merges the trades of every venue of a symbol and publishes them until nobody is subscribed.
+async fn publish(
+    hub: BookHub,
+    channels: Channels,
+    symbol: String,
+    mut venues: StreamMap<ExchangeType, TradeStream>,
+    tx: Arc<broadcast::Sender<Arc<Trade>>>,
+) {
+    let mut held: Vec<Trade> = Vec::new();
+    let mut tick = tokio::time::interval(RELEASE_INTERVAL);
+    loop {
+        tokio::select! {
+            _ = hub.shutting_down() => break,
+            _ = tick.tick() => {
+                let mut channels = channels.lock().unwrap();
+                for trade in release(&mut held, SystemTime::now()) {
+                    // Fails only when nobody is subscribed, which is checked below
+                    let _ = tx.send(Arc::new(trade));
+                }
+                // Checked under the lock, so a new subscriber either joins this tape or starts another
+                if tx.receiver_count() == 0 {
+                    channels.remove(&symbol);
+                    break;
+                }
+            }
+            trade = venues.next() => match trade {
+                Some((_, Ok(trade))) => held.push(trade),
+                Some((exchange, Err(e))) => warn!("{} {symbol} trade could not be read: {e}", exchange.to_string()),
+                // Every venue has given up
+                None => break,
+            },
+        }
+    }
+    debug!("{symbol} trade tape closed");
+    // Dropping the sender ends every subscriber's stream, and the venue streams close their websockets.
+    // The tape may have stopped for another reason, in which case it is still registered
+    let mut channels = channels.lock().unwrap();
+    if channels.get(&symbol).is_some_and(|current| Arc::ptr_eq(current, &tx)) {
+        channels.remove(&symbol);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crate::exchanges::{Aggressor, ExchangeType, Trade};
+
+    use super::{release, REORDER_WINDOW};
+
+    fn trade(exchange: ExchangeType, traded_ms: u64, received_ms: u64) -> Trade {
+        Trade {
+            exchange,
+            symbol: String::from("ethbtc"),
+            id: traded_ms.to_string(),
+            price: 1.0,
+            amount: 1.0,
+            aggressor: Aggressor::Buy,
+            traded_at: UNIX_EPOCH + Duration::from_millis(traded_ms),
+            received_at: UNIX_EPOCH + Duration::from_millis(received_ms),
+        }
+    }
+
+    #[test]
+    fn test_release() {
+        let mut held = vec![
+            trade(ExchangeType::Bitstamp, 20, 40),
+            trade(ExchangeType::Binance, 10, 50),
+            trade(ExchangeType::Binance, 30, 500),
+        ];
+        let now = UNIX_EPOCH + Duration::from_millis(50) + REORDER_WINDOW;
+        let released = release(&mut held, now);
+        // Binance's trade arrived last but was matched first
+        assert_eq!(released.len(), 2);
+        assert_eq!(released[0].exchange, ExchangeType::Binance);
+        assert_eq!(released[1].exchange, ExchangeType::Bitstamp);
+        assert_eq!(held.len(), 1);
+    }
+}
@@ -0,0 +1,341 @@
+use std::{
+    collections::{HashMap, HashSet},
+    net::SocketAddr,
+    sync::Arc,
+};
+
+use async_tungstenite::{
+    tokio::{accept_hdr_async, TokioAdapter},
+    tungstenite::{
+        handshake::server::{ErrorResponse, Request, Response},
+        http::StatusCode,
+        Message,
+    },
+    WebSocketStream,
+};
+use futures::{SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpListener,
+};
+use tokio_native_tls::TlsAcceptor;
+use tokio_stream::StreamMap;
+
+use crate::hub::{BookEvent, BookHub, BookSubscription};
+use crate::json::JsonBook;
+use crate::queue::OverflowPolicy;
+use crate::server::auth::{authorize_symbol, Authenticator, Principal, StreamPermit};
+
+/// Handshake header carrying an API key
+const API_KEY_HEADER: &str = "x-api-key";
+/// Query parameter carrying an API key, for browsers which cannot set handshake headers
+const API_KEY_PARAM: &str = "api_key";
+
+/// A message sent by a websocket client.
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ClientMessage {
+    /// Streams the merged book of `symbol`, or the default symbol, with at most `depth` levels a side
+    Subscribe {
+        symbol: Option<String>,
+        depth: Option<usize>,
+    },
+    Unsubscribe {
+        symbol: String,
+    },
+}
+
+/// A message sent to a websocket client.
+#[derive(Serialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum ServerMessage {
+    /// The first book of a subscription
+    Snapshot(JsonBook),
+    /// Every following book of a subscription
+    Update(JsonBook),
+    Error {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        symbol: Option<String>,
+        message: String,
+    },
+}
+
+impl ServerMessage {
+    fn error<S: Into<String>>(symbol: Option<&str>, message: S) -> Self {
+        Self::Error {
+            symbol: symbol.map(str::to_string),
+            message: message.into(),
+        }
+    }
+}
+
+/// Streams the merged books of a `BookHub` as JSON over websockets.
+///
+/// Clients send `{"type": "subscribe", "symbol": "ethbtc", "depth": 10}` and receive a `snapshot` of the
+/// book followed by an `update` for every change, sharing the exchange feeds of the gRPC service.
+#[derive(Clone)]
+pub struct WebsocketServer {
+    hub: BookHub,
+    authenticator: Authenticator,
+    queue_capacity: usize,
+    overflow_policy: OverflowPolicy,
+    tls: Option<TlsAcceptor>,
+}
+
+impl WebsocketServer {
+    pub fn new(hub: BookHub, authenticator: Authenticator) -> Self {
+        Self {
+            hub,
+            authenticator,
+            queue_capacity: crate::server::DEFAULT_QUEUE_CAPACITY,
+            overflow_policy: OverflowPolicy::default(),
+            tls: None,
+        }
+    }
+
+    /// Secures every connection with TLS before its handshake.
+    pub fn with_tls(mut self, tls: TlsAcceptor) -> Self {
+        self.tls = Some(tls);
+        self
+    }
+
+    /// Sets the queue given to each subscription.
+    pub fn with_client_queue(mut self, capacity: usize, policy: OverflowPolicy) -> Self {
+        self.queue_capacity = capacity;
+        self.overflow_policy = policy;
+        self
+    }
+
+    /// Accepts connections on `addr` until the hub is shut down.
+    pub async fn serve(self, addr: SocketAddr) -> Result<(), std::io::Error> {
+        let listener = TcpListener::bind(addr).await?;
+        match self.tls {
+            Some(_) => info!("websocket server listening on {addr} over TLS"),
+            None => info!("websocket server listening on {addr}"),
+        }
+        loop {
+            let (stream, peer) = tokio::select! {
+                _ = self.hub.shutting_down() => return Ok(()),
+                accepted = listener.accept() => accepted?,
+            };
+            let server = self.clone();
+            tokio::spawn(async move {
+                let result = match &server.tls {
+                    Some(tls) => match tls.accept(stream).await {
+                        Ok(stream) => server.connection(stream, peer).await,
+                        Err(e) => {
+                            debug!("TLS handshake with websocket client {peer} failed: {e}");
+                            return;
+                        }
+                    },
+                    None => server.connection(stream, peer).await,
+                };
+                if let Err(e) = result {
+                    debug!("websocket connection from {peer} ended: {e}");
+                }
+            });
+        }
+    }
+
+    /// Authenticates a connection during the handshake and then serves its subscriptions.
+    async fn connection<S: AsyncRead + AsyncWrite + Unpin>(
+        &self,
+        stream: S,
+        peer: SocketAddr,
+    ) -> Result<(), async_tungstenite::tungstenite::Error> {
+        let mut principal = None;
+        // The rejection type is dictated by tungstenite
+        #[allow(clippy::result_large_err)]
+        let callback = |request: &Request, response: Response| {
+            match self.authenticator.authenticate(presented_key(request).as_deref()) {
+                Ok(authenticated) => {
+                    principal = authenticated;
+                    Ok(response)
+                }
+                Err(e) => {
+                    warn!("rejected websocket connection from {peer}: {e}");
+                    let mut rejection = ErrorResponse::new(Some(e.to_string()));
+                    *rejection.status_mut() = StatusCode::UNAUTHORIZED;
+                    Err(rejection)
+                }
+            }
+        };
+        let websocket = accept_hdr_async(stream, callback).await?;
+        info!("websocket client {peer} connected");
+        Connection {
+            server: self,
+            principal,
+            websocket,
+            subscriptions: StreamMap::new(),
+            permits: HashMap::new(),
+            snapshotted: HashSet::new(),
+        }
+        .run()
+        .await
+    }
+}
+
+/// The API key presented during a handshake, as a header or query parameter.
+fn presented_key(request: &Request) -> Option<String> {
+    if let Some(key) = request.headers().get(API_KEY_HEADER) {
+        return key.to_str().ok().map(str::to_string);
+    }
+    request.uri().query()?.split('&').find_map(|pair| {
+        let (name, value) = pair.split_once('=')?;
+        (name == API_KEY_PARAM).then(|| value.to_string())
+    })
+}
+
+/// A single websocket client and the books it has subscribed to.
+struct Connection<'a, S> {
+    server: &'a WebsocketServer,
+    principal: Option<Arc<Principal>>,
+    websocket: WebSocketStream<TokioAdapter<S>>,
+    subscriptions: StreamMap<String, BookSubscription>,
+    /// Streams reserved against the client's API key, one per subscription
+    permits: HashMap<String, StreamPermit>,
+    /// Subscriptions which have been sent their snapshot
+    snapshotted: HashSet<String>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Connection<'_, S> {
+    async fn run(mut self) -> Result<(), async_tungstenite::tungstenite::Error> {
+        let mut shutdown = self.server.hub.shutdown_signal();
+        loop {
+            tokio::select! {
+                _ = shutdown.changed() => {
+                    self.send(&ServerMessage::error(None, "server is shutting down")).await?;
+                    break;
+                }
+                message = self.websocket.next() => match message {
+                    Some(Ok(Message::Text(text))) => self.handle(&text).await?,
+                    Some(Ok(Message::Close(_))) | None => break,
+                    Some(Ok(_)) => {}
+                    Some(Err(e)) => return Err(e),
+                },
+                Some((symbol, event)) = self.subscriptions.next() => match event {
+                    BookEvent::Update(book) => {
+                        let depth = self
+                            .subscriptions
+                            .iter()
+                            .find(|(subscribed, _)| **subscribed == symbol)
+                            .map_or(0, |(_, subscription)| subscription.depth());
+                        let book = JsonBook::new(&book, depth);
+                        let message = if self.snapshotted.insert(symbol) {
+                            ServerMessage::Snapshot(book)
+                        } else {
+                            ServerMessage::Update(book)
+                        };
+                        self.send(&message).await?;
+                    }
+                    // Exchange errors are reported by VenueStatus, the book carries on without them
+                    BookEvent::FeedError(_) => {}
+                    BookEvent::Overflowed => {
+                        warn!("disconnecting a websocket client that is not keeping up");
+                        let message = "client is not consuming updates quickly enough";
+                        self.send(&ServerMessage::error(Some(&symbol), message)).await?;
+                        break;
+                    }
+                },
+            }
+        }
+        self.websocket.close(None).await
+    }
+
+    async fn handle(&mut self, text: &str) -> Result<(), async_tungstenite::tungstenite::Error> {
+        let message = match serde_json::from_str::<ClientMessage>(text) {
+            Ok(message) => message,
+            Err(e) => return self.send(&ServerMessage::error(None, format!("invalid message: {e}"))).await,
+        };
+        match message {
+            ClientMessage::Subscribe { symbol, depth } => {
+                let requested = symbol.unwrap_or_else(|| self.server.hub.default_symbol().to_string());
+                if let Err(message) = self.subscribe(&requested, depth).await {
+                    return self.send(&ServerMessage::error(Some(&requested), message)).await;
+                }
+            }
+            ClientMessage::Unsubscribe { symbol } => {
+                let symbol = self.server.hub.resolve_symbol(&symbol).unwrap_or(symbol);
+                self.subscriptions.remove(&symbol);
+                self.permits.remove(&symbol);
+                self.snapshotted.remove(&symbol);
+            }
+        }
+        Ok(())
+    }
+
+    /// Subscribes to `symbol`, replacing any existing subscription to it.
+    async fn subscribe(&mut self, symbol: &str, depth: Option<usize>) -> Result<(), String> {
+        let hub = &self.server.hub;
+        let symbol = hub.resolve_symbol(symbol).map_err(|e| e.to_string())?;
+        // A replacement keeps the stream reserved by the subscription it replaces
+        let mut permit = None;
+        authorize_symbol(self.principal.as_deref(), &symbol).map_err(|e| e.to_string())?;
+        if let Some(principal) = &self.principal {
+            if !self.permits.contains_key(&symbol) {
+                permit = Some(principal.open_stream().map_err(|e| e.to_string())?);
+            }
+        }
+        let depth = depth.unwrap_or(hub.config().max_depth_of(&symbol));
+        let subscription = hub
+            .subscribe(&symbol, depth, self.server.queue_capacity, self.server.overflow_policy)
+            .await
+            .map_err(|e| e.to_string())?;
+        // Only swap once everything has succeeded, so a failure leaves any existing subscription in place
+        if let Some(permit) = permit {
+            self.permits.insert(symbol.clone(), permit);
+        }
+        self.snapshotted.remove(&symbol);
+        self.subscriptions.insert(symbol, subscription);
+        Ok(())
+    }
+
+    async fn send(&mut self, message: &ServerMessage) -> Result<(), async_tungstenite::tungstenite::Error> {
+        // Serialising these types cannot fail
+        let text = serde_json::to_string(message).unwrap_or_default();
+        self.websocket.send(Message::Text(text)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{exchanges::ExchangeType, hub::BookFixture};
+
+    use crate::json::JsonBook;
+
+    use super::{ClientMessage, ServerMessage};
+
+    #[test]
+    fn test_client_messages() {
+        let message: ClientMessage =
+            serde_json::from_str(r#"{"type": "subscribe", "symbol": "ethbtc", "depth": 5}"#).unwrap();
+        assert!(matches!(
+            message,
+            ClientMessage::Subscribe { symbol: Some(symbol), depth: Some(5) } if symbol == "ethbtc"
+        ));
+        let message: ClientMessage = serde_json::from_str(r#"{"type": "subscribe"}"#).unwrap();
+        assert!(matches!(message, ClientMessage::Subscribe { symbol: None, depth: None }));
+        assert!(serde_json::from_str::<ClientMessage>(r#"{"type": "unsubscribe"}"#).is_err());
+    }
+
+    #[test]
+    fn test_snapshot_message() {
+        let book = Arc::new(
+            BookFixture::new()
+                .sequence(3)
+                .bid(99.0, 1.0, ExchangeType::Binance)
+                .bid(98.0, 1.0, ExchangeType::Bitstamp)
+                .ask(101.0, 1.0, ExchangeType::Bitstamp)
+                .build(),
+        );
+        let message = serde_json::to_value(ServerMessage::Snapshot(JsonBook::new(&book, 1))).unwrap();
+        assert_eq!(message["type"], "snapshot");
+        assert_eq!(message["sequence"], 3);
+        assert_eq!(message["spread"], 2.0);
+        assert_eq!(message["bids"].as_array().unwrap().len(), 1);
+        assert_eq!(message["bids"][0]["exchange"], "Binance");
+    }
+}